@@ -0,0 +1,732 @@
+//! Text assembly for celsium bytecode.
+//!
+//! One optcode per line, as `print_program` writes it:
+//!
+//! ```text
+//! ; comments start with a semicolon
+//!     LoadInt 10
+//!     CallFunction fakt
+//!     PushToTestingStack
+//!
+//! .function fakt(n: Int #10) -> Int
+//!     LoadVar n #10
+//!     LoadInt 1
+//!     LessOrEq
+//!     JumpIfFalse recurse
+//!     LoadInt 1
+//!     ReturnValue
+//! recurse:
+//!     LoadVar n #10
+//!     ...
+//! .end
+//! ```
+//!
+//! Jumps take a label (`name:` on its own line) or a raw step count (`+5`, `-3`).
+//! Labels are local to the block they are defined in, so loops are written with jumps
+//! and without `Break` or `Continue`. Calls are written by name and linked when the program is built.
+
+use std::{ collections::HashMap, fmt };
+
+mod printer;
+pub use printer::{ format_optcode, print_bytecode, print_program };
+
+use crate::{
+    block::Block,
+    bytecode::{ PathStep, OPTCODE },
+    module::{ FuncArg, Function, FunctionSignature },
+    vm::decimal::parse_decimal,
+    BuiltinTypes,
+    CelsiumProgram,
+    Scope,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssembleErrorKind {
+    UnknownOptcode {
+        name: String,
+    },
+    MissingOperand {
+        optcode: String,
+    },
+    InvalidOperand {
+        optcode: String,
+        operand: String,
+    },
+    UnexpectedOperand {
+        optcode: String,
+        operand: String,
+    },
+    UnknownLabel {
+        label: String,
+    },
+    DuplicateLabel {
+        label: String,
+    },
+    /// A forward jump points backwards or the other way around
+    WrongJumpDirection {
+        optcode: String,
+        label: String,
+    },
+    UnterminatedString,
+    UnknownType {
+        name: String,
+    },
+    InvalidFunctionHeader,
+    UnclosedBlock,
+    UnexpectedBlockEnd,
+    /// `.function` is only allowed at the top level of `assemble_program`
+    UnexpectedFunction,
+    /// Linked optcodes like `JumpToFunction` are produced by `CelsiumProgram::new`
+    LinkedOptcode {
+        optcode: String,
+    },
+    /// `Break` or `Continue`, which only the loop builders of `Block` can lower to jumps
+    LoopControl {
+        optcode: String,
+    },
+    /// `CallFunction` to a name without a `.function` in the program
+    UnknownFunction {
+        name: String,
+    },
+    DuplicateFunction {
+        name: String,
+    },
+}
+
+/// An error in assembly source. `line` starts from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssembleError {
+    pub kind: AssembleErrorKind,
+    pub line: usize,
+}
+
+impl fmt::Display for AssembleErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssembleErrorKind::UnknownOptcode { name } => write!(f, "Unknown optcode \"{}\"", name),
+            AssembleErrorKind::MissingOperand { optcode } =>
+                write!(f, "{} is missing an operand", optcode),
+            AssembleErrorKind::InvalidOperand { optcode, operand } =>
+                write!(f, "\"{}\" is not a valid operand for {}", operand, optcode),
+            AssembleErrorKind::UnexpectedOperand { optcode, operand } =>
+                write!(f, "Unexpected operand \"{}\" for {}", operand, optcode),
+            AssembleErrorKind::UnknownLabel { label } => write!(f, "Unknown label \"{}\"", label),
+            AssembleErrorKind::DuplicateLabel { label } =>
+                write!(f, "Label \"{}\" is defined more than once", label),
+            AssembleErrorKind::WrongJumpDirection { optcode, label } =>
+                write!(f, "{} can not jump to label \"{}\" in that direction", optcode, label),
+            AssembleErrorKind::UnterminatedString => write!(f, "Unterminated string literal"),
+            AssembleErrorKind::UnknownType { name } => write!(f, "Unknown type \"{}\"", name),
+            AssembleErrorKind::InvalidFunctionHeader =>
+                write!(f, "Expected `.function name(arg: Type #id, ...) -> Type`"),
+            AssembleErrorKind::UnclosedBlock => write!(f, "Block is not closed"),
+            AssembleErrorKind::UnexpectedBlockEnd => write!(f, "Nothing to close here"),
+            AssembleErrorKind::UnexpectedFunction =>
+                write!(f, "Functions can only be defined at the top level of a program"),
+            AssembleErrorKind::LinkedOptcode { optcode } =>
+                write!(f, "{} is created by linking, write the call by name instead", optcode),
+            AssembleErrorKind::LoopControl { optcode } =>
+                write!(f, "{} is not supported in assembly, jump out of the loop instead", optcode),
+            AssembleErrorKind::UnknownFunction { name } =>
+                write!(f, "Unknown function \"{}\"", name),
+            AssembleErrorKind::DuplicateFunction { name } =>
+                write!(f, "Function \"{}\" is defined more than once", name),
+        }
+    }
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+/// Assembles a single block of bytecode without functions.
+pub fn assemble(source: &str) -> Result<Vec<OPTCODE>, AssembleError> {
+    let mut parser = Parser::new(source);
+    let bytecode = parser.parse_block(BlockEnd::EndOfSource)?;
+    Ok(bytecode)
+}
+
+/// Assembles the main block and any `.function` sections into a linked program.
+pub fn assemble_program(source: &str) -> Result<CelsiumProgram, AssembleError> {
+    let mut parser = Parser::new(source);
+    // Functions get ids in the order they are defined, so calls can come before the definition
+    for (line, text) in parser.lines.clone() {
+        if text.starts_with(".function") {
+            let signature = parse_function_header(text).map_err(|kind| AssembleError {
+                kind,
+                line,
+            })?;
+            let id = parser.function_ids.len();
+            if parser.function_ids.insert(signature.name.clone(), id).is_some() {
+                return Err(AssembleError {
+                    kind: AssembleErrorKind::DuplicateFunction { name: signature.name },
+                    line,
+                });
+            }
+        }
+    }
+    let mut main_bytecode = vec![];
+    let mut functions = vec![];
+    loop {
+        main_bytecode.extend(parser.parse_block(BlockEnd::Function)?);
+        let Some((line, header)) = parser.next_line() else {
+            break;
+        };
+        let signature = parse_function_header(header).map_err(|kind| AssembleError {
+            kind,
+            line,
+        })?;
+        let body = parser.parse_block(BlockEnd::FunctionEnd)?;
+        functions.push(Function {
+            id: parser.function_ids[&signature.name],
+            signature,
+            body: block_from(body),
+        });
+    }
+    let program = CelsiumProgram::new(
+        block_from(main_bytecode),
+        functions,
+        HashMap::new(),
+        HashMap::new(),
+        HashMap::new()
+    ).expect("Break and Continue are not parsed and every call has a function");
+    Ok(program)
+}
+
+fn block_from(bytecode: Vec<OPTCODE>) -> Block {
+    let mut block = Block::new(Scope { ast_id: 0, module_path: String::new() });
+    block.bytecode = bytecode;
+    block
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum BlockEnd {
+    EndOfSource,
+    /// Main block of a program, stops before `.function`
+    Function,
+    /// `.end`
+    FunctionEnd,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+}
+
+/// An optcode, or a jump to a label that is resolved once the whole block is parsed
+enum Item {
+    Optcode(OPTCODE),
+    Jump {
+        line: usize,
+        optcode: &'static str,
+        label: String,
+    },
+}
+
+struct Parser<'a> {
+    lines: Vec<(usize, &'a str)>,
+    position: usize,
+    /// Ids of the `.function`s by name, empty when assembling a single block
+    function_ids: HashMap<String, usize>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Parser<'a> {
+        let lines = source
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with(';'))
+            .collect();
+        Parser { lines, position: 0, function_ids: HashMap::new() }
+    }
+
+    fn next_line(&mut self) -> Option<(usize, &'a str)> {
+        let line = self.lines.get(self.position).copied();
+        self.position += 1;
+        line
+    }
+
+    fn last_line_number(&self) -> usize {
+        self.lines.last().map_or(1, |(line, _)| *line)
+    }
+
+    fn parse_block(&mut self, end: BlockEnd) -> Result<Vec<OPTCODE>, AssembleError> {
+        let mut items = vec![];
+        let mut labels: HashMap<String, usize> = HashMap::new();
+        loop {
+            let Some((line, text)) = self.lines.get(self.position).copied() else {
+                if end == BlockEnd::FunctionEnd {
+                    return Err(AssembleError {
+                        kind: AssembleErrorKind::UnclosedBlock,
+                        line: self.last_line_number(),
+                    });
+                }
+                break;
+            };
+            let error = |kind| AssembleError { kind, line };
+            if text.starts_with(".function") {
+                match end {
+                    BlockEnd::Function => {
+                        break;
+                    }
+                    _ => {
+                        return Err(error(AssembleErrorKind::UnexpectedFunction));
+                    }
+                }
+            }
+            self.position += 1;
+            if text == ".end" {
+                if end != BlockEnd::FunctionEnd {
+                    return Err(error(AssembleErrorKind::UnexpectedBlockEnd));
+                }
+                break;
+            }
+            if let Some(label) = text.strip_suffix(':') {
+                if !label.contains(char::is_whitespace) {
+                    if labels.insert(label.to_string(), items.len()).is_some() {
+                        return Err(
+                            error(AssembleErrorKind::DuplicateLabel { label: label.to_string() })
+                        );
+                    }
+                    continue;
+                }
+            }
+            let tokens = tokenize(text).map_err(error)?;
+            items.push(self.parse_optcode(line, tokens)?);
+        }
+        resolve_labels(items, &labels)
+    }
+
+    fn parse_optcode(&mut self, line: usize, tokens: Vec<Token>) -> Result<Item, AssembleError> {
+        let error = |kind| AssembleError { kind, line };
+        let mut tokens = tokens.into_iter();
+        let name = match tokens.next() {
+            Some(Token::Word(name)) => name,
+            Some(Token::Str(value)) => {
+                return Err(error(AssembleErrorKind::UnknownOptcode { name: format!("{:?}", value) }));
+            }
+            None => unreachable!("empty lines are skipped"),
+        };
+        let mut operands = Operands { optcode: &name, tokens: tokens.collect(), position: 0 };
+        let optcode = match name.as_str() {
+            "LoadInt" => OPTCODE::LoadInt { value: operands.parse().map_err(error)? },
+            "LoadBool" => OPTCODE::LoadBool { value: operands.parse().map_err(error)? },
+            "LoadFloat" => OPTCODE::LoadFloat { value: operands.parse().map_err(error)? },
+            "LoadDecimal" => OPTCODE::LoadDecimal { value: operands.decimal().map_err(error)? },
+            "LoadString" => OPTCODE::LoadString { value: operands.string().map_err(error)? },
+            "LoadVar" =>
+                OPTCODE::LoadVar {
+                    var_name: operands.word().map_err(error)?,
+                    id: operands.id().map_err(error)?,
+                    node_id: 0,
+                },
+            "DefineVar" =>
+                OPTCODE::DefineVar {
+                    var_name: operands.word().map_err(error)?,
+                    id: operands.id().map_err(error)?,
+                    node_id: 0,
+                },
+            "CallFunction" => {
+                let name = operands.word().map_err(error)?;
+                let Some(&id) = self.function_ids.get(&name) else {
+                    return Err(error(AssembleErrorKind::UnknownFunction { name }));
+                };
+                OPTCODE::CallFunction { id, name }
+            }
+            "CallSpecialFunction" =>
+                OPTCODE::CallSpecialFunction { function: operands.word().map_err(error)? },
+            "Add" => OPTCODE::Add { node_id: 0 },
+            "Subtract" => OPTCODE::Subtract { node_id: 0 },
+            "Multiply" => OPTCODE::Multiply { node_id: 0 },
+            "Divide" => OPTCODE::Divide { node_id: 0 },
+            "Remainder" => OPTCODE::Remainder { node_id: 0 },
+            "LessThan" => OPTCODE::LessThan { node_id: 0 },
+            "LargerThan" => OPTCODE::LargerThan { node_id: 0 },
+            "LessOrEq" => OPTCODE::LessOrEq { node_id: 0 },
+            "LargerOrEq" => OPTCODE::LargerOrEq { node_id: 0 },
+            "NotEq" => OPTCODE::NotEq { node_id: 0 },
+            "Eq" => OPTCODE::Eq { node_id: 0 },
+            "Or" => OPTCODE::Or { node_id: 0 },
+            "And" => OPTCODE::And { node_id: 0 },
+            "Xor" => OPTCODE::Xor { node_id: 0 },
+            "Not" => OPTCODE::Not,
+            "GetIndex" => OPTCODE::GetIndex,
+            "Return" => OPTCODE::Return,
+            "ReturnValue" => OPTCODE::ReturnValue,
+            "Step" => OPTCODE::Step,
+            "JumpIfFalse" | "Jump" | "JumpBack" | "RangeNext" | "ForEachNext" => {
+                let target = operands.word().map_err(error)?;
+                operands.finish().map_err(error)?;
+                let optcode: &'static str = match name.as_str() {
+                    "JumpIfFalse" => "JumpIfFalse",
+                    "Jump" => "Jump",
+                    "RangeNext" => "RangeNext",
+                    "ForEachNext" => "ForEachNext",
+                    _ => "JumpBack",
+                };
+                let sign = if optcode == "JumpBack" { '-' } else { '+' };
+                return match target.strip_prefix(sign) {
+                    Some(steps) => {
+                        let steps = steps.parse().map_err(|_| {
+                            error(AssembleErrorKind::InvalidOperand {
+                                optcode: name.clone(),
+                                operand: target.clone(),
+                            })
+                        })?;
+                        Ok(Item::Optcode(jump(optcode, steps)))
+                    }
+                    None => Ok(Item::Jump { line, optcode, label: target }),
+                };
+            }
+            "DefineObject" => OPTCODE::DefineObject { id: operands.id().map_err(error)? },
+            "CreateObject" => {
+                let mut field_names = vec![];
+                while operands.has_more() {
+                    field_names.push(operands.word().map_err(error)?);
+                }
+                OPTCODE::CreateObject { field_names }
+            }
+            "GetObjectField" =>
+                OPTCODE::GetObjectField { field_name: operands.word().map_err(error)? },
+            "SetObjectField" =>
+                OPTCODE::SetObjectField {
+                    id: operands.id().map_err(error)?,
+                    field_name: operands.word().map_err(error)?,
+                },
+            "CreateArray" =>
+                OPTCODE::CreateArray { init_values_count: operands.parse().map_err(error)? },
+            "AssignAtArrayIndex" =>
+                OPTCODE::AssignAtArrayIndex { id: operands.id().map_err(error)? },
+            "PushToArray" => OPTCODE::PushToArray { id: operands.id().map_err(error)? },
+            "GettArrayLength" => OPTCODE::GettArrayLength { id: operands.id().map_err(error)? },
+            "AssignVar" => OPTCODE::AssignVar { id: operands.id().map_err(error)? },
+            "Pop" => OPTCODE::Pop { count: operands.parse().map_err(error)? },
+            "CreateMap" =>
+                OPTCODE::CreateMap { init_entries_count: operands.parse().map_err(error)? },
+            "GetMapValue" => OPTCODE::GetMapValue,
+            "SetMapValue" => OPTCODE::SetMapValue { id: operands.id().map_err(error)? },
+            "RemoveMapKey" => OPTCODE::RemoveMapKey { id: operands.id().map_err(error)? },
+            "MapContains" => OPTCODE::MapContains,
+            "GetMapKeys" => OPTCODE::GetMapKeys,
+            "CopyValue" => OPTCODE::CopyValue,
+            "AssignAtPath" => {
+                let id = operands.id().map_err(error)?;
+                let mut path = vec![];
+                while operands.has_more() {
+                    let step = operands.word().map_err(error)?;
+                    path.push(match step.strip_prefix('.') {
+                        Some(name) if !name.is_empty() => PathStep::Field { name: name.to_string() },
+                        _ if step == "[]" => PathStep::Index,
+                        _ => {
+                            return Err(error(operands.invalid(&step)));
+                        }
+                    });
+                }
+                OPTCODE::AssignAtPath { id, path }
+            }
+            "PushToTestingStack" => {
+                let duplicate_stackvalue = operands.has_more();
+                if duplicate_stackvalue {
+                    operands.keyword("duplicate").map_err(error)?;
+                }
+                OPTCODE::PushToTestingStack { duplicate_stackvalue }
+            }
+            "Break" | "Continue" => {
+                return Err(error(AssembleErrorKind::LoopControl { optcode: name.clone() }));
+            }
+            "CopyVariableValue" => {
+                let src_var_id = operands.id().map_err(error)?;
+                if operands.peek() == Some(&Token::Word("->".to_string())) {
+                    operands.position += 1;
+                }
+                OPTCODE::CopyVariableValue { src_var_id, dst_var_id: operands.id().map_err(error)? }
+            }
+            "JumpToFunction" | "CallNativeFunction" => {
+                return Err(error(AssembleErrorKind::LinkedOptcode { optcode: name.clone() }));
+            }
+            _ => {
+                return Err(error(AssembleErrorKind::UnknownOptcode { name: name.clone() }));
+            }
+        };
+        operands.finish().map_err(error)?;
+        Ok(Item::Optcode(optcode))
+    }
+}
+
+fn jump(optcode: &str, steps: usize) -> OPTCODE {
+    match optcode {
+        "JumpIfFalse" =>
+            OPTCODE::JumpIfFalse {
+                steps,
+                jump_target_line: 0,
+                jump_target_column: 0,
+                is_skipable: false,
+            },
+        "Jump" => OPTCODE::Jump { steps },
+        "RangeNext" => OPTCODE::RangeNext { steps },
+        "ForEachNext" => OPTCODE::ForEachNext { steps },
+        _ => OPTCODE::JumpBack { steps },
+    }
+}
+
+/// Turns labels into step counts, using the same arithmetic as the VM:
+/// forward jumps land on `index + steps + 1`, `JumpBack` on `index - steps + 1`.
+fn resolve_labels(
+    items: Vec<Item>,
+    labels: &HashMap<String, usize>
+) -> Result<Vec<OPTCODE>, AssembleError> {
+    let mut bytecode = vec![];
+    for (index, item) in items.into_iter().enumerate() {
+        match item {
+            Item::Optcode(optcode) => bytecode.push(optcode),
+            Item::Jump { line, optcode, label } => {
+                let Some(target) = labels.get(&label).copied() else {
+                    return Err(AssembleError {
+                        kind: AssembleErrorKind::UnknownLabel { label },
+                        line,
+                    });
+                };
+                let steps = if optcode == "JumpBack" {
+                    (index + 1).checked_sub(target)
+                } else {
+                    target.checked_sub(index + 1)
+                };
+                match steps {
+                    Some(steps) => bytecode.push(jump(optcode, steps)),
+                    None => {
+                        return Err(AssembleError {
+                            kind: AssembleErrorKind::WrongJumpDirection {
+                                optcode: optcode.to_string(),
+                                label,
+                            },
+                            line,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(bytecode)
+}
+
+struct Operands<'a> {
+    optcode: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Operands<'_> {
+    fn has_more(&self) -> bool {
+        self.position < self.tokens.len()
+    }
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+    fn next(&mut self) -> Result<Token, AssembleErrorKind> {
+        let token = self.tokens
+            .get(self.position)
+            .cloned()
+            .ok_or(AssembleErrorKind::MissingOperand { optcode: self.optcode.to_string() })?;
+        self.position += 1;
+        Ok(token)
+    }
+    fn invalid(&self, operand: &str) -> AssembleErrorKind {
+        AssembleErrorKind::InvalidOperand {
+            optcode: self.optcode.to_string(),
+            operand: operand.to_string(),
+        }
+    }
+    fn word(&mut self) -> Result<String, AssembleErrorKind> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            Token::Str(value) => Err(self.invalid(&format!("{:?}", value))),
+        }
+    }
+    fn string(&mut self) -> Result<String, AssembleErrorKind> {
+        match self.next()? {
+            Token::Str(value) => Ok(value),
+            Token::Word(word) => Err(self.invalid(&word)),
+        }
+    }
+    fn parse<T: std::str::FromStr>(&mut self) -> Result<T, AssembleErrorKind> {
+        let word = self.word()?;
+        word.parse().map_err(|_| self.invalid(&word))
+    }
+    /// A decimal literal, kept as written
+    fn decimal(&mut self) -> Result<String, AssembleErrorKind> {
+        let word = self.word()?;
+        match parse_decimal(&word) {
+            Some(_) => Ok(word),
+            None => Err(self.invalid(&word)),
+        }
+    }
+    /// Variable ids are written as `#10`, the disassembler also wraps them in parentheses
+    fn id(&mut self) -> Result<usize, AssembleErrorKind> {
+        let word = self.word()?;
+        word.trim_start_matches('(')
+            .trim_end_matches(')')
+            .strip_prefix('#')
+            .and_then(|id| id.parse().ok())
+            .ok_or_else(|| self.invalid(&word))
+    }
+    fn keyword(&mut self, keyword: &str) -> Result<(), AssembleErrorKind> {
+        let word = self.word()?;
+        if word == keyword { Ok(()) } else { Err(self.invalid(&word)) }
+    }
+    /// Optional `line column` of a `Break`/`Continue`
+    fn finish(&self) -> Result<(), AssembleErrorKind> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => {
+                let operand = match token {
+                    Token::Word(word) => word.clone(),
+                    Token::Str(value) => format!("{:?}", value),
+                };
+                Err(AssembleErrorKind::UnexpectedOperand {
+                    optcode: self.optcode.to_string(),
+                    operand,
+                })
+            }
+        }
+    }
+}
+
+/// Splits a line on whitespace, keeping quoted strings together and dropping comments.
+fn tokenize(line: &str) -> Result<Vec<Token>, AssembleErrorKind> {
+    let mut tokens = vec![];
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == ';' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => {
+                        break;
+                    }
+                    Some('\\') =>
+                        match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some('r') => value.push('\r'),
+                            Some(escaped) => value.push(escaped),
+                            None => {
+                                return Err(AssembleErrorKind::UnterminatedString);
+                            }
+                        }
+                    Some(c) => value.push(c),
+                    None => {
+                        return Err(AssembleErrorKind::UnterminatedString);
+                    }
+                }
+            }
+            tokens.push(Token::Str(value));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '"' || c == ';' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses `.function name(arg: Type #id, mut other: [Int] #11) -> Type`.
+fn parse_function_header(header: &str) -> Result<FunctionSignature, AssembleErrorKind> {
+    let header = header
+        .strip_prefix(".function")
+        .ok_or(AssembleErrorKind::InvalidFunctionHeader)?
+        .trim();
+    let (name, rest) = header.split_once('(').ok_or(AssembleErrorKind::InvalidFunctionHeader)?;
+    let (args, rest) = rest.split_once(')').ok_or(AssembleErrorKind::InvalidFunctionHeader)?;
+    let return_type = match rest.trim() {
+        "" => None,
+        rest =>
+            Some(
+                parse_type(
+                    rest
+                        .strip_prefix("->")
+                        .ok_or(AssembleErrorKind::InvalidFunctionHeader)?
+                        .trim()
+                )?
+            ),
+    };
+    let mut func_args = vec![];
+    for arg in args.split(',').map(str::trim).filter(|arg| !arg.is_empty()) {
+        let (arg_name, arg_type) = arg
+            .split_once(':')
+            .ok_or(AssembleErrorKind::InvalidFunctionHeader)?;
+        let (mutable, arg_name) = match arg_name.trim().strip_prefix("mut ") {
+            Some(arg_name) => (true, arg_name.trim()),
+            None => (false, arg_name.trim()),
+        };
+        let (arg_type, local_var_id) = match arg_type.split_once('#') {
+            Some((arg_type, id)) =>
+                (
+                    arg_type,
+                    Some(id.trim().parse().map_err(|_| AssembleErrorKind::InvalidFunctionHeader)?),
+                ),
+            None => (arg_type, None),
+        };
+        func_args.push(FuncArg {
+            name: arg_name.to_string(),
+            arg_type: parse_type(arg_type.trim())?,
+            mutable,
+            local_var_id,
+        });
+    }
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(AssembleErrorKind::InvalidFunctionHeader);
+    }
+    Ok(FunctionSignature::new(name.to_string(), func_args, return_type))
+}
+
+/// `Int`, `Float`, `Bool`, `String`, arrays written as `[Int]` and maps written as `{String: Int}`.
+fn parse_type(name: &str) -> Result<BuiltinTypes, AssembleErrorKind> {
+    match name {
+        "Int" => Ok(BuiltinTypes::Int),
+        "Float" => Ok(BuiltinTypes::Float),
+        "Decimal" => Ok(BuiltinTypes::Decimal),
+        "Bool" => Ok(BuiltinTypes::Bool),
+        "String" => Ok(BuiltinTypes::String),
+        _ =>
+            match name.strip_prefix('[').and_then(|name| name.strip_suffix(']')) {
+                Some(element) =>
+                    Ok(BuiltinTypes::Array {
+                        element_type: Box::new(parse_type(element.trim())?),
+                        length: None,
+                    }),
+                None =>
+                    match
+                        name
+                            .strip_prefix('{')
+                            .and_then(|name| name.strip_suffix('}'))
+                            .and_then(|name| name.split_once(':'))
+                    {
+                        // Keys are never maps themselves, so the first colon separates the types
+                        Some((key, value)) =>
+                            Ok(BuiltinTypes::Map {
+                                key: Box::new(parse_type(key.trim())?),
+                                value: Box::new(parse_type(value.trim())?),
+                            }),
+                        None => Err(AssembleErrorKind::UnknownType { name: name.to_string() }),
+                    }
+            }
+    }
+}
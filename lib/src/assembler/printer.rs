@@ -0,0 +1,222 @@
+use std::{ collections::HashSet, ops::Range };
+
+use crate::{
+    bytecode::{ PathStep, OPTCODE },
+    module::FunctionSignature,
+    BuiltinTypes,
+    CelsiumProgram,
+};
+
+/// Prints the program as assembly that `assemble_program` reads back into the same optcodes.
+/// Node ids are not written, so like in any assembled program they are 0 after reading it back.
+/// Jump targets get labels and every optcode is followed by a comment with its index
+/// in the linked bytecode.
+pub fn print_program(program: &CelsiumProgram) -> String {
+    let bytecode = &program.main_block.bytecode;
+    let main_end = program.function_offsets.first().copied().unwrap_or(bytecode.len());
+    // The linker ends every block with a `Return`, which assembling adds back
+    let main_end = match bytecode[..main_end].last() {
+        Some(OPTCODE::Return) => main_end - 1,
+        _ => main_end,
+    };
+    let mut listing = write_block(bytecode, 0..main_end);
+    for (function, &offset) in program.functions.iter().zip(&program.function_offsets) {
+        let end = (offset + function.body.bytecode.len()).min(bytecode.len());
+        listing.push_str(&format!("\n{}\n", format_header(&function.signature)));
+        listing.push_str(&write_block(bytecode, offset..end));
+        listing.push_str(".end\n");
+    }
+    listing
+}
+
+/// Prints bytecode that is not part of a program, e.g. parsed from JSON.
+pub fn print_bytecode(bytecode: &[OPTCODE]) -> String {
+    write_block(bytecode, 0..bytecode.len())
+}
+
+/// Jumps that stay inside `range` are written with labels, others with their raw step count.
+fn write_block(bytecode: &[OPTCODE], range: Range<usize>) -> String {
+    let inside = |target: &usize| (range.start..=range.end).contains(target);
+    let labels: HashSet<usize> = range
+        .clone()
+        .filter_map(|index| jump_target(&bytecode[index], index))
+        .filter(inside)
+        .collect();
+    let mut listing = String::new();
+    for index in range.clone() {
+        if labels.contains(&index) {
+            listing.push_str(&format!("{}:\n", label(index)));
+        }
+        let optcode = &bytecode[index];
+        let text = match (optcode, jump_target(optcode, index).filter(inside)) {
+            (_, Some(target)) => format!("{} {}", optcode_name(optcode), label(target)),
+            _ => format_optcode(optcode),
+        };
+        listing.push_str(&format!("    {:<32} ; {:04}\n", text, index));
+    }
+    if labels.contains(&range.end) {
+        listing.push_str(&format!("{}:\n", label(range.end)));
+    }
+    listing
+}
+
+fn label(index: usize) -> String {
+    format!("L{:04}", index)
+}
+
+/// The index a jump lands on
+fn jump_target(optcode: &OPTCODE, index: usize) -> Option<usize> {
+    match optcode {
+        OPTCODE::JumpIfFalse { steps, .. } |
+        OPTCODE::Jump { steps } |
+        OPTCODE::RangeNext { steps } |
+        OPTCODE::ForEachNext { steps } => (index + 1).checked_add(*steps),
+        OPTCODE::JumpBack { steps } => (index + 1).checked_sub(*steps),
+        _ => None,
+    }
+}
+
+fn optcode_name(optcode: &OPTCODE) -> &'static str {
+    match optcode {
+        OPTCODE::JumpIfFalse { .. } => "JumpIfFalse",
+        OPTCODE::Jump { .. } => "Jump",
+        OPTCODE::RangeNext { .. } => "RangeNext",
+        OPTCODE::ForEachNext { .. } => "ForEachNext",
+        _ => "JumpBack",
+    }
+}
+
+/// `.function name(arg: Type #id, ...) -> Type`
+fn format_header(signature: &FunctionSignature) -> String {
+    let args: Vec<String> = signature.args
+        .iter()
+        .map(|arg| {
+            let mutable = if arg.mutable { "mut " } else { "" };
+            let id = arg.local_var_id.map_or(String::new(), |id| format!(" #{}", id));
+            format!("{}{}: {}{}", mutable, arg.name, format_type(&arg.arg_type), id)
+        })
+        .collect();
+    let return_type = signature.return_type
+        .as_ref()
+        .map_or(String::new(), |return_type| format!(" -> {}", format_type(return_type)));
+    format!(".function {}({}){}", signature.name, args.join(", "), return_type)
+}
+
+/// Types as the assembler writes them. Objects have no assembly syntax.
+fn format_type(data_type: &BuiltinTypes) -> String {
+    match data_type {
+        BuiltinTypes::Array { element_type, length: _ } => format!("[{}]", format_type(element_type)),
+        BuiltinTypes::Map { key, value } =>
+            format!("{{{}: {}}}", format_type(key), format_type(value)),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Quotes text the way the assembler's string literals are read
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The optcode followed by any number of operands
+fn words(optcode: String, operands: &[String]) -> String {
+    std::iter::once(optcode).chain(operands.iter().cloned()).collect::<Vec<_>>().join(" ")
+}
+
+/// Formats a single optcode in assembly, with jumps as raw step counts.
+pub fn format_optcode(optcode: &OPTCODE) -> String {
+    match optcode {
+        OPTCODE::LoadInt { value } => format!("LoadInt {}", value),
+        OPTCODE::LoadBool { value } => format!("LoadBool {}", value),
+        OPTCODE::LoadString { value } => format!("LoadString {}", quote(value)),
+        OPTCODE::LoadFloat { value } => format!("LoadFloat {:?}", value),
+        OPTCODE::LoadDecimal { value } => format!("LoadDecimal {}", value),
+        OPTCODE::LoadVar { id, node_id: _, var_name } => format!("LoadVar {} #{}", var_name, id),
+        OPTCODE::DefineVar { id, var_name, node_id: _ } => format!("DefineVar {} #{}", var_name, id),
+        // Calls are linked by name again when the listing is assembled
+        OPTCODE::CallFunction { id: _, name } |
+        OPTCODE::JumpToFunction { target: _, function: _, function_name: name } =>
+            format!("CallFunction {}", name),
+        OPTCODE::JumpIfFalse { steps, .. } => format!("JumpIfFalse +{}", steps),
+        OPTCODE::Jump { steps } => format!("Jump +{}", steps),
+        OPTCODE::RangeNext { steps } => format!("RangeNext +{}", steps),
+        OPTCODE::ForEachNext { steps } => format!("ForEachNext +{}", steps),
+        OPTCODE::JumpBack { steps } => format!("JumpBack -{}", steps),
+        OPTCODE::DefineObject { id } => format!("DefineObject #{}", id),
+        OPTCODE::CreateObject { field_names } => words("CreateObject".to_string(), field_names),
+        OPTCODE::GetObjectField { field_name } => format!("GetObjectField {}", field_name),
+        OPTCODE::SetObjectField { id, field_name } =>
+            format!("SetObjectField #{} {}", id, field_name),
+        OPTCODE::CreateArray { init_values_count } => format!("CreateArray {}", init_values_count),
+        OPTCODE::AssignAtArrayIndex { id } => format!("AssignAtArrayIndex #{}", id),
+        OPTCODE::PushToArray { id } => format!("PushToArray #{}", id),
+        OPTCODE::GettArrayLength { id } => format!("GettArrayLength #{}", id),
+        OPTCODE::AssignVar { id } => format!("AssignVar #{}", id),
+        OPTCODE::CallSpecialFunction { function: name } |
+        OPTCODE::CallNativeFunction { index: _, function_name: name } =>
+            format!("CallSpecialFunction {}", name),
+        OPTCODE::PushToTestingStack { duplicate_stackvalue } => {
+            if *duplicate_stackvalue {
+                "PushToTestingStack duplicate".to_string()
+            } else {
+                "PushToTestingStack".to_string()
+            }
+        }
+        // Not assembled, the loop builders lower them to jumps
+        OPTCODE::Break { span } => format!("Break ; line {}, column {}", span.line, span.col_start),
+        OPTCODE::Continue { span } =>
+            format!("Continue ; line {}, column {}", span.line, span.col_start),
+        OPTCODE::CopyVariableValue { src_var_id, dst_var_id } =>
+            format!("CopyVariableValue #{} -> #{}", src_var_id, dst_var_id),
+        OPTCODE::Add { .. } => "Add".to_string(),
+        OPTCODE::Subtract { .. } => "Subtract".to_string(),
+        OPTCODE::Multiply { .. } => "Multiply".to_string(),
+        OPTCODE::Divide { .. } => "Divide".to_string(),
+        OPTCODE::Remainder { .. } => "Remainder".to_string(),
+        OPTCODE::LessThan { .. } => "LessThan".to_string(),
+        OPTCODE::LargerThan { .. } => "LargerThan".to_string(),
+        OPTCODE::LessOrEq { .. } => "LessOrEq".to_string(),
+        OPTCODE::LargerOrEq { .. } => "LargerOrEq".to_string(),
+        OPTCODE::NotEq { .. } => "NotEq".to_string(),
+        OPTCODE::Eq { .. } => "Eq".to_string(),
+        OPTCODE::Or { .. } => "Or".to_string(),
+        OPTCODE::And { .. } => "And".to_string(),
+        OPTCODE::Xor { .. } => "Xor".to_string(),
+        OPTCODE::Not => "Not".to_string(),
+        OPTCODE::GetIndex => "GetIndex".to_string(),
+        OPTCODE::Return => "Return".to_string(),
+        OPTCODE::ReturnValue => "ReturnValue".to_string(),
+        OPTCODE::Step => "Step".to_string(),
+        OPTCODE::Pop { count } => format!("Pop {}", count),
+        OPTCODE::CreateMap { init_entries_count } => format!("CreateMap {}", init_entries_count),
+        OPTCODE::GetMapValue => "GetMapValue".to_string(),
+        OPTCODE::SetMapValue { id } => format!("SetMapValue #{}", id),
+        OPTCODE::RemoveMapKey { id } => format!("RemoveMapKey #{}", id),
+        OPTCODE::MapContains => "MapContains".to_string(),
+        OPTCODE::GetMapKeys => "GetMapKeys".to_string(),
+        OPTCODE::CopyValue => "CopyValue".to_string(),
+        OPTCODE::AssignAtPath { id, path } => {
+            let steps: Vec<String> = path
+                .iter()
+                .map(|step| {
+                    match step {
+                        PathStep::Index => "[]".to_string(),
+                        PathStep::Field { name } => format!(".{}", name),
+                    }
+                })
+                .collect();
+            words(format!("AssignAtPath #{}", id), &steps)
+        }
+    }
+}
@@ -0,0 +1,769 @@
+//! Compact binary container for linked programs.
+//!
+//! Layout, all integers are LEB128 varints unless noted:
+//! - magic `CELS` and the format version as a little endian `u16`
+//! - constant pool: every string used by the program, referenced by index everywhere else
+//! - function table: signature, offset and length of each function in the linked bytecode
+//! - main block: scope and linked bytecode
+//! - source tables: spans by node id, node ids by line, node parents
+//!
+//! Decoded programs are checked with the verifier before they are returned.
+
+use std::{ collections::HashMap, fmt };
+
+use crate::{
+    block::{ Block, TextSpan },
+    bytecode::{ PathStep, OPTCODE },
+    bytecode_parser::FunctionEntry,
+    linker,
+    locale::Locale,
+    module::{ FuncArg, FunctionSignature },
+    native::NativeRegistry,
+    observer,
+    verifier::VerifyError,
+    vm::limits::VmLimits,
+    BuiltinTypes,
+    CelsiumProgram,
+    ObjectFieldType,
+    Scope,
+};
+
+pub const MAGIC: &[u8; 4] = b"CELS";
+pub const FORMAT_VERSION: u16 = 3;
+/// Types nested deeper than this are rejected instead of overflowing the stack while decoding
+pub const MAX_TYPE_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    InvalidMagic,
+    UnsupportedVersion {
+        version: u16,
+    },
+    UnexpectedEnd,
+    /// An enum tag that does not belong to any variant
+    InvalidTag {
+        what: &'static str,
+        tag: u8,
+    },
+    InvalidConstant {
+        index: usize,
+    },
+    InvalidUtf8,
+    VarintOverflow,
+    /// A function's bytecode range is outside of the main block
+    InvalidFunctionRange {
+        name: String,
+    },
+    TrailingBytes,
+    /// A type nested deeper than `MAX_TYPE_DEPTH`
+    TypeTooDeep,
+    /// The program decoded, but would fail when run
+    InvalidBytecode {
+        errors: Vec<VerifyError>,
+    },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::InvalidMagic => write!(f, "Not a celsium bytecode file"),
+            DecodeError::UnsupportedVersion { version } =>
+                write!(
+                    f,
+                    "Bytecode format version {} is not supported, expected {}",
+                    version,
+                    FORMAT_VERSION
+                ),
+            DecodeError::UnexpectedEnd => write!(f, "Bytecode ends unexpectedly"),
+            DecodeError::InvalidTag { what, tag } => write!(f, "Invalid {} tag {}", what, tag),
+            DecodeError::InvalidConstant { index } =>
+                write!(f, "Constant {} is not in the constant pool", index),
+            DecodeError::InvalidUtf8 => write!(f, "Constant is not valid UTF-8"),
+            DecodeError::VarintOverflow => write!(f, "Integer does not fit in 64 bits"),
+            DecodeError::InvalidFunctionRange { name } =>
+                write!(f, "Function \"{}\" points outside of the bytecode", name),
+            DecodeError::TrailingBytes => write!(f, "Unexpected bytes after the end of the program"),
+            DecodeError::TypeTooDeep => write!(f, "Type is nested deeper than {} levels", MAX_TYPE_DEPTH),
+            DecodeError::InvalidBytecode { errors } => {
+                write!(f, "Invalid bytecode:")?;
+                for error in errors {
+                    write!(f, "\n{}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+pub fn to_bytes(program: &CelsiumProgram) -> Vec<u8> {
+    let mut body = Writer::default();
+
+    body.usize(program.functions.len());
+    for (function, offset) in program.functions.iter().zip(&program.function_offsets) {
+        body.usize(function.id);
+        body.signature(&function.signature);
+        body.usize(*offset);
+        body.usize(function.body.bytecode.len());
+    }
+
+    body.scope(&program.main_block.scope);
+    body.bytecode(&program.main_block.bytecode);
+
+    // HashMaps are sorted so the same program always gives the same bytes
+    let mut spans: Vec<_> = program.node_locations_by_id.iter().collect();
+    spans.sort_by_key(|(node_id, _)| **node_id);
+    body.usize(spans.len());
+    for (node_id, span) in spans {
+        body.usize(*node_id);
+        body.span(span);
+    }
+    let mut lines: Vec<_> = program.node_ids_by_line.iter().collect();
+    lines.sort_by_key(|(line, _)| **line);
+    body.usize(lines.len());
+    for (line, node_ids) in lines {
+        body.usize(*line);
+        body.usize(node_ids.len());
+        for node_id in node_ids {
+            body.usize(*node_id);
+        }
+    }
+    let mut parents: Vec<_> = program.node_parents.iter().collect();
+    parents.sort_by_key(|(node_id, _)| **node_id);
+    body.usize(parents.len());
+    for (node_id, parent) in parents {
+        body.usize(*node_id);
+        body.optional_usize(*parent);
+    }
+
+    let mut header = Writer::default();
+    header.bytes.extend_from_slice(MAGIC);
+    header.bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header.usize(body.constants.len());
+    for constant in &body.constants {
+        header.usize(constant.len());
+        header.bytes.extend_from_slice(constant.as_bytes());
+    }
+    header.bytes.extend(body.bytes);
+    header.bytes
+}
+
+pub fn from_bytes(bytes: &[u8]) -> Result<CelsiumProgram, DecodeError> {
+    let mut reader = Reader { bytes, position: 0, constants: vec![] };
+    if reader.take(4)? != MAGIC {
+        return Err(DecodeError::InvalidMagic);
+    }
+    let version = u16::from_le_bytes([reader.byte()?, reader.byte()?]);
+    if version != FORMAT_VERSION {
+        return Err(DecodeError::UnsupportedVersion { version });
+    }
+    let constant_count = reader.usize()?;
+    for _ in 0..constant_count {
+        let length = reader.usize()?;
+        let constant = std::str
+            ::from_utf8(reader.take(length)?)
+            .map_err(|_| DecodeError::InvalidUtf8)?;
+        reader.constants.push(constant.to_string());
+    }
+
+    let mut function_table = vec![];
+    for _ in 0..reader.usize()? {
+        function_table.push(FunctionEntry {
+            id: reader.usize()?,
+            signature: reader.signature()?,
+            offset: reader.usize()?,
+            length: reader.usize()?,
+        });
+    }
+
+    let scope = reader.scope()?;
+    let bytecode = reader.bytecode()?;
+
+    let (functions, function_offsets) = linker
+        ::split_functions(&bytecode, function_table, &scope)
+        .map_err(|name| DecodeError::InvalidFunctionRange { name })?;
+
+    let mut node_locations_by_id = HashMap::new();
+    for _ in 0..reader.usize()? {
+        node_locations_by_id.insert(reader.usize()?, reader.span()?);
+    }
+    let mut node_ids_by_line = HashMap::new();
+    for _ in 0..reader.usize()? {
+        let line = reader.usize()?;
+        let mut node_ids = vec![];
+        for _ in 0..reader.usize()? {
+            node_ids.push(reader.usize()?);
+        }
+        node_ids_by_line.insert(line, node_ids);
+    }
+    let mut node_parents = HashMap::new();
+    for _ in 0..reader.usize()? {
+        node_parents.insert(reader.usize()?, reader.optional_usize()?);
+    }
+    if reader.position != bytes.len() {
+        return Err(DecodeError::TrailingBytes);
+    }
+
+    let program = CelsiumProgram {
+        main_block: Block { bytecode, scope },
+        functions,
+        node_locations_by_id,
+        node_ids_by_line,
+        node_parents,
+        natives: NativeRegistry::new(),
+        limits: VmLimits::default(),
+        io: None,
+        observers: observer::default_observer().into_iter().collect(),
+        locale: Locale::default(),
+        function_offsets,
+    };
+    program.verify().map_err(|errors| DecodeError::InvalidBytecode { errors })?;
+    Ok(program)
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+    constants: Vec<String>,
+    constant_ids: HashMap<String, usize>,
+}
+
+impl Writer {
+    fn u64(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.bytes.push(byte);
+                return;
+            }
+            self.bytes.push(byte | 0x80);
+        }
+    }
+    fn usize(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+    fn i64(&mut self, value: i64) {
+        // zigzag, so small negative numbers stay short
+        self.u64(((value << 1) ^ (value >> 63)) as u64);
+    }
+    fn bool(&mut self, value: bool) {
+        self.bytes.push(value as u8);
+    }
+    fn optional_usize(&mut self, value: Option<usize>) {
+        match value {
+            Some(value) => {
+                self.bool(true);
+                self.usize(value);
+            }
+            None => self.bool(false),
+        }
+    }
+    fn string(&mut self, value: &str) {
+        let index = match self.constant_ids.get(value) {
+            Some(index) => *index,
+            None => {
+                self.constants.push(value.to_string());
+                self.constant_ids.insert(value.to_string(), self.constants.len() - 1);
+                self.constants.len() - 1
+            }
+        };
+        self.usize(index);
+    }
+    fn span(&mut self, span: &TextSpan) {
+        self.usize(span.line);
+        self.usize(span.col_start);
+        self.usize(span.length);
+    }
+    fn scope(&mut self, scope: &Scope) {
+        self.usize(scope.ast_id);
+        self.string(&scope.module_path);
+    }
+    fn data_type(&mut self, data_type: &BuiltinTypes) {
+        match data_type {
+            BuiltinTypes::Int => self.bytes.push(0),
+            BuiltinTypes::Bool => self.bytes.push(1),
+            BuiltinTypes::String => self.bytes.push(2),
+            BuiltinTypes::Object { fields } => {
+                self.bytes.push(3);
+                self.usize(fields.len());
+                for field in fields {
+                    self.string(&field.name);
+                    self.data_type(&field.data_type);
+                }
+            }
+            BuiltinTypes::Array { element_type, length } => {
+                self.bytes.push(4);
+                self.data_type(element_type);
+                self.optional_usize(*length);
+            }
+            BuiltinTypes::Float => self.bytes.push(5),
+            BuiltinTypes::Map { key, value } => {
+                self.bytes.push(6);
+                self.data_type(key);
+                self.data_type(value);
+            }
+            BuiltinTypes::Decimal => self.bytes.push(7),
+        }
+    }
+    fn signature(&mut self, signature: &FunctionSignature) {
+        self.string(&signature.name);
+        self.usize(signature.args.len());
+        for arg in &signature.args {
+            self.string(&arg.name);
+            self.data_type(&arg.arg_type);
+            self.bool(arg.mutable);
+            self.optional_usize(arg.local_var_id);
+        }
+        match &signature.return_type {
+            Some(return_type) => {
+                self.bool(true);
+                self.data_type(return_type);
+            }
+            None => self.bool(false),
+        }
+    }
+    fn bytecode(&mut self, bytecode: &[OPTCODE]) {
+        self.usize(bytecode.len());
+        for optcode in bytecode {
+            self.optcode(optcode);
+        }
+    }
+    fn optcode(&mut self, optcode: &OPTCODE) {
+        match optcode {
+            OPTCODE::LoadInt { value } => {
+                self.bytes.push(0);
+                self.i64(*value);
+            }
+            OPTCODE::LoadBool { value } => {
+                self.bytes.push(1);
+                self.bool(*value);
+            }
+            OPTCODE::LoadString { value } => {
+                self.bytes.push(2);
+                self.string(value);
+            }
+            OPTCODE::LoadFloat { value } => {
+                self.bytes.push(3);
+                self.bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            OPTCODE::LoadVar { id, node_id, var_name } => {
+                self.bytes.push(4);
+                self.usize(*id);
+                self.usize(*node_id);
+                self.string(var_name);
+            }
+            OPTCODE::CallFunction { id, name } => {
+                self.bytes.push(5);
+                self.usize(*id);
+                self.string(name);
+            }
+            OPTCODE::Add { node_id } => self.node_optcode(6, *node_id),
+            OPTCODE::Subtract { node_id } => self.node_optcode(7, *node_id),
+            OPTCODE::Multiply { node_id } => self.node_optcode(8, *node_id),
+            OPTCODE::Divide { node_id } => self.node_optcode(9, *node_id),
+            OPTCODE::Remainder { node_id } => self.node_optcode(10, *node_id),
+            OPTCODE::LessThan { node_id } => self.node_optcode(11, *node_id),
+            OPTCODE::LargerThan { node_id } => self.node_optcode(12, *node_id),
+            OPTCODE::LessOrEq { node_id } => self.node_optcode(13, *node_id),
+            OPTCODE::LargerOrEq { node_id } => self.node_optcode(14, *node_id),
+            OPTCODE::NotEq { node_id } => self.node_optcode(15, *node_id),
+            OPTCODE::Eq { node_id } => self.node_optcode(16, *node_id),
+            OPTCODE::Or { node_id } => self.node_optcode(17, *node_id),
+            OPTCODE::And { node_id } => self.node_optcode(18, *node_id),
+            OPTCODE::Xor { node_id } => self.node_optcode(19, *node_id),
+            OPTCODE::Not => self.bytes.push(20),
+            OPTCODE::JumpIfFalse { steps, jump_target_line, jump_target_column, is_skipable } => {
+                self.bytes.push(21);
+                self.usize(*steps);
+                self.usize(*jump_target_line);
+                self.usize(*jump_target_column);
+                self.bool(*is_skipable);
+            }
+            OPTCODE::Jump { steps } => {
+                self.bytes.push(22);
+                self.usize(*steps);
+            }
+            OPTCODE::JumpToFunction { target, function, function_name } => {
+                self.bytes.push(23);
+                self.usize(*target);
+                self.usize(*function);
+                self.string(function_name);
+            }
+            OPTCODE::JumpBack { steps } => {
+                self.bytes.push(24);
+                self.usize(*steps);
+            }
+            OPTCODE::DefineVar { id, var_name, node_id } => {
+                self.bytes.push(25);
+                self.usize(*id);
+                self.string(var_name);
+                self.usize(*node_id);
+            }
+            OPTCODE::DefineObject { id } => {
+                self.bytes.push(26);
+                self.usize(*id);
+            }
+            OPTCODE::CreateObject { field_names } => {
+                self.bytes.push(27);
+                self.usize(field_names.len());
+                for field_name in field_names {
+                    self.string(field_name);
+                }
+            }
+            OPTCODE::GetObjectField { field_name } => {
+                self.bytes.push(28);
+                self.string(field_name);
+            }
+            OPTCODE::SetObjectField { id, field_name } => {
+                self.bytes.push(29);
+                self.usize(*id);
+                self.string(field_name);
+            }
+            OPTCODE::CreateArray { init_values_count } => {
+                self.bytes.push(30);
+                self.usize(*init_values_count);
+            }
+            OPTCODE::GetIndex => self.bytes.push(31),
+            OPTCODE::AssignAtArrayIndex { id } => {
+                self.bytes.push(32);
+                self.usize(*id);
+            }
+            OPTCODE::PushToArray { id } => {
+                self.bytes.push(33);
+                self.usize(*id);
+            }
+            OPTCODE::GettArrayLength { id } => {
+                self.bytes.push(34);
+                self.usize(*id);
+            }
+            OPTCODE::AssignVar { id } => {
+                self.bytes.push(35);
+                self.usize(*id);
+            }
+            OPTCODE::CallSpecialFunction { function } => {
+                self.bytes.push(36);
+                self.string(function);
+            }
+            OPTCODE::CallNativeFunction { index, function_name } => {
+                self.bytes.push(37);
+                self.usize(*index);
+                self.string(function_name);
+            }
+            OPTCODE::PushToTestingStack { duplicate_stackvalue } => {
+                self.bytes.push(39);
+                self.bool(*duplicate_stackvalue);
+            }
+            OPTCODE::Break { span } => {
+                self.bytes.push(40);
+                self.span(span);
+            }
+            OPTCODE::Continue { span } => {
+                self.bytes.push(41);
+                self.span(span);
+            }
+            OPTCODE::Return => self.bytes.push(42),
+            OPTCODE::ReturnValue => self.bytes.push(43),
+            OPTCODE::CopyVariableValue { src_var_id, dst_var_id } => {
+                self.bytes.push(44);
+                self.usize(*src_var_id);
+                self.usize(*dst_var_id);
+            }
+            OPTCODE::Step => self.bytes.push(45),
+            OPTCODE::RangeNext { steps } => {
+                self.bytes.push(46);
+                self.usize(*steps);
+            }
+            OPTCODE::ForEachNext { steps } => {
+                self.bytes.push(47);
+                self.usize(*steps);
+            }
+            OPTCODE::Pop { count } => {
+                self.bytes.push(48);
+                self.usize(*count);
+            }
+            OPTCODE::CreateMap { init_entries_count } => {
+                self.bytes.push(49);
+                self.usize(*init_entries_count);
+            }
+            OPTCODE::GetMapValue => self.bytes.push(50),
+            OPTCODE::SetMapValue { id } => {
+                self.bytes.push(51);
+                self.usize(*id);
+            }
+            OPTCODE::RemoveMapKey { id } => {
+                self.bytes.push(52);
+                self.usize(*id);
+            }
+            OPTCODE::MapContains => self.bytes.push(53),
+            OPTCODE::GetMapKeys => self.bytes.push(54),
+            OPTCODE::CopyValue => self.bytes.push(55),
+            OPTCODE::LoadDecimal { value } => {
+                self.bytes.push(57);
+                self.string(value);
+            }
+            OPTCODE::AssignAtPath { id, path } => {
+                self.bytes.push(56);
+                self.usize(*id);
+                self.usize(path.len());
+                for step in path {
+                    match step {
+                        PathStep::Index => self.bytes.push(0),
+                        PathStep::Field { name } => {
+                            self.bytes.push(1);
+                            self.string(name);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    fn node_optcode(&mut self, tag: u8, node_id: usize) {
+        self.bytes.push(tag);
+        self.usize(node_id);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    constants: Vec<String>,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self.position.checked_add(length).ok_or(DecodeError::UnexpectedEnd)?;
+        let bytes = self.bytes.get(self.position..end).ok_or(DecodeError::UnexpectedEnd)?;
+        self.position = end;
+        Ok(bytes)
+    }
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+    fn u64(&mut self) -> Result<u64, DecodeError> {
+        let mut value: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= 64 || (shift == 63 && byte > 1) {
+                return Err(DecodeError::VarintOverflow);
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+    fn usize(&mut self) -> Result<usize, DecodeError> {
+        usize::try_from(self.u64()?).map_err(|_| DecodeError::VarintOverflow)
+    }
+    fn i64(&mut self) -> Result<i64, DecodeError> {
+        let value = self.u64()?;
+        Ok(((value >> 1) as i64) ^ -((value & 1) as i64))
+    }
+    fn bool(&mut self) -> Result<bool, DecodeError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(DecodeError::InvalidTag { what: "bool", tag }),
+        }
+    }
+    fn optional_usize(&mut self) -> Result<Option<usize>, DecodeError> {
+        if self.bool()? { Ok(Some(self.usize()?)) } else { Ok(None) }
+    }
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let index = self.usize()?;
+        self.constants.get(index).cloned().ok_or(DecodeError::InvalidConstant { index })
+    }
+    fn span(&mut self) -> Result<TextSpan, DecodeError> {
+        Ok(TextSpan {
+            line: self.usize()?,
+            col_start: self.usize()?,
+            length: self.usize()?,
+        })
+    }
+    fn scope(&mut self) -> Result<Scope, DecodeError> {
+        Ok(Scope {
+            ast_id: self.usize()?,
+            module_path: self.string()?,
+        })
+    }
+    fn data_type(&mut self) -> Result<BuiltinTypes, DecodeError> {
+        self.nested_type(0)
+    }
+    fn nested_type(&mut self, depth: usize) -> Result<BuiltinTypes, DecodeError> {
+        if depth > MAX_TYPE_DEPTH {
+            return Err(DecodeError::TypeTooDeep);
+        }
+        match self.byte()? {
+            0 => Ok(BuiltinTypes::Int),
+            1 => Ok(BuiltinTypes::Bool),
+            2 => Ok(BuiltinTypes::String),
+            3 => {
+                let mut fields = vec![];
+                for _ in 0..self.usize()? {
+                    fields.push(ObjectFieldType {
+                        name: self.string()?,
+                        data_type: self.nested_type(depth + 1)?,
+                    });
+                }
+                Ok(BuiltinTypes::Object { fields })
+            }
+            4 =>
+                Ok(BuiltinTypes::Array {
+                    element_type: Box::new(self.nested_type(depth + 1)?),
+                    length: self.optional_usize()?,
+                }),
+            5 => Ok(BuiltinTypes::Float),
+            6 =>
+                Ok(BuiltinTypes::Map {
+                    key: Box::new(self.nested_type(depth + 1)?),
+                    value: Box::new(self.nested_type(depth + 1)?),
+                }),
+            7 => Ok(BuiltinTypes::Decimal),
+            tag => Err(DecodeError::InvalidTag { what: "type", tag }),
+        }
+    }
+    fn signature(&mut self) -> Result<FunctionSignature, DecodeError> {
+        let name = self.string()?;
+        let mut args = vec![];
+        for _ in 0..self.usize()? {
+            args.push(FuncArg {
+                name: self.string()?,
+                arg_type: self.data_type()?,
+                mutable: self.bool()?,
+                local_var_id: self.optional_usize()?,
+            });
+        }
+        let return_type = if self.bool()? { Some(self.data_type()?) } else { None };
+        Ok(FunctionSignature::new(name, args, return_type))
+    }
+    fn bytecode(&mut self) -> Result<Vec<OPTCODE>, DecodeError> {
+        let mut bytecode = vec![];
+        for _ in 0..self.usize()? {
+            bytecode.push(self.optcode()?);
+        }
+        Ok(bytecode)
+    }
+    fn optcode(&mut self) -> Result<OPTCODE, DecodeError> {
+        let optcode = match self.byte()? {
+            0 => OPTCODE::LoadInt { value: self.i64()? },
+            1 => OPTCODE::LoadBool { value: self.bool()? },
+            2 => OPTCODE::LoadString { value: self.string()? },
+            3 => {
+                let bytes = self.take(8)?;
+                let mut value = [0; 8];
+                value.copy_from_slice(bytes);
+                OPTCODE::LoadFloat { value: f64::from_le_bytes(value) }
+            }
+            4 =>
+                OPTCODE::LoadVar {
+                    id: self.usize()?,
+                    node_id: self.usize()?,
+                    var_name: self.string()?,
+                },
+            5 => OPTCODE::CallFunction { id: self.usize()?, name: self.string()? },
+            6 => OPTCODE::Add { node_id: self.usize()? },
+            7 => OPTCODE::Subtract { node_id: self.usize()? },
+            8 => OPTCODE::Multiply { node_id: self.usize()? },
+            9 => OPTCODE::Divide { node_id: self.usize()? },
+            10 => OPTCODE::Remainder { node_id: self.usize()? },
+            11 => OPTCODE::LessThan { node_id: self.usize()? },
+            12 => OPTCODE::LargerThan { node_id: self.usize()? },
+            13 => OPTCODE::LessOrEq { node_id: self.usize()? },
+            14 => OPTCODE::LargerOrEq { node_id: self.usize()? },
+            15 => OPTCODE::NotEq { node_id: self.usize()? },
+            16 => OPTCODE::Eq { node_id: self.usize()? },
+            17 => OPTCODE::Or { node_id: self.usize()? },
+            18 => OPTCODE::And { node_id: self.usize()? },
+            19 => OPTCODE::Xor { node_id: self.usize()? },
+            20 => OPTCODE::Not,
+            21 =>
+                OPTCODE::JumpIfFalse {
+                    steps: self.usize()?,
+                    jump_target_line: self.usize()?,
+                    jump_target_column: self.usize()?,
+                    is_skipable: self.bool()?,
+                },
+            22 => OPTCODE::Jump { steps: self.usize()? },
+            23 =>
+                OPTCODE::JumpToFunction {
+                    target: self.usize()?,
+                    function: self.usize()?,
+                    function_name: self.string()?,
+                },
+            24 => OPTCODE::JumpBack { steps: self.usize()? },
+            25 =>
+                OPTCODE::DefineVar {
+                    id: self.usize()?,
+                    var_name: self.string()?,
+                    node_id: self.usize()?,
+                },
+            26 => OPTCODE::DefineObject { id: self.usize()? },
+            27 => {
+                let mut field_names = vec![];
+                for _ in 0..self.usize()? {
+                    field_names.push(self.string()?);
+                }
+                OPTCODE::CreateObject { field_names }
+            }
+            28 => OPTCODE::GetObjectField { field_name: self.string()? },
+            29 => OPTCODE::SetObjectField { id: self.usize()?, field_name: self.string()? },
+            30 => OPTCODE::CreateArray { init_values_count: self.usize()? },
+            31 => OPTCODE::GetIndex,
+            32 => OPTCODE::AssignAtArrayIndex { id: self.usize()? },
+            33 => OPTCODE::PushToArray { id: self.usize()? },
+            34 => OPTCODE::GettArrayLength { id: self.usize()? },
+            35 => OPTCODE::AssignVar { id: self.usize()? },
+            36 => OPTCODE::CallSpecialFunction { function: self.string()? },
+            37 =>
+                OPTCODE::CallNativeFunction {
+                    index: self.usize()?,
+                    function_name: self.string()?,
+                },
+            // 38 was the nested `SimpleLoop` of version 1
+            39 => OPTCODE::PushToTestingStack { duplicate_stackvalue: self.bool()? },
+            40 => OPTCODE::Break { span: self.span()? },
+            41 => OPTCODE::Continue { span: self.span()? },
+            42 => OPTCODE::Return,
+            43 => OPTCODE::ReturnValue,
+            44 =>
+                OPTCODE::CopyVariableValue {
+                    src_var_id: self.usize()?,
+                    dst_var_id: self.usize()?,
+                },
+            45 => OPTCODE::Step,
+            46 => OPTCODE::RangeNext { steps: self.usize()? },
+            47 => OPTCODE::ForEachNext { steps: self.usize()? },
+            48 => OPTCODE::Pop { count: self.usize()? },
+            49 => OPTCODE::CreateMap { init_entries_count: self.usize()? },
+            50 => OPTCODE::GetMapValue,
+            51 => OPTCODE::SetMapValue { id: self.usize()? },
+            52 => OPTCODE::RemoveMapKey { id: self.usize()? },
+            53 => OPTCODE::MapContains,
+            54 => OPTCODE::GetMapKeys,
+            55 => OPTCODE::CopyValue,
+            56 => {
+                let id = self.usize()?;
+                let mut path = vec![];
+                for _ in 0..self.usize()? {
+                    path.push(match self.byte()? {
+                        0 => PathStep::Index,
+                        1 => PathStep::Field { name: self.string()? },
+                        tag => {
+                            return Err(DecodeError::InvalidTag { what: "path step", tag });
+                        }
+                    });
+                }
+                OPTCODE::AssignAtPath { id, path }
+            }
+            57 => OPTCODE::LoadDecimal { value: self.string()? },
+            tag => {
+                return Err(DecodeError::InvalidTag { what: "optcode", tag });
+            }
+        };
+        Ok(optcode)
+    }
+}
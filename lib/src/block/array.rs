@@ -1,4 +1,4 @@
-use crate::bytecode::OPTCODE;
+use crate::bytecode::{ PathStep, OPTCODE };
 
 use super::Block;
 
@@ -7,6 +7,11 @@ impl Block {
         self.bytecode.push(OPTCODE::GetIndex {})
     }
 
+    /// Assigns to a nested place like `matrix[i][j]`, see `VM::assign_at_path`.
+    pub fn assign_at_path(&mut self, id: usize, path: Vec<PathStep>) {
+        self.bytecode.push(OPTCODE::AssignAtPath { id, path });
+    }
+
     pub fn assign_to_array(&mut self, id: usize) {
         self.bytecode.push(OPTCODE::AssignAtArrayIndex { id });
     }
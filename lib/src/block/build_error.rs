@@ -0,0 +1,59 @@
+use std::fmt;
+
+use super::TextSpan;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildErrorKind {
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+    /// `CallFunction`s whose id is not in the function table, as `(id, name)`, each listed once
+    UnresolvedCalls {
+        calls: Vec<(usize, String)>,
+    },
+    DuplicateFunctionId {
+        id: usize,
+    },
+}
+
+/// A problem in the blocks given to `CelsiumProgram::new`. `span` is the one stored in the
+/// optcode, linking errors have none.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildError {
+    pub kind: BuildErrorKind,
+    pub span: Option<TextSpan>,
+}
+
+impl From<BuildErrorKind> for BuildError {
+    fn from(kind: BuildErrorKind) -> Self {
+        BuildError { kind, span: None }
+    }
+}
+
+impl fmt::Display for BuildErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildErrorKind::BreakOutsideLoop => write!(f, "Break outside of a loop"),
+            BuildErrorKind::ContinueOutsideLoop => write!(f, "Continue outside of a loop"),
+            BuildErrorKind::UnresolvedCalls { calls } => {
+                let calls: Vec<String> = calls
+                    .iter()
+                    .map(|(id, name)| format!("{} (#{})", name, id))
+                    .collect();
+                write!(f, "Calls to undefined functions: {}", calls.join(", "))
+            }
+            BuildErrorKind::DuplicateFunctionId { id } =>
+                write!(f, "More than one function has the ID {}", id),
+        }
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.span {
+            Some(span) => write!(f, "{} (line {}, column {})", self.kind, span.line, span.col_start),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
@@ -0,0 +1,50 @@
+use std::ops::Range;
+
+use crate::bytecode::OPTCODE;
+
+use super::{ build_error::{ BuildError, BuildErrorKind }, Block };
+
+impl Block {
+    /// Turns the `Break` and `Continue` left in `body` into jumps to the given indexes.
+    /// Loops nested in the body were built before and have already resolved their own.
+    pub(crate) fn resolve_loop_control(
+        &mut self,
+        body: Range<usize>,
+        break_target: usize,
+        continue_target: usize
+    ) {
+        for index in body {
+            let target = match &self.bytecode[index] {
+                OPTCODE::Break { .. } => break_target,
+                OPTCODE::Continue { .. } => continue_target,
+                _ => {
+                    continue;
+                }
+            };
+            self.bytecode[index] = if target > index {
+                OPTCODE::Jump { steps: target - index - 1 }
+            } else {
+                OPTCODE::JumpBack { steps: index + 1 - target }
+            };
+        }
+    }
+
+    pub(crate) fn contains_break(&self) -> bool {
+        self.bytecode.iter().any(|optcode| matches!(optcode, OPTCODE::Break { .. }))
+    }
+
+    /// Fails on a `Break` or `Continue` that no loop builder has resolved.
+    pub(crate) fn check_loop_control(&self) -> Result<(), BuildError> {
+        for optcode in &self.bytecode {
+            let (kind, span) = match optcode {
+                OPTCODE::Break { span } => (BuildErrorKind::BreakOutsideLoop, span),
+                OPTCODE::Continue { span } => (BuildErrorKind::ContinueOutsideLoop, span),
+                _ => {
+                    continue;
+                }
+            };
+            return Err(BuildError { kind, span: Some(span.clone()) });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+use crate::bytecode::OPTCODE;
+
+use super::Block;
+
+impl Block {
+    /// Pops `number_of_entries` key and value pairs, each pushed key first.
+    pub fn create_map(&mut self, number_of_entries: usize) {
+        self.bytecode.push(OPTCODE::CreateMap { init_entries_count: number_of_entries });
+    }
+    pub fn get_map_value(&mut self) {
+        self.bytecode.push(OPTCODE::GetMapValue);
+    }
+    /// Pops the key and then the value, like `assign_to_array` pops the index first.
+    pub fn set_map_value(&mut self, id: usize) {
+        self.bytecode.push(OPTCODE::SetMapValue { id });
+    }
+    pub fn remove_map_key(&mut self, id: usize) {
+        self.bytecode.push(OPTCODE::RemoveMapKey { id });
+    }
+    pub fn map_contains(&mut self) {
+        self.bytecode.push(OPTCODE::MapContains);
+    }
+    pub fn get_map_keys(&mut self) {
+        self.bytecode.push(OPTCODE::GetMapKeys);
+    }
+}
@@ -1,13 +1,19 @@
 use crate::{ Scope };
 use crate::{ BINOP, OPTCODE };
 mod array;
+mod map;
+mod loop_control;
+pub mod build_error;
+
+/// Variable ids from here up are taken by the counters of simple loops, so frontends must stay below
+pub const FIRST_LOOP_COUNTER: usize = usize::MAX - 0xffff;
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct Block {
     pub bytecode: Vec<OPTCODE>,
     pub scope: Scope,
 }
-#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct TextSpan {
     pub line: usize,
     pub col_start: usize,
@@ -30,6 +36,10 @@ impl Block {
     pub fn load_float(&mut self, value: f64) {
         self.bytecode.push(OPTCODE::LoadFloat { value });
     }
+    /// `value` is a literal like `0.1` or `0,1`, see `parse_decimal`.
+    pub fn load_decimal(&mut self, value: &str) {
+        self.bytecode.push(OPTCODE::LoadDecimal { value: value.to_string() });
+    }
     pub fn create_array(&mut self, number_of_elements: usize) {
         self.bytecode.push(OPTCODE::CreateArray { init_values_count: number_of_elements });
     }
@@ -95,13 +105,36 @@ impl Block {
             self.bytecode.push(optcode);
         }
     }
-    pub fn call_function(&mut self, name: &str) {
+    pub fn call_function(&mut self, id: usize, name: &str) {
         self.bytecode.push(OPTCODE::CallFunction {
+            id,
             name: name.to_string(),
         });
     }
+    /// Repeats `loop_block` as many times as the popped integer.
+    /// The count is kept in a hidden variable that counts down to 0 before every run of the body.
     pub fn define_simple_loop(&mut self, loop_block: Block) {
-        self.bytecode.push(OPTCODE::SimpleLoop { body_block: loop_block });
+        // Counters of simple loops nested in the body were built first and took the ids above
+        let counter = loop_block.bytecode
+            .iter()
+            .filter_map(|optcode| match optcode {
+                OPTCODE::DefineVar { id, .. } if *id >= FIRST_LOOP_COUNTER => Some(*id),
+                _ => None,
+            })
+            .min()
+            .map_or(usize::MAX, |id| id - 1);
+        self.define_variable(counter, String::new(), 0);
+        let mut condition = Block::new(self.scope.clone());
+        condition.load_variable(counter, 0, "");
+        condition.load_int(0);
+        condition.binop(BINOP::LargerThan, 0);
+        let mut body = Block::new(self.scope.clone());
+        body.load_variable(counter, 0, "");
+        body.load_int(1);
+        body.binop(BINOP::Subtract, 0);
+        body.assign_variable(counter);
+        body.add_blocks_bytecode(loop_block);
+        self.define_while_loop(body, condition, 0, 0);
     }
     pub fn define_while_loop(
         &mut self,
@@ -111,6 +144,7 @@ impl Block {
         jmp_target_column: usize
     ) {
         let block_length = loop_block.bytecode.len();
+        let condition_start = self.bytecode.len();
         for optcode in &conditional_block.bytecode {
             self.bytecode.push(optcode.clone());
         }
@@ -120,12 +154,87 @@ impl Block {
             jump_target_line: jmp_target_line,
             is_skipable: true,
         });
+        let body_start = self.bytecode.len();
         for optcode in loop_block.bytecode {
             self.bytecode.push(optcode);
         }
         self.bytecode.push(OPTCODE::JumpBack {
-            steps: block_length + &conditional_block.bytecode.len() + 2,
+            steps: block_length + conditional_block.bytecode.len() + 2,
         });
+        let end = self.bytecode.len();
+        self.resolve_loop_control(
+            body_start..body_start + block_length,
+            end,
+            condition_start
+        );
+    }
+    /// `for id in start..end step step`, with `end` excluded.
+    /// `range_block` pushes the start, the end and the step as integers.
+    pub fn define_range_loop(
+        &mut self,
+        id: usize,
+        var_name: String,
+        node_id: usize,
+        range_block: Block,
+        loop_block: Block
+    ) {
+        self.add_blocks_bytecode(range_block);
+        self.define_iteration(
+            |steps| OPTCODE::RangeNext { steps },
+            3,
+            OPTCODE::DefineVar { id, var_name, node_id },
+            loop_block
+        );
+    }
+    /// `for id in collection`, over the elements of an array or the characters of a string.
+    pub fn define_foreach_loop(
+        &mut self,
+        id: usize,
+        var_name: String,
+        node_id: usize,
+        collection: Block,
+        loop_block: Block
+    ) {
+        self.add_blocks_bytecode(collection);
+        self.load_int(0);
+        self.define_iteration(
+            |steps| OPTCODE::ForEachNext { steps },
+            2,
+            OPTCODE::DefineVar { id, var_name, node_id },
+            loop_block
+        );
+    }
+    /// The loop head pushes the next item for `bind` to take and the body jumps back to it.
+    /// When there are no items left, the head pops the `state_size` values of the loop state
+    /// and jumps past the `JumpBack`.
+    fn define_iteration(
+        &mut self,
+        head: fn(usize) -> OPTCODE,
+        state_size: usize,
+        bind: OPTCODE,
+        loop_block: Block
+    ) {
+        let block_length = loop_block.bytecode.len();
+        let has_break = loop_block.contains_break();
+        let head_index = self.bytecode.len();
+        self.bytecode.push(head(block_length + 2));
+        self.bytecode.push(bind);
+        let body_start = self.bytecode.len();
+        self.add_blocks_bytecode(loop_block);
+        self.bytecode.push(OPTCODE::JumpBack { steps: block_length + 3 });
+        // A break skips the head, so it has to pop the loop state itself
+        let break_target = if has_break {
+            self.bytecode.push(OPTCODE::Jump { steps: 1 });
+            self.bytecode.push(OPTCODE::Pop { count: state_size });
+            self.bytecode.len() - 1
+        } else {
+            self.bytecode.len()
+        };
+        self.resolve_loop_control(
+            body_start..body_start + block_length,
+            break_target,
+            head_index
+        );
     }
     pub fn define_variable(&mut self, id: usize, var_name: String, node_id: usize) {
         self.bytecode.push(OPTCODE::DefineVar {
@@ -137,6 +246,10 @@ impl Block {
     pub fn copy_var_value(&mut self, src_var_id: usize, dst_var_id: usize) {
         self.bytecode.push(OPTCODE::CopyVariableValue { src_var_id, dst_var_id });
     }
+    /// Copies the array, object or map on top of the stack instead of sharing it.
+    pub fn copy_value(&mut self) {
+        self.bytecode.push(OPTCODE::CopyValue);
+    }
     pub fn define_object(&mut self, id: usize) {
         self.bytecode.push(OPTCODE::DefineObject {
             id,
@@ -148,6 +261,9 @@ impl Block {
     pub fn return_from_function(&mut self) {
         self.bytecode.push(OPTCODE::Return);
     }
+    pub fn return_value(&mut self) {
+        self.bytecode.push(OPTCODE::ReturnValue);
+    }
 
     pub fn assign_variable(&mut self, id: usize) {
         self.bytecode.push(OPTCODE::AssignVar { id })
@@ -172,9 +288,9 @@ impl Block {
         self.bytecode.push(OPTCODE::PushToTestingStack { duplicate_stackvalue });
     }
     pub fn break_loop(&mut self, span: TextSpan) {
-        self.bytecode.push(OPTCODE::Break { span: span });
+        self.bytecode.push(OPTCODE::Break { span });
     }
     pub fn continue_loop(&mut self, span: TextSpan) {
-        self.bytecode.push(OPTCODE::Continue { span: span });
+        self.bytecode.push(OPTCODE::Continue { span });
     }
 }
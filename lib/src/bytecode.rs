@@ -1,6 +1,6 @@
-use crate::block::{ Block, TextSpan };
+use crate::block::TextSpan;
 
-#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
 pub enum BINOP {
     Add,
     Subtract,
@@ -19,6 +19,29 @@ pub enum BINOP {
     Not,
 }
 
+impl BINOP {
+    /// How the operator is written in error messages
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BINOP::Add => "+",
+            BINOP::Subtract => "-",
+            BINOP::Multiply => "*",
+            BINOP::Divide => "/",
+            BINOP::Remainder => "%",
+            BINOP::LessThan => "<",
+            BINOP::LargerThan => ">",
+            BINOP::LessOrEq => "<=",
+            BINOP::LargerOrEq => ">=",
+            BINOP::NotEq => "!=",
+            BINOP::Eq => "==",
+            BINOP::And => "and",
+            BINOP::Or => "or",
+            BINOP::Xor => "xor",
+            BINOP::Not => "not",
+        }
+    }
+}
+
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub enum OPTCODE {
     LoadInt {
@@ -33,12 +56,19 @@ pub enum OPTCODE {
     LoadFloat {
         value: f64,
     },
+    /// Pushes a `StackValue::Decimal`. The literal is kept as written, e.g. `"0,1"`,
+    /// since a float would already have lost the exact value.
+    LoadDecimal {
+        value: String,
+    },
     LoadVar {
         id: usize,
         node_id: usize,
         var_name: String
     },
+    /// A call to the function with this `Function::id`, replaced with `JumpToFunction` by linking
     CallFunction {
+        id: usize,
         name: String,
     },
     Add {
@@ -93,9 +123,11 @@ pub enum OPTCODE {
     Jump {
         steps: usize,
     },
+    /// `function` is the index of the called function in the program
     JumpToFunction {
         target: usize,
-        function_name: Option<String>,
+        function: usize,
+        function_name: String,
     },
     JumpBack {
         steps: usize,
@@ -137,8 +169,9 @@ pub enum OPTCODE {
     CallSpecialFunction {
         function: String,
     },
-    SimpleLoop {
-        body_block: Block,
+    CallNativeFunction {
+        index: usize,
+        function_name: String,
     },
     PushToTestingStack {
         duplicate_stackvalue: bool,
@@ -150,9 +183,78 @@ pub enum OPTCODE {
         span: TextSpan,
     },
     Return,
+    /// Returns the value on top of the stack to the caller
+    ReturnValue,
     CopyVariableValue {
         src_var_id: usize,
         dst_var_id: usize,
     },
     Step,
+    /// Head of a range loop, see `VM::range_next`. Jumps `steps` forward when the range is over.
+    RangeNext {
+        steps: usize,
+    },
+    /// Head of a for each loop, see `VM::foreach_next`. Jumps `steps` forward after the last element.
+    ForEachNext {
+        steps: usize,
+    },
+    /// Drops values from the top of the stack
+    Pop {
+        count: usize,
+    },
+    CreateMap {
+        init_entries_count: usize,
+    },
+    GetMapValue,
+    SetMapValue {
+        id: usize,
+    },
+    RemoveMapKey {
+        id: usize,
+    },
+    MapContains,
+    GetMapKeys,
+    /// Replaces the value on top of the stack with a deep copy, see `StackValue::deep_copy`
+    CopyValue,
+    /// Assigns inside the variable, e.g. `matrix[i][j] = x` or `person.address.city = x`.
+    /// See `VM::assign_at_path` for the stack layout.
+    AssignAtPath {
+        id: usize,
+        path: Vec<PathStep>,
+    },
+}
+
+/// A step into an array, object or map on the way to the place that is assigned
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum PathStep {
+    /// An array index or map key, taken from the stack
+    Index,
+    Field {
+        name: String,
+    },
+}
+
+impl OPTCODE {
+    /// The AST node this optcode was generated from, if it carries one.
+    pub fn node_id(&self) -> Option<usize> {
+        match self {
+            OPTCODE::LoadVar { node_id, .. } |
+            OPTCODE::DefineVar { node_id, .. } |
+            OPTCODE::Add { node_id } |
+            OPTCODE::Subtract { node_id } |
+            OPTCODE::Multiply { node_id } |
+            OPTCODE::Divide { node_id } |
+            OPTCODE::Remainder { node_id } |
+            OPTCODE::LessThan { node_id } |
+            OPTCODE::LargerThan { node_id } |
+            OPTCODE::LessOrEq { node_id } |
+            OPTCODE::LargerOrEq { node_id } |
+            OPTCODE::NotEq { node_id } |
+            OPTCODE::Eq { node_id } |
+            OPTCODE::Or { node_id } |
+            OPTCODE::And { node_id } |
+            OPTCODE::Xor { node_id } => Some(*node_id),
+            _ => None,
+        }
+    }
 }
@@ -1,5 +1,80 @@
-use crate::bytecode::OPTCODE;
+//! The JSON form of linked programs, written by `CelsiumProgram::get_linked_bytecode_json`.
 
-pub fn parse_bytecode(bytecode_json: String) -> Vec<OPTCODE>{
-    serde_json::from_str(&bytecode_json).unwrap()
+use std::fmt;
+
+use crate::{ bytecode::OPTCODE, module::FunctionSignature, verifier::VerifyError };
+
+/// Linked bytecode and the table of functions that its `JumpToFunction`s index into
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct BytecodeJson {
+    pub bytecode: Vec<OPTCODE>,
+    pub functions: Vec<FunctionEntry>,
+}
+
+/// A function of the program and where its body is in the linked bytecode
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct FunctionEntry {
+    pub id: usize,
+    pub signature: FunctionSignature,
+    pub offset: usize,
+    pub length: usize,
+}
+
+/// Bytecode without functions
+impl From<Vec<OPTCODE>> for BytecodeJson {
+    fn from(bytecode: Vec<OPTCODE>) -> Self {
+        BytecodeJson { bytecode, functions: vec![] }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadError {
+    /// Not JSON, or JSON that is not bytecode
+    InvalidJson {
+        message: String,
+    },
+    /// A function's bytecode range is outside of the bytecode
+    InvalidFunctionRange {
+        name: String,
+    },
+    /// The program would fail when run
+    InvalidBytecode {
+        errors: Vec<VerifyError>,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::InvalidJson { message } => write!(f, "Invalid bytecode JSON: {}", message),
+            LoadError::InvalidFunctionRange { name } =>
+                write!(f, "Function \"{}\" points outside of the bytecode", name),
+            LoadError::InvalidBytecode { errors } => {
+                write!(f, "Invalid bytecode:")?;
+                for error in errors {
+                    write!(f, "\n{}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<serde_json::Error> for LoadError {
+    fn from(error: serde_json::Error) -> Self {
+        LoadError::InvalidJson { message: error.to_string() }
+    }
+}
+
+/// Also accepts a plain array of optcodes, the format before functions were included.
+/// The result is not verified yet, see `CelsiumProgram::from_bytecode_json`.
+pub fn parse_bytecode(bytecode_json: String) -> Result<BytecodeJson, LoadError> {
+    let json: serde_json::Value = serde_json::from_str(&bytecode_json)?;
+    if json.is_array() {
+        Ok(BytecodeJson::from(serde_json::from_value::<Vec<OPTCODE>>(json)?))
+    } else {
+        Ok(serde_json::from_value(json)?)
+    }
 }
@@ -98,7 +98,7 @@ impl CompileTimeHelper {
                 return Some(object.clone());
             }
         }
-        return None;
+        None
     }
 
     pub fn change_module(&mut self, file_content: String, path: String) {
@@ -128,16 +128,16 @@ impl CompileTimeHelper {
     ) -> usize {
         self.defined_functions.push(CompileTimeFunction {
             id: self.definition_counter,
-            name: name,
-            arguments: arguments,
-            scope: scope,
-            return_type: return_type,
+            name,
+            arguments,
+            scope,
+            return_type,
             is_exported,
         });
         self.definition_counter += 1;
-        return self.definition_counter - 1;
+        self.definition_counter - 1
     }
-    pub fn get_func_return_type(&mut self, id: usize) -> Option<Option<BuiltinTypes>> {
+    pub fn get_func_return_type(&self, id: usize) -> Option<Option<BuiltinTypes>> {
         for func in self.defined_functions.clone() {
             if func.id == id {
                 return Some(func.return_type);
@@ -145,7 +145,7 @@ impl CompileTimeHelper {
         }
         None
     }
-    pub fn get_func_args(&mut self, id: usize) -> Option<Vec<FuncArg>> {
+    pub fn get_func_args(&self, id: usize) -> Option<Vec<FuncArg>> {
         for func in self.defined_functions.clone() {
             if func.id == id {
                 return Some(func.arguments);
@@ -189,7 +189,7 @@ impl CompileTimeHelper {
         }
         self.defined_variables.push(to_be_defined);
         self.definition_counter += 1;
-        return Some(self.definition_counter - 1);
+        Some(self.definition_counter - 1)
     }
     pub fn def_object(
         &mut self,
@@ -199,7 +199,7 @@ impl CompileTimeHelper {
         fields: Vec<ObjectFieldType>
     ) -> Option<usize> {
         let object: CompileTimeObject = CompileTimeObject {
-            data_type: BuiltinTypes::Object { fields: fields },
+            data_type: BuiltinTypes::Object { fields },
             name,
             id: self.definition_counter,
             scope: scope.clone(),
@@ -227,7 +227,7 @@ impl CompileTimeHelper {
         }
         self.defined_objects.push(object);
         self.definition_counter += 1;
-        return Some(self.definition_counter - 1);
+        Some(self.definition_counter - 1)
     }
     pub fn get_object_if_exists(&mut self, name: &str) -> Option<CompileTimeObject> {
         for object in &self.defined_objects {
@@ -237,7 +237,7 @@ impl CompileTimeHelper {
         }
         None
     }
-    pub fn get_var_type(&mut self, var_id: usize) -> Option<BuiltinTypes> {
+    pub fn get_var_type(&self, var_id: usize) -> Option<BuiltinTypes> {
         for var in self.defined_variables.clone() {
             if var.id == var_id {
                 return Some(var.data_type);
@@ -277,7 +277,7 @@ impl CompileTimeHelper {
 
         self.definition_counter += 1;
 
-        return self.definition_counter - 1;
+        self.definition_counter - 1
     }
 
     pub fn get_array_type_and_length(&mut self, id: usize) -> Option<(BuiltinTypes, usize)> {
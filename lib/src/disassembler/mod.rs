@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use crate::{ bytecode::{ PathStep, OPTCODE }, CelsiumProgram };
+
+/// Prints the linked program as an indexed listing, one optcode per line.
+/// Function entry points are labeled and jumps show the index they land on.
+pub fn disassemble(program: &CelsiumProgram) -> String {
+    let mut entry_points = HashMap::new();
+    for (function, offset) in program.functions.iter().zip(&program.function_offsets) {
+        entry_points.insert(*offset, function.signature.name.clone());
+    }
+    write_listing(&program.main_block.bytecode, &entry_points)
+}
+
+/// Disassembles bytecode that is not part of a program, e.g. parsed from JSON.
+pub fn disassemble_bytecode(bytecode: &[OPTCODE]) -> String {
+    write_listing(bytecode, &HashMap::new())
+}
+
+fn write_listing(bytecode: &[OPTCODE], entry_points: &HashMap<usize, String>) -> String {
+    let mut listing = String::new();
+    for (index, optcode) in bytecode.iter().enumerate() {
+        if let Some(name) = entry_points.get(&index) {
+            listing.push('\n');
+            listing.push_str(&format!("{}:\n", name));
+        }
+        listing.push_str(&format!("{:04}  {}\n", index, format_optcode(optcode, index)));
+    }
+    listing
+}
+
+/// Formats a single optcode at `index` without the index column.
+pub fn format_optcode(optcode: &OPTCODE, index: usize) -> String {
+    // Saturates, so bytecode that was not verified still prints
+    let forward = |steps: &usize| index.saturating_add(*steps).saturating_add(1);
+    match optcode {
+        OPTCODE::LoadInt { value } => format!("LoadInt {}", value),
+        OPTCODE::LoadBool { value } => format!("LoadBool {}", value),
+        OPTCODE::LoadString { value } => format!("LoadString {:?}", value),
+        OPTCODE::LoadFloat { value } => format!("LoadFloat {:?}", value),
+        OPTCODE::LoadDecimal { value } => format!("LoadDecimal {}", value),
+        OPTCODE::LoadVar { id, node_id: _, var_name } => format!("LoadVar {} (#{})", var_name, id),
+        OPTCODE::DefineVar { id, var_name, node_id: _ } =>
+            format!("DefineVar {} (#{})", var_name, id),
+        OPTCODE::CallFunction { id, name } => format!("CallFunction {} (#{})", name, id),
+        OPTCODE::JumpIfFalse { steps, .. } =>
+            format!("JumpIfFalse +{} -> {:04}", steps, forward(steps)),
+        OPTCODE::Jump { steps } => format!("Jump +{} -> {:04}", steps, forward(steps)),
+        OPTCODE::RangeNext { steps } =>
+            format!("RangeNext +{} -> {:04}", steps, forward(steps)),
+        OPTCODE::ForEachNext { steps } =>
+            format!("ForEachNext +{} -> {:04}", steps, forward(steps)),
+        OPTCODE::JumpBack { steps } =>
+            format!("JumpBack -{} -> {:04}", steps, (index + 1).saturating_sub(*steps)),
+        OPTCODE::JumpToFunction { target, function: _, function_name } =>
+            format!("JumpToFunction {} -> {:04}", function_name, target.saturating_add(1)),
+        OPTCODE::DefineObject { id } => format!("DefineObject #{}", id),
+        OPTCODE::CreateObject { field_names } =>
+            format!("CreateObject {{{}}}", field_names.join(", ")),
+        OPTCODE::GetObjectField { field_name } => format!("GetObjectField {}", field_name),
+        OPTCODE::SetObjectField { id, field_name } =>
+            format!("SetObjectField #{} {}", id, field_name),
+        OPTCODE::CreateArray { init_values_count } => format!("CreateArray {}", init_values_count),
+        OPTCODE::AssignAtArrayIndex { id } => format!("AssignAtArrayIndex #{}", id),
+        OPTCODE::PushToArray { id } => format!("PushToArray #{}", id),
+        OPTCODE::GettArrayLength { id } => format!("GettArrayLength #{}", id),
+        OPTCODE::AssignVar { id } => format!("AssignVar #{}", id),
+        OPTCODE::CallSpecialFunction { function } => format!("CallSpecialFunction {}", function),
+        OPTCODE::CallNativeFunction { index, function_name } =>
+            format!("CallNativeFunction {} (#{})", function_name, index),
+        OPTCODE::PushToTestingStack { duplicate_stackvalue } => {
+            if *duplicate_stackvalue {
+                "PushToTestingStack duplicate".to_string()
+            } else {
+                "PushToTestingStack".to_string()
+            }
+        }
+        OPTCODE::Break { span } => format!("Break (line {}, column {})", span.line, span.col_start),
+        OPTCODE::Continue { span } =>
+            format!("Continue (line {}, column {})", span.line, span.col_start),
+        OPTCODE::CopyVariableValue { src_var_id, dst_var_id } =>
+            format!("CopyVariableValue #{} -> #{}", src_var_id, dst_var_id),
+        OPTCODE::Add { .. } => "Add".to_string(),
+        OPTCODE::Subtract { .. } => "Subtract".to_string(),
+        OPTCODE::Multiply { .. } => "Multiply".to_string(),
+        OPTCODE::Divide { .. } => "Divide".to_string(),
+        OPTCODE::Remainder { .. } => "Remainder".to_string(),
+        OPTCODE::LessThan { .. } => "LessThan".to_string(),
+        OPTCODE::LargerThan { .. } => "LargerThan".to_string(),
+        OPTCODE::LessOrEq { .. } => "LessOrEq".to_string(),
+        OPTCODE::LargerOrEq { .. } => "LargerOrEq".to_string(),
+        OPTCODE::NotEq { .. } => "NotEq".to_string(),
+        OPTCODE::Eq { .. } => "Eq".to_string(),
+        OPTCODE::Or { .. } => "Or".to_string(),
+        OPTCODE::And { .. } => "And".to_string(),
+        OPTCODE::Xor { .. } => "Xor".to_string(),
+        OPTCODE::Not => "Not".to_string(),
+        OPTCODE::GetIndex => "GetIndex".to_string(),
+        OPTCODE::Return => "Return".to_string(),
+        OPTCODE::ReturnValue => "ReturnValue".to_string(),
+        OPTCODE::Step => "Step".to_string(),
+        OPTCODE::Pop { count } => format!("Pop {}", count),
+        OPTCODE::CreateMap { init_entries_count } => format!("CreateMap {}", init_entries_count),
+        OPTCODE::GetMapValue => "GetMapValue".to_string(),
+        OPTCODE::SetMapValue { id } => format!("SetMapValue #{}", id),
+        OPTCODE::RemoveMapKey { id } => format!("RemoveMapKey #{}", id),
+        OPTCODE::MapContains => "MapContains".to_string(),
+        OPTCODE::GetMapKeys => "GetMapKeys".to_string(),
+        OPTCODE::CopyValue => "CopyValue".to_string(),
+        OPTCODE::AssignAtPath { id, path } => {
+            let steps: Vec<String> = path
+                .iter()
+                .map(|step| {
+                    match step {
+                        PathStep::Index => "[]".to_string(),
+                        PathStep::Field { name } => format!(".{}", name),
+                    }
+                })
+                .collect();
+            format!("AssignAtPath #{} {}", id, steps.join(" "))
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::{ BTreeSet, HashMap };
+
+use crate::{
+    bytecode::OPTCODE,
+    vm::{ runtime_error::RuntimeError, vm::{ CallStackItem, Variable, VM }, StackValue },
+    CelsiumProgram,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepResult {
+    Paused,
+    Finished,
+}
+
+/// Why `run_until_breakpoint` returned. The optcode at `index` has not run yet.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    Breakpoint {
+        index: usize,
+    },
+    LineBreakpoint {
+        line: usize,
+        index: usize,
+    },
+    Finished,
+}
+
+/// A program that runs one optcode at a time and can be inspected in between.
+pub struct Execution {
+    program: CelsiumProgram,
+    vm: VM,
+    bytecode: Vec<OPTCODE>,
+    index: usize,
+    finished: bool,
+    breakpoints: BTreeSet<usize>,
+    line_breakpoints: BTreeSet<usize>,
+    lines_by_node_id: HashMap<usize, usize>,
+    /// Line of the last executed optcode that has one, so a line breakpoint
+    /// only stops when the line is entered and not before each of its optcodes
+    current_line: Option<usize>,
+}
+
+impl Execution {
+    pub fn new(mut program: CelsiumProgram) -> Execution {
+        program.link_native_functions();
+        let vm = program.create_vm();
+        let bytecode = program.main_block.bytecode.clone();
+        let mut lines_by_node_id = HashMap::new();
+        for (line, node_ids) in &program.node_ids_by_line {
+            for node_id in node_ids {
+                lines_by_node_id.insert(*node_id, *line);
+            }
+        }
+        Execution {
+            finished: bytecode.is_empty(),
+            program,
+            vm,
+            bytecode,
+            index: 0,
+            breakpoints: BTreeSet::new(),
+            line_breakpoints: BTreeSet::new(),
+            lines_by_node_id,
+            current_line: None,
+        }
+    }
+
+    /// Runs the next optcode. Stepping a finished execution does nothing.
+    pub fn step(&mut self) -> Result<StepResult, RuntimeError> {
+        if self.finished {
+            return Ok(StepResult::Finished);
+        }
+        if let Some(line) = self.line_at(self.index) {
+            self.current_line = Some(line);
+        }
+        let keep_running = match self.program.step(&mut self.vm, &self.bytecode, &mut self.index) {
+            Ok(keep_running) => keep_running,
+            Err(error) => {
+                self.finished = true;
+                return Err(error);
+            }
+        };
+        if !keep_running || self.index >= self.bytecode.len() {
+            self.finished = true;
+            return Ok(StepResult::Finished);
+        }
+        Ok(StepResult::Paused)
+    }
+
+    /// Runs at least one optcode and then until the next breakpoint or the end of the program.
+    pub fn run_until_breakpoint(&mut self) -> Result<StopReason, RuntimeError> {
+        loop {
+            if self.step()? == StepResult::Finished {
+                return Ok(StopReason::Finished);
+            }
+            if self.breakpoints.contains(&self.index) {
+                return Ok(StopReason::Breakpoint { index: self.index });
+            }
+            if let Some(line) = self.line_at(self.index) {
+                if self.line_breakpoints.contains(&line) && self.current_line != Some(line) {
+                    return Ok(StopReason::LineBreakpoint { line, index: self.index });
+                }
+            }
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, index: usize) {
+        self.breakpoints.insert(index);
+    }
+    pub fn remove_breakpoint(&mut self, index: usize) {
+        self.breakpoints.remove(&index);
+    }
+    /// Stops before the first optcode of the line every time the line is entered.
+    /// Returns `false` if no optcode belongs to the line, so the breakpoint would never hit.
+    pub fn add_line_breakpoint(&mut self, line: usize) -> bool {
+        self.line_breakpoints.insert(line);
+        (0..self.bytecode.len()).any(|index| self.line_at(index) == Some(line))
+    }
+    pub fn remove_line_breakpoint(&mut self, line: usize) {
+        self.line_breakpoints.remove(&line);
+    }
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+        self.line_breakpoints.clear();
+    }
+
+    /// Index of the optcode that runs next.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+    pub fn current_optcode(&self) -> Option<&OPTCODE> {
+        if self.finished { None } else { self.bytecode.get(self.index) }
+    }
+    /// Source line of the optcode that runs next, if it carries a node.
+    pub fn current_line(&self) -> Option<usize> {
+        if self.finished { None } else { self.line_at(self.index) }
+    }
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Operand stack from the bottom to the top.
+    pub fn stack(&self) -> Vec<&StackValue> {
+        self.vm.stack().collect()
+    }
+    /// Variables visible to the optcode that runs next: locals of the innermost call and globals.
+    pub fn variables(&self) -> Vec<&Variable> {
+        let locals = self.vm.call_stack().last().map(|frame| &frame.locals);
+        let mut variables: Vec<&Variable> = locals
+            .map(|locals| locals.values().collect())
+            .unwrap_or_default();
+        let is_shadowed = |id: &usize| locals.is_some_and(|locals| locals.contains_key(id));
+        variables.extend(self.vm.globals().filter(|global| !is_shadowed(&global.id)));
+        variables.sort_by_key(|variable| variable.id);
+        variables
+    }
+    pub fn call_stack(&self) -> Vec<&CallStackItem> {
+        self.vm.call_stack().collect()
+    }
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+    pub fn program(&self) -> &CelsiumProgram {
+        &self.program
+    }
+
+    fn line_at(&self, index: usize) -> Option<usize> {
+        let node_id = self.bytecode.get(index)?.node_id()?;
+        self.lines_by_node_id.get(&node_id).copied()
+    }
+}
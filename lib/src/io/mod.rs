@@ -0,0 +1,95 @@
+use std::{ cell::RefCell, collections::VecDeque, fmt, io::{ self, BufRead, Write }, rc::Rc };
+
+#[cfg(target_family = "wasm")]
+use futures::executor::block_on;
+#[cfg(target_family = "wasm")]
+use wasm_bindgen::{ JsValue, prelude::wasm_bindgen };
+
+#[cfg(target_family = "wasm")]
+#[wasm_bindgen]
+extern "C" {
+    fn wasm_print(s: &str);
+    async fn wasm_input() -> JsValue;
+}
+
+/// Where `izvade` writes to and `ievade` reads from.
+pub trait IoHost: fmt::Debug {
+    fn write(&mut self, text: &str);
+    /// Reads a line without the line ending. `None` when there is no more input.
+    fn read_line(&mut self) -> Option<String>;
+    fn flush(&mut self) {}
+}
+
+/// The VM and the embedder share the host, so output can be read while or after the program runs.
+pub type SharedIo = Rc<RefCell<dyn IoHost>>;
+
+/// The host used when nothing else is set: stdio natively, the page in the browser.
+pub fn default_io() -> SharedIo {
+    #[cfg(target_family = "wasm")]
+    return Rc::new(RefCell::new(WasmHost));
+    #[cfg(not(target_family = "wasm"))]
+    Rc::new(RefCell::new(StdioHost))
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdioHost;
+
+impl IoHost for StdioHost {
+    fn write(&mut self, text: &str) {
+        print!("{}", text);
+    }
+    fn read_line(&mut self) -> Option<String> {
+        let line = io::stdin().lock().lines().next()?.ok()?;
+        Some(line.trim_end().to_owned())
+    }
+    fn flush(&mut self) {
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Calls `wasm_print` and `wasm_input` provided by the page.
+#[cfg(target_family = "wasm")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WasmHost;
+
+#[cfg(target_family = "wasm")]
+impl IoHost for WasmHost {
+    fn write(&mut self, text: &str) {
+        wasm_print(text);
+    }
+    fn read_line(&mut self) -> Option<String> {
+        block_on(async { wasm_input().await.as_string() })
+    }
+}
+
+/// Scripted input and captured output, for tests and graders.
+#[derive(Debug, Clone, Default)]
+pub struct BufferIo {
+    pub input: VecDeque<String>,
+    pub output: String,
+}
+
+impl BufferIo {
+    pub fn new<I, S>(input: I) -> BufferIo where I: IntoIterator<Item = S>, S: Into<String> {
+        BufferIo {
+            input: input.into_iter().map(Into::into).collect(),
+            output: String::new(),
+        }
+    }
+    /// Wraps the buffer so it can be given to the VM while keeping a handle to it.
+    pub fn shared(self) -> Rc<RefCell<BufferIo>> {
+        Rc::new(RefCell::new(self))
+    }
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.output)
+    }
+}
+
+impl IoHost for BufferIo {
+    fn write(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+    fn read_line(&mut self) -> Option<String> {
+        self.input.pop_front()
+    }
+}
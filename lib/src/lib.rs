@@ -8,7 +8,6 @@ use bytecode::{ BINOP, OPTCODE };
 use module::Function;
 extern crate serde;
 extern crate serde_json;
-use rand::prelude::*;
 
 extern crate js_sys;
 pub mod compiletime_helper;
@@ -16,42 +15,44 @@ pub mod block;
 pub mod module;
 pub mod vm;
 pub mod typestack;
-use vm::vm::Variable;
+pub mod native;
+pub mod disassembler;
+pub mod assembler;
+pub mod binary;
+pub mod verifier;
+pub mod typecheck;
+pub mod operators;
+pub mod io;
+pub mod execution;
+pub mod observer;
+pub mod locale;
+mod linker;
+use vm::limits::VmLimits;
+use vm::runtime_error::RuntimeError;
 use vm::vm::VM;
 use vm::ObjectField;
 use vm::StackValue;
+use vm::decimal::parse_decimal;
+#[cfg(target_family = "wasm")]
 use wasm_bindgen::prelude::*;
 
 use crate::block::TextSpan;
-use crate::vm::vm::CallStackItem;
-use crate::std::*;
-
-/// Macro to generate a match statement that calls functions based on string names
-macro_rules! call_special_function {
-    ($function:expr, $vm:expr, $(($name:literal => $func:ident)),* $(,)?) => {
-        match $function.as_str() {
-            $(
-                $name => $func($vm),
-            )*
-            _ => unreachable!("Unknown special function: {}", $function),
-        }
-    };
-}
-
-// When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
-// allocator.
-#[cfg(feature = "wee_alloc")]
-#[global_allocator]
-static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
+use crate::block::build_error::BuildError;
+use crate::vm::runtime_error::RuntimeErrorKind;
+use crate::module::FunctionSignature;
+use crate::native::NativeRegistry;
+use crate::verifier::VerifyError;
+use crate::bytecode_parser::{ BytecodeJson, FunctionEntry, LoadError };
+use crate::io::SharedIo;
+use crate::execution::Execution;
+use crate::observer::{ ExecutionEvent, SharedObserver };
+use crate::locale::Locale;
 
 #[cfg(target_family = "wasm")]
 #[wasm_bindgen]
 extern "C" {
     fn alert(s: &str);
     fn wasm_print(s: &str);
-    fn code_replace(replace_with: &str, line: usize, column: usize, span: usize);
-    fn step();
-    fn explain(a: &str, line: usize, column: usize, span: usize);
     async fn wasm_input() -> JsValue;
 }
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize, serde::Serialize)]
@@ -62,6 +63,7 @@ pub struct ObjectFieldType {
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize, serde::Serialize)]
 pub enum BuiltinTypes {
+    /// Also the type of `StackValue::BigInt`, which an `Int` becomes when it overflows
     Int,
     Bool,
     String,
@@ -73,6 +75,12 @@ pub enum BuiltinTypes {
         length: Option<usize>,
     },
     Float,
+    Map {
+        key: Box<BuiltinTypes>,
+        value: Box<BuiltinTypes>,
+    },
+    /// An exact fraction, see `StackValue::Decimal`
+    Decimal,
 }
 
 #[derive(Debug, Clone)]
@@ -82,6 +90,15 @@ pub struct CelsiumProgram {
     node_locations_by_id: HashMap<usize, TextSpan>,
     node_ids_by_line: HashMap<usize, Vec<usize>>,
     node_parents: HashMap<usize, Option<usize>>,
+    natives: NativeRegistry,
+    /// Index of the first optcode of each function in `functions`
+    function_offsets: Vec<usize>,
+    limits: VmLimits,
+    /// Replaces the default stdio/browser I/O of `run_program`
+    io: Option<SharedIo>,
+    /// Receive the explanations and code replacements of the execution, see `observer`
+    observers: Vec<SharedObserver>,
+    locale: Locale,
 }
 
 #[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
@@ -94,76 +111,216 @@ impl Scope {
     pub fn change_ast_id(&mut self, new_id: usize) -> Scope {
         let mut new = self.clone();
         new.ast_id = new_id;
-        return new;
+        new
     }
 }
 
 impl CelsiumProgram {
+    /// Links the functions with the main block by their ids. Fails on a `Break` or `Continue`
+    /// outside of a loop and on calls to functions that are not in `functions`.
     pub fn new(
         main_block: Block,
         functions: Vec<Function>,
         node_locations_by_id: HashMap<usize, TextSpan>,
         node_ids_by_line: HashMap<usize, Vec<usize>>,
         node_parents: HashMap<usize, Option<usize>>
-    ) -> CelsiumProgram {
-        let mut bytecode = main_block.bytecode.clone();
-        bytecode.push(OPTCODE::Return); // Return from the main function
+    ) -> Result<CelsiumProgram, BuildError> {
+        main_block.check_loop_control()?;
         for function in &functions {
-            let bytecode_index_of_this_function = &mut bytecode.len();
-            bytecode.extend(function.body.bytecode.clone());
-            bytecode.push(OPTCODE::Return); // Return from the user defined function
-            let mut i = 0;
-            while i < bytecode.len() {
-                match &bytecode[i] {
-                    OPTCODE::CallFunction { name } => {
-                        if name == &function.signature.name {
-                            bytecode[i] = OPTCODE::JumpToFunction {
-                                target: *bytecode_index_of_this_function - 1,
-                                function_name: Some(name.to_string()),
-                            };
-                        }
-                    }
-                    _ => (),
-                }
-                i += 1;
-            }
+            function.body.check_loop_control()?;
         }
+        let linker::Linked { bytecode, function_offsets } = linker::link(&main_block, &functions)?;
         let mut modified_block = main_block.clone();
         modified_block.bytecode = bytecode;
-        CelsiumProgram {
+        Ok(CelsiumProgram {
             main_block: modified_block,
             functions,
             node_ids_by_line,
             node_locations_by_id,
             node_parents,
+            natives: NativeRegistry::new(),
+            limits: VmLimits::default(),
+            io: None,
+            observers: observer::default_observer().into_iter().collect(),
+            locale: Locale::default(),
+            function_offsets,
+        })
+    }
+
+    /// Readable listing of the linked bytecode, see `disassembler::disassemble`.
+    pub fn disassemble(&self) -> String {
+        disassembler::disassemble(self)
+    }
+
+    /// Registers a host function callable with `CallSpecialFunction` under the signature's name.
+    pub fn register_native_function<F>(&mut self, signature: FunctionSignature, function: F) -> usize
+        where F: Fn(&mut VM, Vec<StackValue>) -> Result<StackValue, RuntimeError> + 'static
+    {
+        self.natives.register(signature, function)
+    }
+
+    /// Limits for the VMs created by `run_program`. Programs are unlimited by default.
+    pub fn set_limits(&mut self, limits: VmLimits) {
+        self.limits = limits;
+    }
+
+    /// Sets where `izvade` writes and `ievade` reads, e.g. a shared `io::BufferIo`.
+    pub fn set_io(&mut self, io: SharedIo) {
+        self.io = Some(io);
+    }
+
+    /// Adds an observer of the execution events, e.g. an `observer::ExplanationLog`.
+    pub fn add_observer(&mut self, observer: SharedObserver) {
+        self.observers.push(observer);
+    }
+
+    /// Language of the explanations and of the values printed by `izvade`. Latvian by default.
+    pub fn set_locale(&mut self, locale: Locale) {
+        self.locale = locale;
+    }
+
+    pub fn locale(&self) -> Locale {
+        self.locale
+    }
+
+    /// Removes all observers, including the default one of the browser build.
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    pub fn set_native_registry(&mut self, natives: NativeRegistry) {
+        self.natives = natives;
+    }
+
+    /// Signatures of the std functions and every registered host function.
+    pub fn get_native_functions(&self) -> Vec<FunctionSignature> {
+        self.natives.signatures()
+    }
+
+    /// Replaces calls by name with calls by registry index.
+    /// Unknown names are left as they are and fail when executed.
+    fn link_native_functions(&mut self) {
+        for optcode in self.main_block.bytecode.iter_mut() {
+            // Already linked calls are resolved again, the program could come
+            // from `from_bytes` and be run with a different registry
+            let function = match optcode {
+                OPTCODE::CallSpecialFunction { function } => function,
+                OPTCODE::CallNativeFunction { function_name, .. } => function_name,
+                _ => {
+                    continue;
+                }
+            };
+            if let Some(index) = self.natives.resolve(function) {
+                *optcode = OPTCODE::CallNativeFunction {
+                    index,
+                    function_name: function.clone(),
+                };
+            }
         }
     }
 
     pub fn stackvalue_type_to_str(value: StackValue) -> String {
-        let var_type = match &value {
-            StackValue::Bool { value: _ } => "būls",
-            StackValue::Int { value: _ } => "vesels skaitlis",
-            StackValue::Float { value: _ } => "decimālskaitlis",
-            StackValue::String { value: _ } => "teksts",
-            StackValue::Array { value: _ } => "saraksts",
-            StackValue::Object { value: _ } => "objekts",
-        };
-        return var_type.to_string();
+        Locale::default().type_name(&value).to_string()
     }
 
-    pub fn run_program(&mut self) -> Vec<StackValue> {
+    pub fn run_program(&mut self) -> Result<Vec<StackValue>, RuntimeError> {
+        self.link_native_functions();
         let global_bytecode: Vec<OPTCODE> = self.main_block.bytecode.clone();
-        let mut vm = VM::new();
+        let mut vm = self.create_vm();
+
+        self.run(&mut vm, &global_bytecode)?;
 
-        self.run(&mut vm, &global_bytecode);
+        Ok(vm.testing_stack)
+    }
+
+    /// Starts a paused execution of the program for stepping through it, see `execution::Execution`.
+    pub fn start_execution(self) -> Execution {
+        Execution::new(self)
+    }
 
-        return vm.testing_stack;
+    /// A VM with the limits and I/O host set on the program.
+    fn create_vm(&self) -> VM {
+        let mut vm = VM::with_limits(self.limits.clone());
+        vm.set_locale(self.locale);
+        if let Some(io) = &self.io {
+            vm.set_io(io.clone());
+        }
+        vm
     }
 
+    /// The linked bytecode alone as a JSON array of optcodes.
     pub fn get_bytecode_json(self) -> String {
         let global_bytecode: Vec<OPTCODE> = self.main_block.bytecode.clone();
-        let json_bytecode = serde_json::to_string(&global_bytecode).unwrap();
-        json_bytecode
+        serde_json::to_string(&global_bytecode).unwrap()
+    }
+
+    /// The linked bytecode and the function table as JSON, see `bytecode_parser::BytecodeJson`.
+    /// Unlike `get_bytecode_json` this keeps the function signatures, so calls can be checked when loading.
+    pub fn get_linked_bytecode_json(self) -> String {
+        let functions = self.functions
+            .iter()
+            .zip(&self.function_offsets)
+            .map(|(function, offset)| FunctionEntry {
+                id: function.id,
+                signature: function.signature.clone(),
+                offset: *offset,
+                length: function.body.bytecode.len(),
+            })
+            .collect();
+        let json = BytecodeJson { bytecode: self.main_block.bytecode, functions };
+        serde_json::to_string(&json).unwrap()
+    }
+
+    /// Serializes the linked program into the binary format, see `binary`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        binary::to_bytes(self)
+    }
+
+    /// Wraps bytecode that was linked elsewhere, e.g. parsed with `bytecode_parser::parse_bytecode`.
+    /// The bytecode is verified first, since it does not come from our own `Block` builders.
+    /// Bytecode without functions converts with `Vec<OPTCODE>::into`.
+    pub fn from_bytecode(program: BytecodeJson) -> Result<CelsiumProgram, LoadError> {
+        let BytecodeJson { bytecode, functions } = program;
+        let mut main_block = Block::new(Scope { ast_id: 0, module_path: String::new() });
+        let (functions, function_offsets) = linker
+            ::split_functions(&bytecode, functions, &main_block.scope)
+            .map_err(|name| LoadError::InvalidFunctionRange { name })?;
+        main_block.bytecode = bytecode;
+        let program = CelsiumProgram {
+            main_block,
+            functions,
+            node_locations_by_id: HashMap::new(),
+            node_ids_by_line: HashMap::new(),
+            node_parents: HashMap::new(),
+            natives: NativeRegistry::new(),
+            limits: VmLimits::default(),
+            io: None,
+            observers: observer::default_observer().into_iter().collect(),
+            locale: Locale::default(),
+            function_offsets,
+        };
+        program.verify().map_err(|errors| LoadError::InvalidBytecode { errors })?;
+        Ok(program)
+    }
+
+    /// Parses and verifies JSON from `get_linked_bytecode_json` or `get_bytecode_json`,
+    /// which may come from anywhere.
+    pub fn from_bytecode_json(bytecode_json: String) -> Result<CelsiumProgram, LoadError> {
+        CelsiumProgram::from_bytecode(bytecode_parser::parse_bytecode(bytecode_json)?)
+    }
+
+    /// Checks the linked bytecode for jumps out of bounds, stack underflows and undefined variables.
+    pub fn verify(&self) -> Result<(), Vec<VerifyError>> {
+        let signatures: Vec<FunctionSignature> = self.functions
+            .iter()
+            .map(|function| function.signature.clone())
+            .collect();
+        verifier::verify_with(&self.main_block.bytecode, &signatures, &self.natives)
+    }
+
+    /// Loads a program saved with `to_bytes`. Host functions have to be registered again.
+    pub fn from_bytes(bytes: &[u8]) -> Result<CelsiumProgram, binary::DecodeError> {
+        binary::from_bytes(bytes)
     }
 
     pub fn get_bytecode(&self) -> Vec<OPTCODE> {
@@ -171,17 +328,31 @@ impl CelsiumProgram {
     }
 
     fn code_replace_calculate(&mut self, node_id: &usize, new_value: String) {
-        let binding = self.clone();
-        let span = binding.node_locations_by_id.get(node_id).unwrap();
+        let span = match self.node_locations_by_id.get(node_id) {
+            Some(span) => span.clone(),
+            None => {
+                return;
+            }
+        };
         let delta_span: isize = (new_value.len() as isize) - (span.length as isize);
 
-        #[cfg(target_family = "wasm")]
-        code_replace(&new_value, span.line, span.col_start, span.length);
+        self.notify(&(ExecutionEvent::CodeReplaced { replacement: &new_value, span: span.clone() }));
 
         let current_line = span.line;
         self.change_span_of_parent(node_id, current_line, delta_span);
-        for node_in_the_same_line in self.node_ids_by_line.get(&current_line).unwrap() {
-            let node_position = self.node_locations_by_id.get_mut(node_in_the_same_line).unwrap();
+        let nodes_in_the_same_line = match self.node_ids_by_line.get(&current_line) {
+            Some(nodes) => nodes.clone(),
+            None => {
+                return;
+            }
+        };
+        for node_in_the_same_line in nodes_in_the_same_line {
+            let node_position = match self.node_locations_by_id.get_mut(&node_in_the_same_line) {
+                Some(position) => position,
+                None => {
+                    continue;
+                }
+            };
             if span.col_start + span.length < node_position.col_start {
                 if delta_span >= 0 {
                     node_position.col_start += delta_span as usize;
@@ -192,387 +363,284 @@ impl CelsiumProgram {
         }
     }
     fn change_span_of_parent(&mut self, node_id: &usize, line: usize, delta_span: isize) {
-        let parrent_node = self.node_parents.get(node_id).unwrap();
-        if parrent_node.is_some() {
-            let parrent_id = parrent_node.unwrap();
-            let parent_line = self.node_locations_by_id.get(&parrent_id).unwrap().line;
-            if line == parent_line {
-                let parrent_location = self.node_locations_by_id.get_mut(&parrent_id).unwrap();
-                if delta_span >= 0 {
-                    parrent_location.length += delta_span as usize;
-                } else {
-                    if -delta_span >= (parrent_location.length as isize) {
-                        parrent_location.length = 0;
-                    } else {
-                        parrent_location.length -= -delta_span as usize;
-                    }
-                }
+        let parrent_id = match self.node_parents.get(node_id) {
+            Some(Some(parrent_id)) => *parrent_id,
+            _ => {
+                return;
+            }
+        };
+        let parrent_location = match self.node_locations_by_id.get_mut(&parrent_id) {
+            Some(location) => location,
+            None => {
+                return;
             }
-            self.change_span_of_parent(&parrent_node.unwrap(), line, delta_span);
+        };
+        if line == parrent_location.line {
+            if delta_span >= 0 {
+                parrent_location.length += delta_span as usize;
+            } else if -delta_span >= (parrent_location.length as isize) {
+                parrent_location.length = 0;
+            } else {
+                parrent_location.length -= -delta_span as usize;
+            }
+        }
+        self.change_span_of_parent(&parrent_id, line, delta_span);
+    }
+    fn notify(&self, event: &ExecutionEvent) {
+        for observer in &self.observers {
+            observer.borrow_mut().on_event(event, self.locale);
         }
     }
-    fn explain_process(&mut self, explanation: String, node_id: &usize) {
-        let span = self.node_locations_by_id.get(node_id).unwrap();
-        #[cfg(target_family = "wasm")]
-        explain(&explanation, span.line, span.col_start, span.length);
+
+    fn binary_operation(
+        &mut self,
+        vm: &mut VM,
+        operator: BINOP,
+        node_id: usize
+    ) -> Result<(), RuntimeError> {
+        if operator == BINOP::Not {
+            unreachable!("Not is a unary operation");
+        }
+        let (left, right, result) = vm.aritmethics(operator.symbol())?;
+        self.notify(
+            &(ExecutionEvent::BinaryOperation {
+                operator,
+                left: &left,
+                right: &right,
+                result: &result,
+                node_id,
+                span: self.node_locations_by_id.get(&node_id).cloned(),
+            })
+        );
+        let replace_value = self.locale.format_value(&result);
+        self.code_replace_calculate(&node_id, replace_value);
+        self.notify(&ExecutionEvent::Step);
+        Ok(())
+    }
+
+    fn locate_error(&self, error: RuntimeError, index: usize, optcode: &OPTCODE) -> RuntimeError {
+        let node_id = optcode.node_id();
+        let span = match optcode {
+            OPTCODE::Break { span } | OPTCODE::Continue { span } => Some(span.clone()),
+            _ => node_id.and_then(|id| self.node_locations_by_id.get(&id).cloned()),
+        };
+        error.locate(index, node_id, span)
     }
 
-    fn run(&mut self, vm: &mut VM, bytecode: &Vec<OPTCODE>) {
+    fn run(&mut self, vm: &mut VM, bytecode: &[OPTCODE]) -> Result<(), RuntimeError> {
         let mut index: usize = 0;
 
         while index < bytecode.len() {
-            let optcode = &bytecode[index];
-            let _ = match optcode {
-                OPTCODE::Step => {
-                    #[cfg(target_family = "wasm")]
-                    step();
-                }
-                OPTCODE::PushToTestingStack { duplicate_stackvalue } =>
-                    vm.push_to_testing_stack(*duplicate_stackvalue),
-                OPTCODE::CallFunction { name } => {
-                    vm.call_function(name, self);
-                }
-                OPTCODE::Add { node_id } => {
-                    let (a, b, result) = vm.aritmethics("+");
-                    let replace_value = vm::format_for_print::format_for_print(
-                        &vm.stack.back().unwrap().clone(),
-                        false
-                    );
-                    self.explain_process(
-                        format!("Saskaitīšanas darbība: {} + {} = {}", a, b, result),
-                        node_id
-                    );
-                    self.code_replace_calculate(node_id, replace_value);
-                    #[cfg(target_family = "wasm")]
-                    step();
-                }
-                OPTCODE::Subtract { node_id } => {
-                    let (a, b, result) = vm.aritmethics("-");
-                    let replace_value = vm::format_for_print::format_for_print(
-                        &vm.stack.back().unwrap().clone(),
-                        false
-                    );
-                    self.explain_process(
-                        format!("Atņemšanas darbība: {} - {} = {}", a, b, result),
-                        node_id
-                    );
-                    self.code_replace_calculate(node_id, replace_value);
-                    #[cfg(target_family = "wasm")]
-                    step();
-                }
-                OPTCODE::Multiply { node_id } => {
-                    let (a, b, result) = vm.aritmethics("*");
-                    let replace_value = vm::format_for_print::format_for_print(
-                        &vm.stack.back().unwrap().clone(),
-                        false
-                    );
-                    self.explain_process(
-                        format!("Reizināšanas darbība: {} * {} = {}", a, b, result),
-                        node_id
-                    );
-                    self.code_replace_calculate(node_id, replace_value);
-                    #[cfg(target_family = "wasm")]
-                    step();
-                }
-                OPTCODE::Divide { node_id } => {
-                    let (a, b, result) = vm.aritmethics("/");
-                    let replace_value = vm::format_for_print::format_for_print(
-                        &vm.stack.back().unwrap().clone(),
-                        false
-                    );
-                    self.explain_process(
-                        format!("Dalīšanas darbība: {} / {} = {}", a, b, result),
-                        node_id
-                    );
-                    self.code_replace_calculate(node_id, replace_value);
-                    #[cfg(target_family = "wasm")]
-                    step();
-                }
-                OPTCODE::Remainder { node_id } => {
-                    let (a, b, result) = vm.aritmethics("%");
-                    let replace_value = vm::format_for_print::format_for_print(
-                        &vm.stack.back().unwrap().clone(),
-                        false
-                    );
-                    self.explain_process(
-                        format!("Atlikums {} dalot ar {} ir {}", a, b, result),
-                        node_id
-                    );
-                    self.code_replace_calculate(node_id, replace_value);
-                    #[cfg(target_family = "wasm")]
-                    step();
-                }
-                OPTCODE::JumpIfFalse {
-                    steps,
-                    jump_target_column: _,
-                    jump_target_line: _,
-                    is_skipable: _,
-                } => {
-                    if vm.must_jump() {
-                        // println!("line: {}, col: {}", jump_target_line, jump_target_column);
-                        index += *steps;
-                    }
-                }
-                OPTCODE::Jump { steps } => {
-                    index += *steps;
-                }
-                OPTCODE::JumpBack { steps } => {
-                    index -= *steps;
-                }
-                OPTCODE::LessThan { node_id } => {
-                    let (a, b, result) = vm.aritmethics("<");
-                    let replace_value = vm::format_for_print::format_for_print(
-                        &vm.stack.back().unwrap().clone(),
-                        false
-                    );
-                    self.explain_process(
-                        format!("Vai {} ir mazāks par {}? {}.", a, b, result),
-                        node_id
-                    );
-                    self.code_replace_calculate(node_id, replace_value);
-                    #[cfg(target_family = "wasm")]
-                    step();
-                }
-                OPTCODE::LargerThan { node_id } => {
-                    let (a, b, result) = vm.aritmethics(">");
-                    let replace_value = vm::format_for_print::format_for_print(
-                        &vm.stack.back().unwrap().clone(),
-                        false
-                    );
-                    self.explain_process(
-                        format!("Vai {} ir lielāks par {}? {}.", a, b, result),
-                        node_id
-                    );
-                    self.code_replace_calculate(node_id, replace_value);
-                    #[cfg(target_family = "wasm")]
-                    step();
-                }
-                OPTCODE::LessOrEq { node_id } => {
-                    let (a, b, result) = vm.aritmethics("<=");
-                    let replace_value = vm::format_for_print::format_for_print(
-                        &vm.stack.back().unwrap().clone(),
-                        false
-                    );
-                    self.explain_process(
-                        format!("Vai {} ir vienāds vai mazāks par {}? {}.", a, b, result),
-                        node_id
-                    );
-                    self.code_replace_calculate(node_id, replace_value);
-                    #[cfg(target_family = "wasm")]
-                    step();
-                }
-                OPTCODE::LargerOrEq { node_id } => {
-                    let (a, b, result) = vm.aritmethics(">=");
-                    let replace_value = vm::format_for_print::format_for_print(
-                        &vm.stack.back().unwrap().clone(),
-                        false
-                    );
-                    self.explain_process(
-                        format!("Vai {} ir vienāds vai lielāks par {}? {}.", a, b, result),
-                        node_id
-                    );
-                    self.code_replace_calculate(node_id, replace_value);
-                    #[cfg(target_family = "wasm")]
-                    step();
-                }
-                OPTCODE::NotEq { node_id } => {
-                    let (a, b, result) = vm.aritmethics("!=");
-                    let replace_value = vm::format_for_print::format_for_print(
-                        &vm.stack.back().unwrap().clone(),
-                        false
-                    );
-                    self.explain_process(
-                        format!("Vai {} ir nevienāds ar {}? {}.", a, b, result),
-                        node_id
-                    );
-                    self.code_replace_calculate(node_id, replace_value);
-                    #[cfg(target_family = "wasm")]
-                    step();
-                }
-                OPTCODE::Eq { node_id } => {
-                    let (a, b, result) = vm.aritmethics("==");
-                    let replace_value = vm::format_for_print::format_for_print(
-                        &vm.stack.back().unwrap().clone(),
-                        false
-                    );
-                    self.explain_process(
-                        format!("Vai {} ir vienāds ar {}? {}.", a, b, result),
-                        node_id
-                    );
-                    self.code_replace_calculate(node_id, replace_value);
-                    #[cfg(target_family = "wasm")]
-                    step();
-                }
-                OPTCODE::Or { node_id } => {
-                    let (a, b, result) = vm.aritmethics("or");
-                    let replace_value = vm::format_for_print::format_for_print(
-                        &vm.stack.back().unwrap().clone(),
-                        false
-                    );
-                    self.explain_process(
-                        format!(
-                            "Vai {} un {} vismaz viena ir pateisa izteiksme? {}.",
-                            a,
-                            b,
-                            result
-                        ),
-                        node_id
-                    );
-                    self.code_replace_calculate(node_id, replace_value);
-                    #[cfg(target_family = "wasm")]
-                    step();
+            if !self.step(vm, bytecode, &mut index)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes the optcode at `index` with the limit checks and moves `index` to the next one.
+    /// Returns `false` when the program has ended.
+    pub(crate) fn step(
+        &mut self,
+        vm: &mut VM,
+        bytecode: &[OPTCODE],
+        index: &mut usize
+    ) -> Result<bool, RuntimeError> {
+        let optcode_index = *index;
+        let optcode = &bytecode[optcode_index];
+        let keep_running = vm
+            .count_instruction()
+            .and_then(|_| self.execute(vm, optcode, index))
+            .and_then(|keep_running| {
+                vm.check_limits(optcode)?;
+                Ok(keep_running)
+            })
+            .map_err(|error| self.locate_error(error, optcode_index, optcode))?;
+        *index = index.wrapping_add(1);
+        Ok(keep_running)
+    }
+
+    /// Executes a single optcode. Returns `false` when the program has ended.
+    fn execute(
+        &mut self,
+        vm: &mut VM,
+        optcode: &OPTCODE,
+        index: &mut usize
+    ) -> Result<bool, RuntimeError> {
+        match optcode {
+            OPTCODE::Step => self.notify(&ExecutionEvent::Step),
+            OPTCODE::PushToTestingStack { duplicate_stackvalue } =>
+                vm.push_to_testing_stack(*duplicate_stackvalue),
+            OPTCODE::CallFunction { name, .. } => {
+                // Only bytecode that was not linked or verified gets here
+                return Err(RuntimeErrorKind::UnknownFunction { name: name.clone() }.into());
+            }
+            OPTCODE::Add { node_id } => self.binary_operation(vm, BINOP::Add, *node_id)?,
+            OPTCODE::Subtract { node_id } => self.binary_operation(vm, BINOP::Subtract, *node_id)?,
+            OPTCODE::Multiply { node_id } => self.binary_operation(vm, BINOP::Multiply, *node_id)?,
+            OPTCODE::Divide { node_id } => self.binary_operation(vm, BINOP::Divide, *node_id)?,
+            OPTCODE::Remainder { node_id } => self.binary_operation(vm, BINOP::Remainder, *node_id)?,
+            OPTCODE::LessThan { node_id } => self.binary_operation(vm, BINOP::LessThan, *node_id)?,
+            OPTCODE::LargerThan { node_id } => self.binary_operation(vm, BINOP::LargerThan, *node_id)?,
+            OPTCODE::LessOrEq { node_id } => self.binary_operation(vm, BINOP::LessOrEq, *node_id)?,
+            OPTCODE::LargerOrEq { node_id } => self.binary_operation(vm, BINOP::LargerOrEq, *node_id)?,
+            OPTCODE::NotEq { node_id } => self.binary_operation(vm, BINOP::NotEq, *node_id)?,
+            OPTCODE::Eq { node_id } => self.binary_operation(vm, BINOP::Eq, *node_id)?,
+            OPTCODE::Or { node_id } => self.binary_operation(vm, BINOP::Or, *node_id)?,
+            OPTCODE::And { node_id } => self.binary_operation(vm, BINOP::And, *node_id)?,
+            OPTCODE::Xor { node_id } => self.binary_operation(vm, BINOP::Xor, *node_id)?,
+            OPTCODE::JumpIfFalse {
+                steps,
+                jump_target_column: _,
+                jump_target_line: _,
+                is_skipable: _,
+            } => {
+                let jumped = vm.must_jump()?;
+                self.notify(&(ExecutionEvent::BranchTaken { index: *index, jumped }));
+                if jumped {
+                    *index += *steps;
                 }
-                OPTCODE::And { node_id } => {
-                    let (a, b, result) = vm.aritmethics("and");
-                    let replace_value = vm::format_for_print::format_for_print(
-                        &vm.stack.back().unwrap().clone(),
-                        false
-                    );
-                    self.explain_process(
-                        format!("Vai {} un {} ir patiesas izteksmes? {}.", a, b, result),
-                        node_id
-                    );
-                    self.code_replace_calculate(node_id, replace_value);
-                    #[cfg(target_family = "wasm")]
-                    step();
+            }
+            OPTCODE::Jump { steps } => {
+                *index += *steps;
+            }
+            OPTCODE::RangeNext { steps } => {
+                if !vm.range_next()? {
+                    *index += *steps;
                 }
-                OPTCODE::Xor { node_id } => {
-                    let (a, b, result) = vm.aritmethics("xor");
-                    let replace_value = vm::format_for_print::format_for_print(&result, false);
-                    self.explain_process(
-                        format!(
-                            "Vai {} vai {} ir patiesas izteksmes, bet ne abas? {}.",
-                            a,
-                            b,
-                            result
-                        ),
-                        node_id
-                    );
-                    self.code_replace_calculate(node_id, replace_value);
-                    #[cfg(target_family = "wasm")]
-                    step();
+            }
+            OPTCODE::ForEachNext { steps } => {
+                if !vm.foreach_next()? {
+                    *index += *steps;
                 }
-                OPTCODE::Not => vm.not(),
-                OPTCODE::DefineVar { id, var_name, node_id } => {
-                    let value = vm.stack.pop_back().unwrap();
-                    vm.variables.insert(*id, Variable {
+            }
+            OPTCODE::JumpBack { steps } => {
+                // Jumping back to index 0 goes through usize::MAX, the loop in `run` adds 1
+                *index = index.wrapping_sub(*steps);
+            }
+            OPTCODE::Not => vm.not()?,
+            OPTCODE::DefineVar { id, var_name, node_id } => {
+                let value = vm.define_var(*id)?;
+                self.notify(
+                    &(ExecutionEvent::VariableDefined {
                         id: *id,
-                        value: value.clone(),
-                    });
-                    self.explain_process(
-                        format!(
-                            "Jaunam mainīgajam \"{}\" tiek piešķirta vērtība {} ({})",
-                            var_name,
-                            value.clone(),
-                            CelsiumProgram::stackvalue_type_to_str(value)
-                        ),
-                        node_id
-                    );
-                    #[cfg(target_family = "wasm")]
-                    step();
+                        name: var_name,
+                        value: &value,
+                        node_id: *node_id,
+                        span: self.node_locations_by_id.get(node_id).cloned(),
+                    })
+                );
+                self.notify(&ExecutionEvent::Step);
+            }
+            OPTCODE::DefineObject { id } => {
+                vm.define_var(*id)?;
+            }
+            OPTCODE::GetObjectField { field_name } => vm.get_object_field(field_name)?,
+            OPTCODE::LoadVar { id, node_id, var_name } => {
+                let var_value = vm.load_var(*id)?;
+                self.notify(
+                    &(ExecutionEvent::VariableLoaded {
+                        id: *id,
+                        name: var_name,
+                        value: &var_value,
+                        node_id: *node_id,
+                        span: self.node_locations_by_id.get(node_id).cloned(),
+                    })
+                );
+                self.code_replace_calculate(node_id, self.locale.format_value(&var_value));
+                self.notify(&ExecutionEvent::Step);
+            }
+            OPTCODE::AssignVar { id } => vm.assign_var(*id)?,
+            OPTCODE::CreateArray { init_values_count } => {
+                let mut init_values: Vec<StackValue> = vec![];
+                for _ in 0..*init_values_count {
+                    init_values.push(vm.pop()?);
                 }
-                OPTCODE::DefineObject { id } => {
-                    let object = vm.pop();
-                    let _ = vm.variables.insert(*id, Variable { id: *id, value: object });
+                init_values.reverse();
+                vm.stack.push_back(StackValue::array(init_values));
+            }
+            OPTCODE::GetIndex => vm.get_index()?,
+            OPTCODE::CreateMap { init_entries_count } => vm.create_map(*init_entries_count)?,
+            OPTCODE::GetMapValue => vm.get_map_value()?,
+            OPTCODE::SetMapValue { id } => vm.set_map_value(*id)?,
+            OPTCODE::RemoveMapKey { id } => vm.remove_map_key(*id)?,
+            OPTCODE::MapContains => vm.map_contains()?,
+            OPTCODE::GetMapKeys => vm.get_map_keys()?,
+            OPTCODE::AssignAtPath { id, path } => vm.assign_at_path(*id, path)?,
+            OPTCODE::CopyValue => {
+                let value = vm.pop()?;
+                vm.push_stackvalue(value.deep_copy());
+            }
+            OPTCODE::PushToArray { id } => vm.push_to_array(*id)?,
+            OPTCODE::GettArrayLength { id } => vm.get_array_length(*id)?,
+            OPTCODE::CallSpecialFunction { function } => {
+                let index = self.natives
+                    .resolve(function)
+                    .ok_or(RuntimeErrorKind::UnknownFunction { name: function.clone() })?;
+                self.natives.call(index, vm)?;
+            }
+            OPTCODE::CallNativeFunction { index, function_name: _ } => self.natives.call(*index, vm)?,
+            OPTCODE::AssignAtArrayIndex { id } => vm.set_at_array(*id)?,
+            OPTCODE::CreateObject { field_names } => {
+                let mut fields = vec![];
+                let mut field_names_reversed = field_names.clone();
+                field_names_reversed.reverse();
+                for fieldname in field_names_reversed {
+                    fields.push(ObjectField { name: fieldname.to_string(), value: vm.pop()? });
                 }
-                OPTCODE::GetObjectField { field_name } => vm.get_object_field(field_name),
-                OPTCODE::LoadVar { id, node_id, var_name } => {
-                    let var_value = vm.load_var(*id);
-
-                    self.explain_process(
-                        format!(
-                            "Mainīgā \"{}\" vērtība ir {} ({})",
-                            var_name,
-                            var_value,
-                            CelsiumProgram::stackvalue_type_to_str(var_value.clone())
-                        ),
-                        node_id
-                    );
-                    self.code_replace_calculate(node_id, format!("{}", var_value));
-                    #[cfg(target_family = "wasm")]
-                    step();
+                vm.push_stackvalue(StackValue::object(fields));
+            }
+            OPTCODE::LoadInt { value } => vm.push_stackvalue(StackValue::Int { value: *value }),
+            OPTCODE::LoadBool { value } => vm.push_stackvalue(StackValue::Bool { value: *value }),
+            OPTCODE::LoadString { value } =>
+                vm.push_stackvalue(StackValue::String { value: value.to_string() }),
+            OPTCODE::LoadFloat { value } => vm.push_stackvalue(StackValue::Float { value: *value }),
+            OPTCODE::LoadDecimal { value } => {
+                let Some(decimal) = parse_decimal(value) else {
+                    return Err(RuntimeErrorKind::InvalidDecimal { literal: value.clone() }.into());
+                };
+                vm.push_stackvalue(StackValue::Decimal { value: decimal });
+            }
+            OPTCODE::Break { span: _ } | OPTCODE::Continue { span: _ } =>
+                unreachable!("loop builders lower Break and Continue to jumps"),
+            OPTCODE::Pop { count } => {
+                for _ in 0..*count {
+                    vm.pop()?;
                 }
-                OPTCODE::AssignVar { id } => vm.assign_var(*id),
-                OPTCODE::CreateArray { init_values_count } => {
-                    let mut init_values: Vec<StackValue> = vec![];
-                    for _ in 0..*init_values_count {
-                        init_values.push(vm.pop());
+            }
+            OPTCODE::Return => {
+                match vm.return_from_function(None)? {
+                    Some(return_index) => {
+                        *index = return_index;
                     }
-                    init_values.reverse();
-                    vm.stack.push_back(StackValue::Array { value: init_values });
-                }
-                OPTCODE::GetIndex => vm.get_index(),
-                OPTCODE::PushToArray { id } => vm.push_to_array(*id),
-                OPTCODE::GettArrayLength { id } => vm.get_array_length(*id),
-                OPTCODE::CallSpecialFunction { function } => {
-                    call_special_function!(
-                        function,
-                        vm,
-                        ("izvade" => izvade),
-                        ("izvadetp" => izvadetp),
-                        ("ievade" => ievade),
-                        ("garums" => garums),
-                        ("nejaušs" => nejauss),
-                        ("nejaušs_robežās" => nejauss_robezas),
-                        ("apgriezt" => apgriezt),
-                        ("mazie_burti" => mazie_burti),
-                        ("lielie_burti" => lielie_burti),
-                        ("apakšvirkne" => apaksvirkne),
-                        ("aizvietot" => aizvietot),
-                        ("satur" => satur),
-                        ("sākas_ar" => sakas_ar),
-                        ("beidzas_ar" => beidzas_ar),
-                        ("absolūtā_vērtība" => absoluta_vertiba),
-                        ("minimums" => minimums),
-                        ("maksimums" => maksimums),
-                        ("apaļot" => apalot),
-                        ("grīda" => grida),
-                        ("griesti" => griesti),
-                        ("pakāpināt" => pakapinat),
-                        ("kvadrātsakne" => kvadratsakne),
-                    );
-                }
-                OPTCODE::AssignAtArrayIndex { id } => vm.set_at_array(*id),
-                OPTCODE::SimpleLoop { body_block } =>
-                    vm.simple_loop(self, body_block.bytecode.clone()),
-                OPTCODE::CreateObject { field_names } => {
-                    let mut fields = vec![];
-                    let mut field_names_reversed = field_names.clone();
-                    field_names_reversed.reverse();
-                    for fieldname in field_names_reversed {
-                        fields.push(ObjectField { name: fieldname.to_string(), value: vm.pop() });
+                    None => {
+                        return Ok(false); //Programma beigusies
                     }
-                    vm.push_stackvalue(StackValue::Object { value: fields.clone() });
                 }
-                OPTCODE::LoadInt { value } => vm.push_stackvalue(StackValue::Int { value: *value }),
-                OPTCODE::LoadBool { value } =>
-                    vm.push_stackvalue(StackValue::Bool { value: *value }),
-                OPTCODE::LoadString { value } =>
-                    vm.push_stackvalue(StackValue::String { value: value.to_string() }),
-                OPTCODE::LoadFloat { value } =>
-                    vm.push_stackvalue(StackValue::Float { value: *value }),
-                OPTCODE::Break { span: _ } => todo!("Break should not appear in bytecode"),
-                OPTCODE::Continue { span: _ } => todo!("Continue should not appear in bytecode"),
-                OPTCODE::Return => {
-                    let call_stack_item = vm.call_stack.pop_back();
-                    if call_stack_item.is_none() {
-                        break; //Programma beigusies
+            }
+            OPTCODE::ReturnValue => {
+                let value = vm.pop()?;
+                match vm.return_from_function(Some(value))? {
+                    Some(return_index) => {
+                        *index = return_index;
+                    }
+                    None => {
+                        return Ok(false);
                     }
-                    index = call_stack_item.unwrap().optode_index;
-                }
-                OPTCODE::JumpToFunction { target, function_name } => {
-                    vm.call_stack.push_back(CallStackItem {
-                        optode_index: index,
-                        function_name: function_name.clone(),
-                    });
-                    index = *target;
-                }
-                OPTCODE::SetObjectField { id, field_name } => vm.set_object_field(*id, field_name),
-                OPTCODE::CopyVariableValue { src_var_id, dst_var_id } => {
-                    vm.copy_var_value(*src_var_id, *dst_var_id);
                 }
-            };
-            index += 1;
+            }
+            OPTCODE::JumpToFunction { target, function, function_name } => {
+                let signature = self.functions
+                    .get(*function)
+                    .map(|function| function.signature.clone())
+                    .ok_or(RuntimeErrorKind::UnknownFunction { name: function_name.clone() })?;
+                vm.enter_function(*index, &signature)?;
+                *index = *target;
+            }
+            OPTCODE::SetObjectField { id, field_name } => vm.set_object_field(*id, field_name)?,
+            OPTCODE::CopyVariableValue { src_var_id, dst_var_id } =>
+                vm.copy_var_value(*src_var_id, *dst_var_id)?,
         }
+        Ok(true)
     }
 }
@@ -0,0 +1,88 @@
+//! Lays out the main block and the function bodies as one bytecode and turns every
+//! `CallFunction` into a `JumpToFunction` through a table of function ids.
+
+use std::collections::HashMap;
+
+use crate::{
+    block::{ build_error::{ BuildError, BuildErrorKind }, Block },
+    bytecode::OPTCODE,
+    bytecode_parser::FunctionEntry,
+    module::Function,
+    Scope,
+};
+
+/// The linked bytecode and the index where each function's body starts.
+pub(crate) struct Linked {
+    pub bytecode: Vec<OPTCODE>,
+    pub function_offsets: Vec<usize>,
+}
+
+/// Fails on two functions with the same id or on calls to ids that are not in `functions`.
+pub(crate) fn link(main_block: &Block, functions: &[Function]) -> Result<Linked, BuildError> {
+    let mut table = HashMap::new();
+    for (index, function) in functions.iter().enumerate() {
+        if table.insert(function.id, index).is_some() {
+            return Err(BuildErrorKind::DuplicateFunctionId { id: function.id }.into());
+        }
+    }
+
+    let mut bytecode = main_block.bytecode.clone();
+    bytecode.push(OPTCODE::Return); // Return from the main function
+    let mut function_offsets = vec![];
+    for function in functions {
+        function_offsets.push(bytecode.len());
+        bytecode.extend(function.body.bytecode.iter().cloned());
+        bytecode.push(OPTCODE::Return); // Return from the user defined function
+    }
+
+    let mut unresolved: Vec<(usize, String)> = vec![];
+    for optcode in &mut bytecode {
+        let OPTCODE::CallFunction { id, name } = optcode else {
+            continue;
+        };
+        match table.get(id) {
+            Some(&function) => {
+                *optcode = OPTCODE::JumpToFunction {
+                    // The VM steps past the target before running it
+                    target: function_offsets[function] - 1,
+                    function,
+                    function_name: name.clone(),
+                };
+            }
+            None => {
+                if !unresolved.iter().any(|(unresolved_id, _)| unresolved_id == id) {
+                    unresolved.push((*id, name.clone()));
+                }
+            }
+        }
+    }
+    if !unresolved.is_empty() {
+        return Err(BuildErrorKind::UnresolvedCalls { calls: unresolved }.into());
+    }
+    Ok(Linked { bytecode, function_offsets })
+}
+
+/// Takes the function bodies back out of linked bytecode that was saved with its function table.
+/// Fails with the name of the first function whose range is outside of the bytecode.
+pub(crate) fn split_functions(
+    bytecode: &[OPTCODE],
+    table: Vec<FunctionEntry>,
+    scope: &Scope
+) -> Result<(Vec<Function>, Vec<usize>), String> {
+    let mut functions = vec![];
+    let mut function_offsets = vec![];
+    for FunctionEntry { id, signature, offset, length } in table {
+        let Some(body) = offset
+            .checked_add(length)
+            .and_then(|end| bytecode.get(offset..end)) else {
+            return Err(signature.name);
+        };
+        functions.push(Function {
+            id,
+            signature,
+            body: Block { bytecode: body.to_vec(), scope: scope.clone() },
+        });
+        function_offsets.push(offset);
+    }
+    Ok((functions, function_offsets))
+}
@@ -0,0 +1,318 @@
+use crate::{
+    bytecode::BINOP,
+    observer::ExecutionEvent,
+    typecheck::Suggestion,
+    vm::{ decimal::format_decimal, StackValue },
+};
+
+/// Language of the explanations, type names and printed values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    Latvian,
+    English,
+    Lithuanian,
+}
+
+impl Locale {
+    /// Picks a locale by its ISO 639-1 code, e.g. `"lv"`.
+    pub fn from_code(code: &str) -> Option<Locale> {
+        match code {
+            "lv" => Some(Locale::Latvian),
+            "en" => Some(Locale::English),
+            "lt" => Some(Locale::Lithuanian),
+            _ => None,
+        }
+    }
+
+    pub fn bool_word(&self, value: bool) -> &'static str {
+        match (self, value) {
+            (Locale::Latvian, true) => "Jā",
+            (Locale::Latvian, false) => "Nē",
+            (Locale::English, true) => "Yes",
+            (Locale::English, false) => "No",
+            (Locale::Lithuanian, true) => "Taip",
+            (Locale::Lithuanian, false) => "Ne",
+        }
+    }
+
+    pub fn decimal_separator(&self) -> &'static str {
+        match self {
+            Locale::Latvian | Locale::Lithuanian => ",",
+            Locale::English => ".",
+        }
+    }
+
+    /// Separates array elements, so it can't be the decimal separator.
+    pub fn list_separator(&self) -> &'static str {
+        match self {
+            Locale::Latvian | Locale::Lithuanian => ";",
+            Locale::English => ", ",
+        }
+    }
+
+    pub fn type_name(&self, value: &StackValue) -> &'static str {
+        match self {
+            Locale::Latvian =>
+                match value {
+                    StackValue::Bool { value: _ } => "būls",
+                    StackValue::Int { value: _ } | StackValue::BigInt { value: _ } => "vesels skaitlis",
+                    StackValue::Float { value: _ } => "decimālskaitlis",
+                    StackValue::Decimal { value: _ } => "precīzs decimālskaitlis",
+                    StackValue::String { value: _ } => "teksts",
+                    StackValue::Array { value: _ } => "saraksts",
+                    StackValue::Object { value: _ } => "objekts",
+                    StackValue::Map { value: _ } => "vārdnīca",
+                }
+            Locale::English =>
+                match value {
+                    StackValue::Bool { value: _ } => "boolean",
+                    StackValue::Int { value: _ } | StackValue::BigInt { value: _ } => "integer",
+                    StackValue::Float { value: _ } => "decimal number",
+                    StackValue::Decimal { value: _ } => "exact decimal number",
+                    StackValue::String { value: _ } => "text",
+                    StackValue::Array { value: _ } => "list",
+                    StackValue::Object { value: _ } => "object",
+                    StackValue::Map { value: _ } => "dictionary",
+                }
+            Locale::Lithuanian =>
+                match value {
+                    StackValue::Bool { value: _ } => "loginė reikšmė",
+                    StackValue::Int { value: _ } | StackValue::BigInt { value: _ } => "sveikasis skaičius",
+                    StackValue::Float { value: _ } => "realusis skaičius",
+                    StackValue::Decimal { value: _ } => "tikslusis dešimtainis skaičius",
+                    StackValue::String { value: _ } => "tekstas",
+                    StackValue::Array { value: _ } => "sąrašas",
+                    StackValue::Object { value: _ } => "objektas",
+                    StackValue::Map { value: _ } => "žodynas",
+                }
+        }
+    }
+
+    fn object_name(&self) -> &'static str {
+        match self {
+            Locale::Latvian => "Objekts",
+            Locale::English => "Object",
+            Locale::Lithuanian => "Objektas",
+        }
+    }
+
+    /// Formats a value the way `izvade` prints it.
+    pub fn format_value(&self, value: &StackValue) -> String {
+        match value {
+            StackValue::Bool { value } => self.bool_word(*value).to_owned(),
+            StackValue::Int { value } => value.to_string(),
+            StackValue::BigInt { value } => value.to_string(),
+            StackValue::String { value } => value.to_string(),
+            StackValue::Array { value } => {
+                let elements: Vec<String> = value
+                    .borrow()
+                    .iter()
+                    .map(|element| self.format_element(element))
+                    .collect();
+                format!("[{}]", elements.join(self.list_separator()))
+            }
+            StackValue::Map { value } => {
+                let entries: Vec<String> = value
+                    .borrow()
+                    .iter()
+                    .map(|entry| {
+                        format!(
+                            "{}: {}",
+                            self.format_element(&entry.key),
+                            self.format_element(&entry.value)
+                        )
+                    })
+                    .collect();
+                format!("{{{}}}", entries.join(self.list_separator()))
+            }
+            StackValue::Object { value: fields } => {
+                let mut printable_object = format!("{} {{\n", self.object_name());
+                for field in fields.borrow().iter() {
+                    printable_object += &format!(
+                        "   {}: {}\n",
+                        field.name,
+                        self.format_value(&field.value)
+                    );
+                }
+                printable_object += "}";
+                printable_object
+            }
+            StackValue::Float { value } =>
+                value.to_string().replace(".", self.decimal_separator()),
+            StackValue::Decimal { value } => format_decimal(value, self.decimal_separator()),
+        }
+    }
+
+    /// Strings inside arrays and maps are quoted.
+    fn format_element(&self, value: &StackValue) -> String {
+        match value {
+            StackValue::String { value } => format!("\"{}\"", value),
+            _ => self.format_value(value),
+        }
+    }
+
+    /// How to fix an operator error, see `TypeErrorKind::suggestion`.
+    pub fn suggestion(&self, suggestion: Suggestion) -> &'static str {
+        match self {
+            Locale::Latvian =>
+                match suggestion {
+                    Suggestion::UsePart => "izmanto elementu vai lauku, nevis visu vērtību",
+                    Suggestion::ConvertToText => "pārveido vērtību par tekstu ar teksts()",
+                    Suggestion::RepeatByInt => "tekstu var atkārtot tikai vesela skaitļa reižu",
+                    Suggestion::JoinWithPlus => "tekstu var tikai savienot ar +",
+                }
+            Locale::English =>
+                match suggestion {
+                    Suggestion::UsePart => "use an element or a field instead of the whole value",
+                    Suggestion::ConvertToText => "convert the value to text with teksts()",
+                    Suggestion::RepeatByInt => "text can only be repeated an Int number of times",
+                    Suggestion::JoinWithPlus => "text can only be joined with +",
+                }
+            Locale::Lithuanian =>
+                match suggestion {
+                    Suggestion::UsePart => "naudok elementą arba lauką, o ne visą reikšmę",
+                    Suggestion::ConvertToText => "paversk reikšmę tekstu su teksts()",
+                    Suggestion::RepeatByInt => "tekstą galima kartoti tik sveikąjį skaičių kartų",
+                    Suggestion::JoinWithPlus => "tekstą galima tik sujungti su +",
+                }
+        }
+    }
+
+    /// Text shown to students for an event. Events without an explanation return `None`.
+    pub fn explain(&self, event: &ExecutionEvent) -> Option<String> {
+        match event {
+            ExecutionEvent::BinaryOperation { operator, left, right, result, .. } => {
+                let (a, b, result) = (
+                    self.format_value(left),
+                    self.format_value(right),
+                    self.format_value(result),
+                );
+                self.explain_binop(operator, &a, &b, &result)
+            }
+            ExecutionEvent::VariableDefined { name, value, .. } => {
+                let (value, type_name) = (self.format_value(value), self.type_name(value));
+                Some(match self {
+                    Locale::Latvian =>
+                        format!(
+                            "Jaunam mainīgajam \"{}\" tiek piešķirta vērtība {} ({})",
+                            name,
+                            value,
+                            type_name
+                        ),
+                    Locale::English =>
+                        format!("The new variable \"{}\" is assigned {} ({})", name, value, type_name),
+                    Locale::Lithuanian =>
+                        format!(
+                            "Naujam kintamajam \"{}\" priskiriama reikšmė {} ({})",
+                            name,
+                            value,
+                            type_name
+                        ),
+                })
+            }
+            ExecutionEvent::VariableLoaded { name, value, .. } => {
+                let (value, type_name) = (self.format_value(value), self.type_name(value));
+                Some(match self {
+                    Locale::Latvian =>
+                        format!("Mainīgā \"{}\" vērtība ir {} ({})", name, value, type_name),
+                    Locale::English =>
+                        format!("The value of the variable \"{}\" is {} ({})", name, value, type_name),
+                    Locale::Lithuanian =>
+                        format!("Kintamojo \"{}\" reikšmė yra {} ({})", name, value, type_name),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn explain_binop(&self, operator: &BINOP, a: &str, b: &str, result: &str) -> Option<String> {
+        let explanation = match self {
+            Locale::Latvian =>
+                match operator {
+                    BINOP::Add => format!("Saskaitīšanas darbība: {} + {} = {}", a, b, result),
+                    BINOP::Subtract => format!("Atņemšanas darbība: {} - {} = {}", a, b, result),
+                    BINOP::Multiply =>
+                        format!("Reizināšanas darbība: {} * {} = {}", a, b, result),
+                    BINOP::Divide => format!("Dalīšanas darbība: {} / {} = {}", a, b, result),
+                    BINOP::Remainder => format!("Atlikums {} dalot ar {} ir {}", a, b, result),
+                    BINOP::LessThan => format!("Vai {} ir mazāks par {}? {}.", a, b, result),
+                    BINOP::LargerThan => format!("Vai {} ir lielāks par {}? {}.", a, b, result),
+                    BINOP::LessOrEq =>
+                        format!("Vai {} ir vienāds vai mazāks par {}? {}.", a, b, result),
+                    BINOP::LargerOrEq =>
+                        format!("Vai {} ir vienāds vai lielāks par {}? {}.", a, b, result),
+                    BINOP::NotEq => format!("Vai {} ir nevienāds ar {}? {}.", a, b, result),
+                    BINOP::Eq => format!("Vai {} ir vienāds ar {}? {}.", a, b, result),
+                    BINOP::Or =>
+                        format!(
+                            "Vai {} un {} vismaz viena ir pateisa izteiksme? {}.",
+                            a,
+                            b,
+                            result
+                        ),
+                    BINOP::And =>
+                        format!("Vai {} un {} ir patiesas izteksmes? {}.", a, b, result),
+                    BINOP::Xor =>
+                        format!(
+                            "Vai {} vai {} ir patiesas izteksmes, bet ne abas? {}.",
+                            a,
+                            b,
+                            result
+                        ),
+                    BINOP::Not => {
+                        return None;
+                    }
+                }
+            Locale::English =>
+                match operator {
+                    BINOP::Add => format!("Addition: {} + {} = {}", a, b, result),
+                    BINOP::Subtract => format!("Subtraction: {} - {} = {}", a, b, result),
+                    BINOP::Multiply => format!("Multiplication: {} * {} = {}", a, b, result),
+                    BINOP::Divide => format!("Division: {} / {} = {}", a, b, result),
+                    BINOP::Remainder =>
+                        format!("The remainder of {} divided by {} is {}", a, b, result),
+                    BINOP::LessThan => format!("Is {} less than {}? {}.", a, b, result),
+                    BINOP::LargerThan => format!("Is {} greater than {}? {}.", a, b, result),
+                    BINOP::LessOrEq =>
+                        format!("Is {} less than or equal to {}? {}.", a, b, result),
+                    BINOP::LargerOrEq =>
+                        format!("Is {} greater than or equal to {}? {}.", a, b, result),
+                    BINOP::NotEq => format!("Is {} not equal to {}? {}.", a, b, result),
+                    BINOP::Eq => format!("Is {} equal to {}? {}.", a, b, result),
+                    BINOP::Or => format!("Is at least one of {} and {} true? {}.", a, b, result),
+                    BINOP::And => format!("Are both {} and {} true? {}.", a, b, result),
+                    BINOP::Xor =>
+                        format!("Is either {} or {} true, but not both? {}.", a, b, result),
+                    BINOP::Not => {
+                        return None;
+                    }
+                }
+            Locale::Lithuanian =>
+                match operator {
+                    BINOP::Add => format!("Sudėties veiksmas: {} + {} = {}", a, b, result),
+                    BINOP::Subtract => format!("Atimties veiksmas: {} - {} = {}", a, b, result),
+                    BINOP::Multiply => format!("Daugybos veiksmas: {} * {} = {}", a, b, result),
+                    BINOP::Divide => format!("Dalybos veiksmas: {} / {} = {}", a, b, result),
+                    BINOP::Remainder => format!("Dalijant {} iš {} liekana yra {}", a, b, result),
+                    BINOP::LessThan => format!("Ar {} yra mažesnis už {}? {}.", a, b, result),
+                    BINOP::LargerThan => format!("Ar {} yra didesnis už {}? {}.", a, b, result),
+                    BINOP::LessOrEq =>
+                        format!("Ar {} yra mažesnis arba lygus {}? {}.", a, b, result),
+                    BINOP::LargerOrEq =>
+                        format!("Ar {} yra didesnis arba lygus {}? {}.", a, b, result),
+                    BINOP::NotEq => format!("Ar {} nelygu {}? {}.", a, b, result),
+                    BINOP::Eq => format!("Ar {} lygu {}? {}.", a, b, result),
+                    BINOP::Or =>
+                        format!("Ar bent viena iš {} ir {} yra teisinga? {}.", a, b, result),
+                    BINOP::And => format!("Ar {} ir {} abi yra teisingos? {}.", a, b, result),
+                    BINOP::Xor =>
+                        format!("Ar teisinga {} arba {}, bet ne abi? {}.", a, b, result),
+                    BINOP::Not => {
+                        return None;
+                    }
+                }
+        };
+        Some(explanation)
+    }
+}
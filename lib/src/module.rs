@@ -36,6 +36,8 @@ pub enum VISIBILITY {
 }
 #[derive(Debug, Clone)]
 pub struct Function {
+    /// Unique in the program, e.g. `CompileTimeFunction::id`. Calls are linked by it, not by name.
+    pub id: usize,
     pub signature: FunctionSignature,
     pub body: Block,
 }
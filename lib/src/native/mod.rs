@@ -0,0 +1,141 @@
+use std::{ fmt, rc::Rc };
+
+use crate::{
+    module::FunctionSignature,
+    vm::{ runtime_error::{ RuntimeError, RuntimeErrorKind }, vm::VM, StackValue },
+};
+
+/// A function provided by the embedder. It gets the arguments in declaration order
+/// and returns the value that is pushed on the stack if the signature has a return type.
+pub type HostFunction = Rc<dyn Fn(&mut VM, Vec<StackValue>) -> Result<StackValue, RuntimeError>>;
+
+/// Builtins from `std` manage the stack themselves.
+pub(crate) type BuiltinFunction = fn(&mut VM) -> Result<(), RuntimeError>;
+
+#[derive(Clone)]
+enum NativeImplementation {
+    Builtin(BuiltinFunction),
+    Host(HostFunction),
+}
+
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub signature: FunctionSignature,
+    /// How many values the function pops from the stack
+    pub arity: usize,
+    /// Whether the function leaves a value on the stack
+    pub returns_value: bool,
+    implementation: NativeImplementation,
+}
+
+/// Functions that bytecode can call with `CallSpecialFunction`.
+/// Calls are resolved to an index in this registry, so the VM does not match on names at runtime.
+#[derive(Clone)]
+pub struct NativeRegistry {
+    functions: Vec<NativeFunction>,
+}
+
+impl Default for NativeRegistry {
+    fn default() -> Self {
+        NativeRegistry::new()
+    }
+}
+
+impl fmt::Debug for NativeRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.functions.iter().map(|function| &function.signature.name))
+            .finish()
+    }
+}
+
+impl NativeRegistry {
+    /// Creates a registry with the standard library functions already registered.
+    pub fn new() -> NativeRegistry {
+        let mut registry = NativeRegistry { functions: vec![] };
+        crate::std::register_std_functions(&mut registry);
+        registry
+    }
+
+    /// Registers a host function and returns its index.
+    /// A function with the same name is replaced, which keeps already resolved indexes valid.
+    pub fn register<F>(&mut self, signature: FunctionSignature, function: F) -> usize
+        where F: Fn(&mut VM, Vec<StackValue>) -> Result<StackValue, RuntimeError> + 'static
+    {
+        let native = NativeFunction {
+            arity: signature.args.len(),
+            returns_value: signature.return_type.is_some(),
+            signature,
+            implementation: NativeImplementation::Host(Rc::new(function)),
+        };
+        self.insert(native)
+    }
+
+    pub(crate) fn register_builtin(
+        &mut self,
+        signature: FunctionSignature,
+        arity: usize,
+        returns_value: bool,
+        function: BuiltinFunction
+    ) -> usize {
+        self.insert(NativeFunction {
+            signature,
+            arity,
+            returns_value,
+            implementation: NativeImplementation::Builtin(function),
+        })
+    }
+
+    fn insert(&mut self, native: NativeFunction) -> usize {
+        match self.resolve(&native.signature.name) {
+            Some(index) => {
+                self.functions[index] = native;
+                index
+            }
+            None => {
+                self.functions.push(native);
+                self.functions.len() - 1
+            }
+        }
+    }
+
+    pub fn resolve(&self, name: &str) -> Option<usize> {
+        self.functions.iter().position(|function| function.signature.name == name)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&NativeFunction> {
+        self.functions.get(index)
+    }
+
+    /// Signatures of every registered function, for the frontend's type checker.
+    pub fn signatures(&self) -> Vec<FunctionSignature> {
+        self.functions
+            .iter()
+            .map(|function| function.signature.clone())
+            .collect()
+    }
+
+    pub(crate) fn call(&self, index: usize, vm: &mut VM) -> Result<(), RuntimeError> {
+        let function = self.functions
+            .get(index)
+            .ok_or(RuntimeErrorKind::UnknownFunction { name: format!("#{}", index) })?;
+        match &function.implementation {
+            NativeImplementation::Builtin(builtin) => builtin(vm)?,
+            NativeImplementation::Host(host) => {
+                let mut arguments = vec![];
+                for _ in 0..function.arity {
+                    arguments.push(vm.pop()?);
+                }
+                arguments.reverse();
+                let result = host(vm, arguments)?;
+                if function.returns_value {
+                    vm.push_stackvalue(result);
+                }
+            }
+        }
+        if function.returns_value {
+            vm.allocate_top()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use std::{ cell::RefCell, fmt, rc::Rc };
+
+#[cfg(target_family = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{ block::TextSpan, bytecode::BINOP, locale::Locale, vm::StackValue };
+
+#[cfg(target_family = "wasm")]
+#[wasm_bindgen]
+extern "C" {
+    fn code_replace(replace_with: &str, line: usize, column: usize, span: usize);
+    fn step();
+    fn explain(a: &str, line: usize, column: usize, span: usize);
+}
+
+/// Something that happened while running a program, for hosts that visualize the execution.
+/// `span` is the source location of the node, if the frontend provided one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecutionEvent<'a> {
+    BinaryOperation {
+        operator: BINOP,
+        left: &'a StackValue,
+        right: &'a StackValue,
+        result: &'a StackValue,
+        node_id: usize,
+        span: Option<TextSpan>,
+    },
+    VariableDefined {
+        id: usize,
+        name: &'a str,
+        value: &'a StackValue,
+        node_id: usize,
+        span: Option<TextSpan>,
+    },
+    VariableLoaded {
+        id: usize,
+        name: &'a str,
+        value: &'a StackValue,
+        node_id: usize,
+        span: Option<TextSpan>,
+    },
+    /// The code at `span` was evaluated to `replacement`. Spans of the following nodes
+    /// on the same line are shifted by the program after this event.
+    CodeReplaced {
+        replacement: &'a str,
+        span: TextSpan,
+    },
+    /// A `JumpIfFalse` checked its condition. `jumped` is true when the condition was false.
+    BranchTaken {
+        index: usize,
+        jumped: bool,
+    },
+    /// A point where a visual runner can pause: an `OPTCODE::Step` or the end of an explained operation
+    Step,
+}
+
+pub trait ExecutionObserver: fmt::Debug {
+    /// `locale` is the one set on the program, for observers that show `Locale::explain`.
+    fn on_event(&mut self, event: &ExecutionEvent, locale: Locale);
+}
+
+pub type SharedObserver = Rc<RefCell<dyn ExecutionObserver>>;
+
+/// The observer a program starts with: the page's callbacks in the browser, none natively.
+pub fn default_observer() -> Option<SharedObserver> {
+    #[cfg(target_family = "wasm")]
+    return Some(Rc::new(RefCell::new(WasmObserver)));
+    #[cfg(not(target_family = "wasm"))]
+    None
+}
+
+/// Forwards events to the `explain`, `code_replace` and `step` functions of the page.
+#[cfg(target_family = "wasm")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WasmObserver;
+
+#[cfg(target_family = "wasm")]
+impl ExecutionObserver for WasmObserver {
+    fn on_event(&mut self, event: &ExecutionEvent, locale: Locale) {
+        match event {
+            ExecutionEvent::BinaryOperation { span: Some(span), .. } |
+            ExecutionEvent::VariableDefined { span: Some(span), .. } |
+            ExecutionEvent::VariableLoaded { span: Some(span), .. } => {
+                if let Some(explanation) = locale.explain(event) {
+                    explain(&explanation, span.line, span.col_start, span.length);
+                }
+            }
+            ExecutionEvent::CodeReplaced { replacement, span } =>
+                code_replace(replacement, span.line, span.col_start, span.length),
+            ExecutionEvent::Step => step(),
+            _ => (),
+        }
+    }
+}
+
+/// Collects the explanation of every event, e.g. for a terminal tutor.
+#[derive(Debug, Clone, Default)]
+pub struct ExplanationLog {
+    pub lines: Vec<String>,
+}
+
+impl ExplanationLog {
+    pub fn shared() -> Rc<RefCell<ExplanationLog>> {
+        Rc::new(RefCell::new(ExplanationLog::default()))
+    }
+}
+
+impl ExecutionObserver for ExplanationLog {
+    fn on_event(&mut self, event: &ExecutionEvent, locale: Locale) {
+        if let Some(explanation) = locale.explain(event) {
+            self.lines.push(explanation);
+        }
+    }
+}
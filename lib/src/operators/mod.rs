@@ -0,0 +1,136 @@
+//! Which operand types each binary operator accepts and what type it produces.
+//!
+//! `TypeStack::binop` types expressions with these rules and `vm::math_operators` refuses
+//! everything else, so the static type of an operation is the type the VM produces.
+
+use crate::{ bytecode::BINOP, vm::StackValue, BuiltinTypes };
+use OperandType::{ Array, Bool, Decimal, Float, Int, Map, Object, String as Text };
+
+/// A type as far as operators are concerned, without element or field types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandType {
+    Bool,
+    /// Also `StackValue::BigInt`
+    Int,
+    Float,
+    Decimal,
+    String,
+    Array,
+    Object,
+    Map,
+}
+
+impl OperandType {
+    pub fn of_type(data_type: &BuiltinTypes) -> OperandType {
+        match data_type {
+            BuiltinTypes::Bool => OperandType::Bool,
+            BuiltinTypes::Int => OperandType::Int,
+            BuiltinTypes::Float => OperandType::Float,
+            BuiltinTypes::Decimal => OperandType::Decimal,
+            BuiltinTypes::String => OperandType::String,
+            BuiltinTypes::Array { .. } => OperandType::Array,
+            BuiltinTypes::Object { .. } => OperandType::Object,
+            BuiltinTypes::Map { .. } => OperandType::Map,
+        }
+    }
+
+    pub fn of_value(value: &StackValue) -> OperandType {
+        match value {
+            StackValue::Bool { .. } => OperandType::Bool,
+            StackValue::Int { .. } | StackValue::BigInt { .. } => OperandType::Int,
+            StackValue::Float { .. } => OperandType::Float,
+            StackValue::Decimal { .. } => OperandType::Decimal,
+            StackValue::String { .. } => OperandType::String,
+            StackValue::Array { .. } => OperandType::Array,
+            StackValue::Object { .. } => OperandType::Object,
+            StackValue::Map { .. } => OperandType::Map,
+        }
+    }
+
+    /// Operators only produce scalars, so those are the only types that are converted back
+    fn to_builtin(self) -> BuiltinTypes {
+        match self {
+            OperandType::Bool => BuiltinTypes::Bool,
+            OperandType::Int => BuiltinTypes::Int,
+            OperandType::Float => BuiltinTypes::Float,
+            OperandType::Decimal => BuiltinTypes::Decimal,
+            OperandType::String => BuiltinTypes::String,
+            OperandType::Array | OperandType::Object | OperandType::Map =>
+                unreachable!("no operator produces a collection"),
+        }
+    }
+}
+
+struct Rule {
+    operators: &'static [BINOP],
+    left: &'static [OperandType],
+    right: &'static [OperandType],
+    result: OperandType,
+}
+
+const fn rule(
+    operators: &'static [BINOP],
+    left: &'static [OperandType],
+    right: &'static [OperandType],
+    result: OperandType
+) -> Rule {
+    Rule { operators, left, right, result }
+}
+
+const ARITHMETIC: &[BINOP] = &[
+    BINOP::Add,
+    BINOP::Subtract,
+    BINOP::Multiply,
+    BINOP::Divide,
+    BINOP::Remainder,
+];
+const ORDERING: &[BINOP] = &[BINOP::LessThan, BINOP::LargerThan, BINOP::LessOrEq, BINOP::LargerOrEq];
+const EQUALITY: &[BINOP] = &[BINOP::Eq, BINOP::NotEq];
+const LOGIC: &[BINOP] = &[BINOP::And, BINOP::Or, BINOP::Xor];
+
+const NUMBERS: &[OperandType] = &[Int, Float, Decimal];
+/// Numbers are joined to text with the separator of the locale, see `Locale::format_value`
+const JOINABLE: &[OperandType] = &[Int, Float, Decimal, Text];
+const ANY: &[OperandType] = &[Bool, Int, Float, Decimal, Text, Array, Object, Map];
+
+/// The first rule that matches decides the result type.
+/// Integers stay exact with decimals, anything with a float becomes a float.
+const RULES: &[Rule] = &[
+    rule(ARITHMETIC, &[Int], &[Int], Int),
+    rule(ARITHMETIC, &[Int, Decimal], &[Int, Decimal], Decimal),
+    rule(ARITHMETIC, NUMBERS, NUMBERS, Float),
+    rule(&[BINOP::Add], &[Text], JOINABLE, Text),
+    rule(&[BINOP::Add], JOINABLE, &[Text], Text),
+    // Repetition, like `"ab" * 3`
+    rule(&[BINOP::Multiply], &[Text], &[Int], Text),
+    rule(&[BINOP::Multiply], &[Int], &[Text], Text),
+    rule(ORDERING, NUMBERS, NUMBERS, Bool),
+    rule(ORDERING, &[Bool], &[Bool], Bool),
+    rule(EQUALITY, NUMBERS, NUMBERS, Bool),
+    rule(EQUALITY, &[Bool], &[Bool], Bool),
+    rule(EQUALITY, &[Text], &[Text], Bool),
+    // Operands are converted with `VM::to_bool`
+    rule(LOGIC, ANY, ANY, Bool),
+];
+
+/// The type of `left operator right`, or `None` if the operator does not take these types.
+pub fn result_type(operator: BINOP, left: OperandType, right: OperandType) -> Option<OperandType> {
+    RULES.iter()
+        .find(|rule| {
+            rule.operators.contains(&operator) &&
+                rule.left.contains(&left) &&
+                rule.right.contains(&right)
+        })
+        .map(|rule| rule.result)
+}
+
+/// `result_type` for full types, as `TypeStack` needs it.
+pub fn result_builtin_type(
+    operator: BINOP,
+    left: &BuiltinTypes,
+    right: &BuiltinTypes
+) -> Option<BuiltinTypes> {
+    result_type(operator, OperandType::of_type(left), OperandType::of_type(right)).map(
+        OperandType::to_builtin
+    )
+}
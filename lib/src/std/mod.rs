@@ -1,27 +1,26 @@
+use num::{ BigInt, FromPrimitive, Signed };
 use rand::Rng;
+#[cfg(target_family = "wasm")]
 use wasm_bindgen::{ JsValue, prelude::wasm_bindgen };
-use futures::executor::block_on;
-
-// When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
-// allocator.
-#[cfg(feature = "wee_alloc")]
-#[global_allocator]
-static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 #[cfg(target_family = "wasm")]
 #[wasm_bindgen]
 extern "C" {
     fn alert(s: &str);
-    fn wasm_print(s: &str);
     fn code_replace(replace_with: &str, line: usize, column: usize, span: usize);
-    async fn wasm_input() -> JsValue;
     fn testfn() -> JsValue;
 }
 
 use crate::{
     BuiltinTypes,
     module::{ FuncArg, FunctionSignature },
-    vm::{ StackValue, vm::VM },
+    native::{ BuiltinFunction, NativeRegistry },
+    vm::{
+        decimal::format_decimal,
+        runtime_error::{ RuntimeError, RuntimeErrorKind },
+        StackValue,
+        vm::VM,
+    },
 };
 
 fn arg(name: &str, arg_type: BuiltinTypes) -> FuncArg {
@@ -33,53 +32,53 @@ fn arg(name: &str, arg_type: BuiltinTypes) -> FuncArg {
     }
 }
 
-fn stackvalue_to_string(value: StackValue) -> String {
+fn stackvalue_to_string(function: &'static str, value: StackValue) -> Result<String, RuntimeError> {
     match value {
-        StackValue::String { value } => value,
-        _ => unreachable!(),
+        StackValue::String { value } => Ok(value),
+        other => Err(RuntimeError::type_mismatch(function, &other, None)),
     }
 }
-fn stackvalue_to_int(value: StackValue) -> i64 {
+fn stackvalue_to_int(function: &'static str, value: StackValue) -> Result<i64, RuntimeError> {
     match value {
-        StackValue::Int { value } => value,
-        _ => unreachable!(),
+        StackValue::Int { value } => Ok(value),
+        other => Err(RuntimeError::type_mismatch(function, &other, None)),
     }
 }
-fn stackvalue_to_f64(value: StackValue) -> f64 {
-    match value {
-        StackValue::Int { value } => value as f64,
-        StackValue::Float { value } => value,
-        _ => unreachable!(),
-    }
+fn stackvalue_to_f64(function: &'static str, value: StackValue) -> Result<f64, RuntimeError> {
+    value.to_f64().ok_or_else(|| RuntimeError::type_mismatch(function, &value, None))
 }
-fn pop_arguments(vm: &mut VM, count: usize) -> Vec<StackValue> {
+fn pop_arguments(vm: &mut VM, count: usize) -> Result<Vec<StackValue>, RuntimeError> {
     let mut arguments = vec![];
     for _ in 0..count {
-        arguments.push(vm.pop());
+        arguments.push(vm.pop()?);
     }
-    arguments
+    Ok(arguments)
 }
 
-fn min_numeric(a: StackValue, b: StackValue) -> StackValue {
-    match (&a, &b) {
-        (StackValue::Int { value: va }, StackValue::Int { value: vb }) =>
-            StackValue::Int { value: (*va).min(*vb) },
-        _ => StackValue::Float {
-            value: stackvalue_to_f64(a).min(stackvalue_to_f64(b)),
-        },
+fn min_numeric(a: StackValue, b: StackValue) -> Result<StackValue, RuntimeError> {
+    match (a.to_big_int(), b.to_big_int()) {
+        (Some(va), Some(vb)) => Ok(StackValue::from_big_int(va.min(vb))),
+        _ =>
+            Ok(StackValue::Float {
+                value: stackvalue_to_f64("minimums", a)?.min(stackvalue_to_f64("minimums", b)?),
+            }),
     }
 }
 
-fn max_numeric(a: StackValue, b: StackValue) -> StackValue {
-    match (&a, &b) {
-        (StackValue::Int { value: va }, StackValue::Int { value: vb }) =>
-            StackValue::Int { value: (*va).max(*vb) },
-        _ => StackValue::Float {
-            value: stackvalue_to_f64(a).max(stackvalue_to_f64(b)),
-        },
+fn max_numeric(a: StackValue, b: StackValue) -> Result<StackValue, RuntimeError> {
+    match (a.to_big_int(), b.to_big_int()) {
+        (Some(va), Some(vb)) => Ok(StackValue::from_big_int(va.max(vb))),
+        _ =>
+            Ok(StackValue::Float {
+                value: stackvalue_to_f64("maksimums", a)?.max(stackvalue_to_f64("maksimums", b)?),
+            }),
     }
 }
 
+/// Std functions that take a value of any type. Their signatures declare the argument as `String`,
+/// which is what it becomes, and `typecheck` does not check it.
+pub const ANY_VALUE_FUNCTIONS: [&str; 1] = ["teksts"];
+
 pub fn get_std_functions() -> Vec<FunctionSignature> {
     let s = BuiltinTypes::String;
     let i = BuiltinTypes::Int;
@@ -98,6 +97,11 @@ pub fn get_std_functions() -> Vec<FunctionSignature> {
             args: vec![],
             return_type: Some(i.clone()),
         },
+        FunctionSignature {
+            name: "teksts".to_string(),
+            args: vec![arg("vērtība", s.clone())],
+            return_type: Some(s.clone()),
+        },
         FunctionSignature {
             name: "nejaušs".to_string(),
             args: vec![],
@@ -153,15 +157,16 @@ pub fn get_std_functions() -> Vec<FunctionSignature> {
             args: vec![],
             return_type: None,
         },
+        // Two ints give an int, which is accepted wherever a float is
         FunctionSignature {
             name: "minimums".to_string(),
-            args: vec![arg("a", i.clone()), arg("b", i.clone())],
-            return_type: None,
+            args: vec![arg("a", f.clone()), arg("b", f.clone())],
+            return_type: Some(f.clone()),
         },
         FunctionSignature {
             name: "maksimums".to_string(),
-            args: vec![arg("a", i.clone()), arg("b", i.clone())],
-            return_type: None,
+            args: vec![arg("a", f.clone()), arg("b", f.clone())],
+            return_type: Some(f.clone()),
         },
         FunctionSignature {
             name: "apaļot".to_string(),
@@ -191,149 +196,232 @@ pub fn get_std_functions() -> Vec<FunctionSignature> {
     ]
 }
 
-pub fn izvade(vm: &mut VM) {
-    let printable = &vm.format_for_print(true);
-    #[cfg(target_family = "wasm")]
-    wasm_print(printable);
-    print!("{}", printable);
-}
-pub fn izvadetp(vm: &mut VM) {
-    let printable = &vm.format_for_print(false);
-    #[cfg(target_family = "wasm")]
-    wasm_print(printable);
-    print!("{}", printable);
-}
-pub fn ievade(vm: &mut VM) {
-    #[cfg(target_family = "wasm")]
-    block_on(async {
-        let value = wasm_input().await.as_string().unwrap();
-        vm.push(&BuiltinTypes::String, &value);
-    });
-    #[cfg(not(target_family = "wasm"))]
+/// Arity, whether a value is returned and the implementation of every std function.
+/// The signatures in `get_std_functions` do not list every argument, so the stack effect is kept here.
+fn std_implementation(name: &str) -> Option<(usize, bool, BuiltinFunction)> {
+    let implementation: (usize, bool, BuiltinFunction) = match name {
+        "izvade" => (1, false, izvade),
+        "izvadetp" => (1, false, izvadetp),
+        "ievade" => (0, true, ievade),
+        "garums" => (1, true, garums),
+        "teksts" => (1, true, teksts),
+        "nejaušs" => (0, true, nejauss),
+        "nejaušs_robežās" => (2, true, nejauss_robezas),
+        "apgriezt" => (1, true, apgriezt),
+        "mazie_burti" => (1, true, mazie_burti),
+        "lielie_burti" => (1, true, lielie_burti),
+        "apakšvirkne" => (3, true, apaksvirkne),
+        "aizvietot" => (3, true, aizvietot),
+        "satur" => (2, true, satur),
+        "sākas_ar" => (2, true, sakas_ar),
+        "beidzas_ar" => (2, true, beidzas_ar),
+        "absolūtā_vērtība" => (1, true, absoluta_vertiba),
+        "minimums" => (2, true, minimums),
+        "maksimums" => (2, true, maksimums),
+        "apaļot" => (1, true, apalot),
+        "grīda" => (1, true, grida),
+        "griesti" => (1, true, griesti),
+        "pakāpināt" => (2, true, pakapinat),
+        "kvadrātsakne" => (1, true, kvadratsakne),
+        _ => {
+            return None;
+        }
+    };
+    Some(implementation)
+}
+
+pub(crate) fn register_std_functions(registry: &mut NativeRegistry) {
+    for signature in get_std_functions() {
+        let (arity, returns_value, function) = std_implementation(&signature.name).expect(
+            "every std function has an implementation"
+        );
+        registry.register_builtin(signature, arity, returns_value, function);
+    }
+}
+
+pub fn izvade(vm: &mut VM) -> Result<(), RuntimeError> {
+    let printable = vm.format_for_print(true);
+    vm.write_output(&printable);
+    Ok(())
+}
+pub fn izvadetp(vm: &mut VM) -> Result<(), RuntimeError> {
+    let printable = vm.format_for_print(false);
+    vm.write_output(&printable);
+    Ok(())
+}
+pub fn ievade(vm: &mut VM) -> Result<(), RuntimeError> {
     vm.input("");
+    Ok(())
 }
-pub fn nejauss_robezas(vm: &mut VM) {
-    let args = pop_arguments(vm, 2);
-    let value = rand::thread_rng().gen_range(
-        stackvalue_to_int(args[1].clone())..stackvalue_to_int(args[0].clone()),
-    );
+pub fn nejauss_robezas(vm: &mut VM) -> Result<(), RuntimeError> {
+    let args = pop_arguments(vm, 2)?;
+    let maks = stackvalue_to_int("nejaušs_robežās", args[0].clone())?;
+    let min = stackvalue_to_int("nejaušs_robežās", args[1].clone())?;
+    if min >= maks {
+        return Err(RuntimeErrorKind::EmptyRandomRange { min, max: maks }.into());
+    }
+    let value = rand::thread_rng().gen_range(min..maks);
     vm.push_stackvalue(StackValue::Int { value });
+    Ok(())
 }
-pub fn nejauss(vm: &mut VM) {
+pub fn nejauss(vm: &mut VM) -> Result<(), RuntimeError> {
     let value = rand::thread_rng().gen::<f64>();
     vm.push_stackvalue(StackValue::Float { value });
+    Ok(())
 }
 
-pub fn garums(vm: &mut VM) {
-    let value = vm.pop();
+pub fn garums(vm: &mut VM) -> Result<(), RuntimeError> {
+    let value = vm.pop()?;
     let length_value = match value {
         StackValue::Bool { value: _ } => 1,
         StackValue::Int { value } => value.to_string().len(),
+        StackValue::BigInt { value } => value.to_string().len(),
         StackValue::Float { value } => value.to_string().len(),
+        StackValue::Decimal { value } => format_decimal(&value, ",").len(),
         StackValue::String { value } => value.len(),
-        StackValue::Array { value } => value.len(),
-        StackValue::Object { value } => value.len(),
+        StackValue::Array { value } => value.borrow().len(),
+        StackValue::Object { value } => value.borrow().len(),
+        StackValue::Map { value } => value.borrow().len(),
     };
     vm.push_stackvalue(StackValue::Int { value: length_value as i64 });
+    Ok(())
 }
 
-pub fn apgriezt(vm: &mut VM) {
-    let teksts = stackvalue_to_string(pop_arguments(vm, 1)[0].clone());
+/// Any value as text, written like `izvade` writes it in the VM's locale.
+pub fn teksts(vm: &mut VM) -> Result<(), RuntimeError> {
+    let value = vm.pop()?;
+    let text = vm.locale.format_value(&value);
+    vm.push_stackvalue(StackValue::String { value: text });
+    Ok(())
+}
+
+pub fn apgriezt(vm: &mut VM) -> Result<(), RuntimeError> {
+    let teksts = stackvalue_to_string("apgriezt", pop_arguments(vm, 1)?[0].clone())?;
     vm.push_stackvalue(StackValue::String { value: teksts.trim().to_string() });
+    Ok(())
 }
 
-pub fn mazie_burti(vm: &mut VM) {
-    let teksts = stackvalue_to_string(pop_arguments(vm, 1)[0].clone());
+pub fn mazie_burti(vm: &mut VM) -> Result<(), RuntimeError> {
+    let teksts = stackvalue_to_string("mazie_burti", pop_arguments(vm, 1)?[0].clone())?;
     vm.push_stackvalue(StackValue::String { value: teksts.to_lowercase() });
+    Ok(())
 }
 
-pub fn lielie_burti(vm: &mut VM) {
-    let teksts = stackvalue_to_string(pop_arguments(vm, 1)[0].clone());
+pub fn lielie_burti(vm: &mut VM) -> Result<(), RuntimeError> {
+    let teksts = stackvalue_to_string("lielie_burti", pop_arguments(vm, 1)?[0].clone())?;
     vm.push_stackvalue(StackValue::String { value: teksts.to_uppercase() });
+    Ok(())
 }
 
-pub fn apaksvirkne(vm: &mut VM) {
-    let args = pop_arguments(vm, 3);
-    let garums = stackvalue_to_int(args[0].clone()) as usize;
-    let sakums = stackvalue_to_int(args[1].clone()) as usize;
-    let teksts = stackvalue_to_string(args[2].clone());
+pub fn apaksvirkne(vm: &mut VM) -> Result<(), RuntimeError> {
+    let args = pop_arguments(vm, 3)?;
+    let garums = stackvalue_to_int("apakšvirkne", args[0].clone())? as usize;
+    let sakums = stackvalue_to_int("apakšvirkne", args[1].clone())? as usize;
+    let teksts = stackvalue_to_string("apakšvirkne", args[2].clone())?;
     let chars: Vec<char> = teksts.chars().collect();
     let sub: String = chars.into_iter().skip(sakums).take(garums).collect();
     vm.push_stackvalue(StackValue::String { value: sub });
+    Ok(())
 }
 
-pub fn aizvietot(vm: &mut VM) {
-    let args = pop_arguments(vm, 3);
-    let uz = stackvalue_to_string(args[0].clone());
-    let no = stackvalue_to_string(args[1].clone());
-    let teksts = stackvalue_to_string(args[2].clone());
+pub fn aizvietot(vm: &mut VM) -> Result<(), RuntimeError> {
+    let args = pop_arguments(vm, 3)?;
+    let uz = stackvalue_to_string("aizvietot", args[0].clone())?;
+    let no = stackvalue_to_string("aizvietot", args[1].clone())?;
+    let teksts = stackvalue_to_string("aizvietot", args[2].clone())?;
     vm.push_stackvalue(StackValue::String { value: teksts.replace(&no, &uz) });
+    Ok(())
 }
 
-pub fn satur(vm: &mut VM) {
-    let args = pop_arguments(vm, 2);
-    let meklet = stackvalue_to_string(args[0].clone());
-    let teksts = stackvalue_to_string(args[1].clone());
+pub fn satur(vm: &mut VM) -> Result<(), RuntimeError> {
+    let args = pop_arguments(vm, 2)?;
+    let meklet = stackvalue_to_string("satur", args[0].clone())?;
+    let teksts = stackvalue_to_string("satur", args[1].clone())?;
     vm.push_stackvalue(StackValue::Bool { value: teksts.contains(&meklet) });
+    Ok(())
 }
 
-pub fn sakas_ar(vm: &mut VM) {
-    let args = pop_arguments(vm, 2);
-    let prefikss = stackvalue_to_string(args[0].clone());
-    let teksts = stackvalue_to_string(args[1].clone());
+pub fn sakas_ar(vm: &mut VM) -> Result<(), RuntimeError> {
+    let args = pop_arguments(vm, 2)?;
+    let prefikss = stackvalue_to_string("sākas_ar", args[0].clone())?;
+    let teksts = stackvalue_to_string("sākas_ar", args[1].clone())?;
     vm.push_stackvalue(StackValue::Bool { value: teksts.starts_with(&prefikss) });
+    Ok(())
 }
 
-pub fn beidzas_ar(vm: &mut VM) {
-    let args = pop_arguments(vm, 2);
-    let sufikss = stackvalue_to_string(args[0].clone());
-    let teksts = stackvalue_to_string(args[1].clone());
+pub fn beidzas_ar(vm: &mut VM) -> Result<(), RuntimeError> {
+    let args = pop_arguments(vm, 2)?;
+    let sufikss = stackvalue_to_string("beidzas_ar", args[0].clone())?;
+    let teksts = stackvalue_to_string("beidzas_ar", args[1].clone())?;
     vm.push_stackvalue(StackValue::Bool { value: teksts.ends_with(&sufikss) });
+    Ok(())
 }
 
-pub fn absoluta_vertiba(vm: &mut VM) {
-    let value = vm.pop();
+pub fn absoluta_vertiba(vm: &mut VM) -> Result<(), RuntimeError> {
+    let value = vm.pop()?;
     let result = match value {
-        StackValue::Int { value } => StackValue::Int { value: value.abs() },
+        StackValue::Int { value } =>
+            match value.checked_abs() {
+                Some(value) => StackValue::Int { value },
+                None => StackValue::from_big_int(BigInt::from(value).abs()),
+            }
+        StackValue::BigInt { value } => StackValue::from_big_int(value.abs()),
         StackValue::Float { value } => StackValue::Float { value: value.abs() },
-        _ => unreachable!(),
+        StackValue::Decimal { value } => StackValue::Decimal { value: value.abs() },
+        other => {
+            return Err(RuntimeError::type_mismatch("absolūtā_vērtība", &other, None));
+        }
     };
     vm.push_stackvalue(result);
+    Ok(())
 }
 
-pub fn minimums(vm: &mut VM) {
-    let args = pop_arguments(vm, 2);
-    vm.push_stackvalue(min_numeric(args[1].clone(), args[0].clone()));
+pub fn minimums(vm: &mut VM) -> Result<(), RuntimeError> {
+    let args = pop_arguments(vm, 2)?;
+    vm.push_stackvalue(min_numeric(args[1].clone(), args[0].clone())?);
+    Ok(())
 }
 
-pub fn maksimums(vm: &mut VM) {
-    let args = pop_arguments(vm, 2);
-    vm.push_stackvalue(max_numeric(args[1].clone(), args[0].clone()));
+pub fn maksimums(vm: &mut VM) -> Result<(), RuntimeError> {
+    let args = pop_arguments(vm, 2)?;
+    vm.push_stackvalue(max_numeric(args[1].clone(), args[0].clone())?);
+    Ok(())
 }
 
-pub fn apalot(vm: &mut VM) {
-    let x = stackvalue_to_f64(pop_arguments(vm, 1)[0].clone());
-    vm.push_stackvalue(StackValue::Int { value: x.round() as i64 });
+pub fn apalot(vm: &mut VM) -> Result<(), RuntimeError> {
+    let x = stackvalue_to_f64("apaļot", pop_arguments(vm, 1)?[0].clone())?;
+    let rounded = x.round();
+    // Past the `i64` range the result is a `BigInt`, NaN and infinities saturate like `as` does
+    let result = BigInt::from_f64(rounded).map_or(
+        StackValue::Int { value: rounded as i64 },
+        StackValue::from_big_int
+    );
+    vm.push_stackvalue(result);
+    Ok(())
 }
 
-pub fn grida(vm: &mut VM) {
-    let x = stackvalue_to_f64(pop_arguments(vm, 1)[0].clone());
+pub fn grida(vm: &mut VM) -> Result<(), RuntimeError> {
+    let x = stackvalue_to_f64("grīda", pop_arguments(vm, 1)?[0].clone())?;
     vm.push_stackvalue(StackValue::Float { value: x.floor() });
+    Ok(())
 }
 
-pub fn griesti(vm: &mut VM) {
-    let x = stackvalue_to_f64(pop_arguments(vm, 1)[0].clone());
+pub fn griesti(vm: &mut VM) -> Result<(), RuntimeError> {
+    let x = stackvalue_to_f64("griesti", pop_arguments(vm, 1)?[0].clone())?;
     vm.push_stackvalue(StackValue::Float { value: x.ceil() });
+    Ok(())
 }
 
-pub fn pakapinat(vm: &mut VM) {
-    let args = pop_arguments(vm, 2);
-    let eksponents = stackvalue_to_f64(args[0].clone());
-    let baze = stackvalue_to_f64(args[1].clone());
+pub fn pakapinat(vm: &mut VM) -> Result<(), RuntimeError> {
+    let args = pop_arguments(vm, 2)?;
+    let eksponents = stackvalue_to_f64("pakāpināt", args[0].clone())?;
+    let baze = stackvalue_to_f64("pakāpināt", args[1].clone())?;
     vm.push_stackvalue(StackValue::Float { value: baze.powf(eksponents) });
+    Ok(())
 }
 
-pub fn kvadratsakne(vm: &mut VM) {
-    let x = stackvalue_to_f64(pop_arguments(vm, 1)[0].clone());
+pub fn kvadratsakne(vm: &mut VM) -> Result<(), RuntimeError> {
+    let x = stackvalue_to_f64("kvadrātsakne", pop_arguments(vm, 1)?[0].clone())?;
     vm.push_stackvalue(StackValue::Float { value: x.sqrt() });
+    Ok(())
 }
@@ -0,0 +1,492 @@
+//! Static type checks for programs built by a frontend.
+//!
+//! Like the verifier, every reachable optcode is visited with an abstract stack, but here it holds
+//! the type of every value. Types that can not be known, e.g. after a call to a native without a
+//! signature, are `None` and are never reported. Where paths meet, types that differ become unknown.
+
+mod type_error;
+
+use std::collections::VecDeque;
+
+use crate::{
+    bytecode::{ BINOP, OPTCODE },
+    compiletime_helper::CompileTimeHelper,
+    module::{ FuncArg, FunctionSignature },
+    typestack::TypeStack,
+    verifier::stack_effect,
+    BuiltinTypes,
+    CelsiumProgram,
+    ObjectFieldType,
+};
+pub use type_error::{ Suggestion, TypeError, TypeErrorKind };
+
+/// The stack of one path, with the top at the end
+type Types = Vec<Option<BuiltinTypes>>;
+
+/// Checks the main block and every function body against the types the frontend declared in
+/// `helper`. Natives are checked with the signatures of the program's registry, which starts
+/// with those of `std::get_std_functions`.
+pub fn typecheck(program: &CelsiumProgram, helper: &CompileTimeHelper) -> Vec<TypeError> {
+    let checker = Checker { program, helper };
+    let mut errors = vec![];
+    // The linked main block is followed by the function bodies
+    let main_block = &program.main_block.bytecode;
+    let main_end = program.function_offsets.first().copied().unwrap_or(main_block.len());
+    checker.check_block(&main_block[..main_end], vec![], None, &mut errors);
+    for function in &program.functions {
+        let args = &function.signature.args;
+        // Without ids the arguments stay on the stack for the body to define
+        let entry = if args.iter().all(|arg| arg.local_var_id.is_some()) {
+            vec![]
+        } else {
+            args.iter()
+                .map(|arg| Some(arg.arg_type.clone()))
+                .collect()
+        };
+        checker.check_block(&function.body.bytecode, entry, Some(&function.signature), &mut errors);
+    }
+    errors
+}
+
+struct Checker<'a> {
+    program: &'a CelsiumProgram,
+    helper: &'a CompileTimeHelper,
+}
+
+impl Checker<'_> {
+    /// Finds the types before every reachable optcode, then checks the optcodes against them.
+    /// Returns are checked against `function`, which is `None` for the main block.
+    fn check_block(
+        &self,
+        bytecode: &[OPTCODE],
+        entry: Types,
+        function: Option<&FunctionSignature>,
+        errors: &mut Vec<TypeError>
+    ) {
+        if bytecode.is_empty() {
+            return;
+        }
+        let mut states: Vec<Option<Types>> = vec![None; bytecode.len() + 1];
+        states[0] = Some(entry);
+        let mut worklist = VecDeque::from([0]);
+        while let Some(index) = worklist.pop_front() {
+            if index >= bytecode.len() {
+                continue;
+            }
+            let types = states[index].clone().unwrap();
+            let mut scratch = vec![];
+            for (successor, types) in self.transfer(bytecode, index, types, function, &mut scratch) {
+                if merge_into(&mut states, successor, types) {
+                    worklist.push_back(successor);
+                }
+            }
+        }
+        for (index, types) in states[..bytecode.len()].iter().enumerate() {
+            if let Some(types) = types {
+                self.transfer(bytecode, index, types.clone(), function, errors);
+            }
+        }
+        // The linker ends every body with a `Return`
+        if let Some(function) = function {
+            if states[bytecode.len()].is_some() {
+                self.check_return(function, None, &mut |kind| {
+                    errors.push(self.error(bytecode, bytecode.len() - 1, kind))
+                });
+            }
+        }
+    }
+
+    /// Applies one optcode to `types` and returns the types before the optcodes that can run next.
+    fn transfer(
+        &self,
+        bytecode: &[OPTCODE],
+        index: usize,
+        mut types: Types,
+        function: Option<&FunctionSignature>,
+        errors: &mut Vec<TypeError>
+    ) -> Vec<(usize, Types)> {
+        let mut report = |kind| errors.push(self.error(bytecode, index, kind));
+        let optcode = &bytecode[index];
+        let pushed = match optcode {
+            OPTCODE::LoadInt { .. } | OPTCODE::GettArrayLength { .. } => Some(BuiltinTypes::Int),
+            OPTCODE::LoadBool { .. } => Some(BuiltinTypes::Bool),
+            OPTCODE::LoadString { .. } => Some(BuiltinTypes::String),
+            OPTCODE::LoadFloat { .. } => Some(BuiltinTypes::Float),
+            OPTCODE::LoadDecimal { .. } => Some(BuiltinTypes::Decimal),
+            OPTCODE::LoadVar { id, .. } => self.helper.get_var_type(*id),
+            OPTCODE::Not => {
+                types.pop();
+                Some(BuiltinTypes::Bool)
+            }
+            OPTCODE::CopyValue => pop(&mut types),
+            OPTCODE::GetIndex => {
+                let index_type = pop(&mut types);
+                let target = pop(&mut types);
+                if let Some(found) = index_type {
+                    if found != BuiltinTypes::Int {
+                        report(TypeErrorKind::IndexType { found });
+                    }
+                }
+                match target {
+                    Some(BuiltinTypes::Array { element_type, .. }) => Some(*element_type),
+                    Some(BuiltinTypes::String) => Some(BuiltinTypes::String),
+                    Some(found) => {
+                        report(TypeErrorKind::NotIndexable { found });
+                        None
+                    }
+                    None => None,
+                }
+            }
+            OPTCODE::GetObjectField { field_name } =>
+                match pop(&mut types) {
+                    Some(BuiltinTypes::Object { fields }) => {
+                        let field = fields.into_iter().find(|field| &field.name == field_name);
+                        if field.is_none() {
+                            report(TypeErrorKind::MissingField { field_name: field_name.clone() });
+                        }
+                        field.map(|field| field.data_type)
+                    }
+                    Some(found) => {
+                        report(TypeErrorKind::NotAnObject { field_name: field_name.clone(), found });
+                        None
+                    }
+                    None => None,
+                }
+            OPTCODE::GetMapValue => {
+                types.pop();
+                match pop(&mut types) {
+                    Some(BuiltinTypes::Map { key: _, value }) => Some(*value),
+                    _ => None,
+                }
+            }
+            OPTCODE::MapContains => {
+                pop_many(&mut types, 2);
+                Some(BuiltinTypes::Bool)
+            }
+            OPTCODE::GetMapKeys =>
+                match pop(&mut types) {
+                    Some(BuiltinTypes::Map { key, value: _ }) =>
+                        Some(BuiltinTypes::Array { element_type: key, length: None }),
+                    _ => None,
+                }
+            OPTCODE::CreateArray { init_values_count } => {
+                let elements = pop_many(&mut types, *init_values_count);
+                match elements.first() {
+                    Some(Some(first)) if elements.iter().all(|element| element.as_ref() == Some(first)) =>
+                        Some(BuiltinTypes::Array {
+                            element_type: Box::new(first.clone()),
+                            length: Some(*init_values_count),
+                        }),
+                    _ => None,
+                }
+            }
+            OPTCODE::CreateObject { field_names } => {
+                let values = pop_many(&mut types, field_names.len());
+                let fields: Option<Vec<ObjectFieldType>> = field_names
+                    .iter()
+                    .zip(values)
+                    .map(|(name, data_type)| {
+                        Some(ObjectFieldType { name: name.clone(), data_type: data_type? })
+                    })
+                    .collect();
+                fields.map(|fields| BuiltinTypes::Object { fields })
+            }
+            OPTCODE::DefineVar { id, .. } | OPTCODE::AssignVar { id } => {
+                let found = pop(&mut types);
+                if let (Some(found), Some(expected)) = (found, self.helper.get_var_type(*id)) {
+                    if !assignable(&found, &expected) {
+                        let name = self.variable_name(*id);
+                        report(TypeErrorKind::VariableType { name, expected, found });
+                    }
+                }
+                return self.successors(bytecode, index, types);
+            }
+            OPTCODE::CallFunction { id, .. } => {
+                self.call_function(*id, &mut types, &mut report);
+                return self.successors(bytecode, index, types);
+            }
+            OPTCODE::JumpToFunction { function, .. } => {
+                match self.program.functions.get(*function) {
+                    Some(function) => self.call_function(function.id, &mut types, &mut report),
+                    None => types.clear(),
+                }
+                return self.successors(bytecode, index, types);
+            }
+            OPTCODE::CallSpecialFunction { function: name } |
+            OPTCODE::CallNativeFunction { function_name: name, .. } => {
+                let natives = &self.program.natives;
+                match natives.resolve(name).and_then(|index| natives.get(index)) {
+                    Some(native) => {
+                        let signature = &native.signature;
+                        let found = pop_many(&mut types, native.arity);
+                        // Some std signatures do not list their arguments
+                        let any_value = crate::std::ANY_VALUE_FUNCTIONS.contains(&signature.name.as_str());
+                        if signature.args.len() == native.arity && !any_value {
+                            check_arguments(&signature.name, &signature.args, found, &mut report);
+                        }
+                        if native.returns_value {
+                            types.push(signature.return_type.clone());
+                        }
+                    }
+                    // Unknown natives leave the stack depth unknown
+                    None => types.clear(),
+                }
+                return self.successors(bytecode, index, types);
+            }
+            OPTCODE::PushToTestingStack { duplicate_stackvalue } => {
+                if !duplicate_stackvalue {
+                    types.pop();
+                }
+                return self.successors(bytecode, index, types);
+            }
+            OPTCODE::Return | OPTCODE::ReturnValue => {
+                if let Some(function) = function {
+                    let returned = match optcode {
+                        OPTCODE::ReturnValue => Some(pop(&mut types)),
+                        _ => None,
+                    };
+                    self.check_return(function, returned, &mut report);
+                }
+                return self.successors(bytecode, index, types);
+            }
+            OPTCODE::RangeNext { .. } => Some(BuiltinTypes::Int),
+            OPTCODE::ForEachNext { .. } =>
+                match types.iter().nth_back(1).cloned().flatten() {
+                    Some(BuiltinTypes::Array { element_type, .. }) => Some(*element_type),
+                    Some(BuiltinTypes::String) => Some(BuiltinTypes::String),
+                    Some(BuiltinTypes::Map { key, value: _ }) => Some(*key),
+                    _ => None,
+                }
+            _ =>
+                match operator(optcode) {
+                    Some(operator) => {
+                        let right = pop(&mut types);
+                        let left = pop(&mut types);
+                        self.operator_type(operator, left, right, &mut report)
+                    }
+                    None => {
+                        let (pops, pushes) = stack_effect(optcode);
+                        pop_many(&mut types, pops);
+                        types.extend((0..pushes).map(|_| None));
+                        return self.successors(bytecode, index, types);
+                    }
+                }
+        };
+        types.push(pushed);
+        self.successors(bytecode, index, types)
+    }
+
+    fn operator_type(
+        &self,
+        operator: BINOP,
+        left: Option<BuiltinTypes>,
+        right: Option<BuiltinTypes>,
+        report: &mut impl FnMut(TypeErrorKind)
+    ) -> Option<BuiltinTypes> {
+        let (left, right) = (left?, right?);
+        let mut type_stack = TypeStack::new();
+        type_stack.push(left.clone());
+        type_stack.push(right.clone());
+        match type_stack.binop(operator) {
+            Ok(result) => Some(result),
+            Err(error) => {
+                report(error.kind);
+                None
+            }
+        }
+    }
+
+    /// Checks the arguments of a user defined function and pushes its return type
+    fn call_function(
+        &self,
+        id: usize,
+        types: &mut Types,
+        report: &mut impl FnMut(TypeErrorKind)
+    ) {
+        let Some(function) = self.helper.defined_functions.iter().find(|function| function.id == id) else {
+            types.clear();
+            return;
+        };
+        let found = pop_many(types, function.arguments.len());
+        check_arguments(&function.name, &function.arguments, found, report);
+        if let Some(return_type) = &function.return_type {
+            types.push(Some(return_type.clone()));
+        }
+    }
+
+    /// `returned` is `None` for a `Return` without a value and `Some(None)` for a value of unknown type
+    fn check_return(
+        &self,
+        function: &FunctionSignature,
+        returned: Option<Option<BuiltinTypes>>,
+        report: &mut impl FnMut(TypeErrorKind)
+    ) {
+        let name = function.name.clone();
+        match (&function.return_type, returned) {
+            (Some(expected), None) =>
+                report(TypeErrorKind::MissingReturnValue { function: name, expected: expected.clone() }),
+            (Some(expected), Some(Some(found))) if !assignable(&found, expected) =>
+                report(TypeErrorKind::ReturnType { function: name, expected: Some(expected.clone()), found }),
+            (None, Some(Some(found))) =>
+                report(TypeErrorKind::ReturnType { function: name, expected: None, found }),
+            _ => {}
+        }
+    }
+
+    /// Where execution continues after the optcode. The loop heads pop their state when they exit.
+    fn successors(&self, bytecode: &[OPTCODE], index: usize, types: Types) -> Vec<(usize, Types)> {
+        let next = index + 1;
+        let jump = |target: Option<usize>, types: Types| {
+            match target {
+                Some(target) if target <= bytecode.len() => vec![(target, types)],
+                // Reported by the verifier
+                _ => vec![],
+            }
+        };
+        match &bytecode[index] {
+            OPTCODE::JumpIfFalse { steps, .. } => {
+                let mut successors = jump(next.checked_add(*steps), types.clone());
+                successors.push((next, types));
+                successors
+            }
+            OPTCODE::RangeNext { steps } | OPTCODE::ForEachNext { steps } => {
+                let state = if matches!(bytecode[index], OPTCODE::RangeNext { .. }) { 3 } else { 2 };
+                let mut exit = types.clone();
+                pop_many(&mut exit, state + 1);
+                let mut successors = jump(next.checked_add(*steps), exit);
+                successors.push((next, types));
+                successors
+            }
+            OPTCODE::Jump { steps } => jump(next.checked_add(*steps), types),
+            OPTCODE::JumpBack { steps } => jump(next.checked_sub(*steps), types),
+            OPTCODE::Return | OPTCODE::ReturnValue | OPTCODE::Break { .. } | OPTCODE::Continue { .. } =>
+                vec![],
+            _ => vec![(next, types)],
+        }
+    }
+
+    fn variable_name(&self, id: usize) -> String {
+        let helper = self.helper;
+        let variables = helper.defined_variables.iter().map(|variable| (variable.id, &variable.name));
+        let objects = helper.defined_objects.iter().map(|object| (object.id, &object.name));
+        let arrays = helper.defined_arrays.iter().map(|array| (array.id, &array.name));
+        variables
+            .chain(objects)
+            .chain(arrays)
+            .find(|(variable_id, _)| *variable_id == id)
+            .map_or_else(|| format!("#{}", id), |(_, name)| name.clone())
+    }
+
+    /// Optcodes without a node take the one of the closest optcode before them
+    fn error(&self, bytecode: &[OPTCODE], index: usize, kind: TypeErrorKind) -> TypeError {
+        let node_id = bytecode[..=index].iter().rev().find_map(OPTCODE::node_id);
+        let span = node_id.and_then(|id| self.program.node_locations_by_id.get(&id).cloned());
+        TypeError { kind, node_id, span }
+    }
+}
+
+fn check_arguments(
+    function: &str,
+    arguments: &[FuncArg],
+    found: Types,
+    report: &mut impl FnMut(TypeErrorKind)
+) {
+    for (argument, found) in arguments.iter().zip(found) {
+        let Some(found) = found else {
+            continue;
+        };
+        if !assignable(&found, &argument.arg_type) {
+            report(TypeErrorKind::ArgumentType {
+                function: function.to_string(),
+                argument: argument.name.clone(),
+                expected: argument.arg_type.clone(),
+                found,
+            });
+        }
+    }
+}
+
+/// Whether a value of type `found` can be stored where `expected` is declared.
+/// Like `StackValue::is_of_type`, ints are accepted as floats and decimals and array lengths are not checked.
+fn assignable(found: &BuiltinTypes, expected: &BuiltinTypes) -> bool {
+    match (found, expected) {
+        (BuiltinTypes::Int, BuiltinTypes::Float | BuiltinTypes::Decimal) => true,
+        (
+            BuiltinTypes::Array { element_type: found, length: _ },
+            BuiltinTypes::Array { element_type: expected, length: _ },
+        ) => assignable(found, expected),
+        (BuiltinTypes::Object { fields: found }, BuiltinTypes::Object { fields: expected }) =>
+            found.len() == expected.len() &&
+                expected.iter().all(|expected| {
+                    found
+                        .iter()
+                        .any(
+                            |found|
+                                found.name == expected.name &&
+                                assignable(&found.data_type, &expected.data_type)
+                        )
+                }),
+        (
+            BuiltinTypes::Map { key: found_key, value: found_value },
+            BuiltinTypes::Map { key: expected_key, value: expected_value },
+        ) => assignable(found_key, expected_key) && assignable(found_value, expected_value),
+        _ => found == expected,
+    }
+}
+
+fn operator(optcode: &OPTCODE) -> Option<BINOP> {
+    let operator = match optcode {
+        OPTCODE::Add { .. } => BINOP::Add,
+        OPTCODE::Subtract { .. } => BINOP::Subtract,
+        OPTCODE::Multiply { .. } => BINOP::Multiply,
+        OPTCODE::Divide { .. } => BINOP::Divide,
+        OPTCODE::Remainder { .. } => BINOP::Remainder,
+        OPTCODE::LessThan { .. } => BINOP::LessThan,
+        OPTCODE::LargerThan { .. } => BINOP::LargerThan,
+        OPTCODE::LessOrEq { .. } => BINOP::LessOrEq,
+        OPTCODE::LargerOrEq { .. } => BINOP::LargerOrEq,
+        OPTCODE::NotEq { .. } => BINOP::NotEq,
+        OPTCODE::Eq { .. } => BINOP::Eq,
+        OPTCODE::And { .. } => BINOP::And,
+        OPTCODE::Or { .. } => BINOP::Or,
+        OPTCODE::Xor { .. } => BINOP::Xor,
+        _ => {
+            return None;
+        }
+    };
+    Some(operator)
+}
+
+/// Values below the known part of the stack have an unknown type
+fn pop(types: &mut Types) -> Option<BuiltinTypes> {
+    types.pop().flatten()
+}
+
+/// The top `count` types in the order they were pushed
+fn pop_many(types: &mut Types, count: usize) -> Types {
+    let mut popped = types.split_off(types.len().saturating_sub(count));
+    while popped.len() < count {
+        popped.insert(0, None);
+    }
+    popped
+}
+
+/// The stacks are aligned at the top, since a path may know fewer values than the other
+fn merge_into(states: &mut [Option<Types>], index: usize, types: Types) -> bool {
+    let merged = match &states[index] {
+        Some(existing) => {
+            let length = existing.len().min(types.len());
+            existing[existing.len() - length..]
+                .iter()
+                .zip(&types[types.len() - length..])
+                .map(|(a, b)| if a == b { a.clone() } else { None })
+                .collect()
+        }
+        None => types,
+    };
+    if states[index].as_ref() == Some(&merged) {
+        return false;
+    }
+    states[index] = Some(merged);
+    true
+}
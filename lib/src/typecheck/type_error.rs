@@ -0,0 +1,168 @@
+use std::fmt;
+
+use crate::{ block::TextSpan, bytecode::BINOP, locale::Locale, BuiltinTypes };
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeErrorKind {
+    /// Operand types that `TypeStack` has no rule for
+    Operator {
+        operator: BINOP,
+        left: BuiltinTypes,
+        right: BuiltinTypes,
+    },
+    /// `TypeStack::binop` with fewer types on the stack than the operator takes
+    StackUnderflow {
+        operator: BINOP,
+        needed: usize,
+        available: usize,
+    },
+    ArgumentType {
+        function: String,
+        argument: String,
+        expected: BuiltinTypes,
+        found: BuiltinTypes,
+    },
+    /// A `DefineVar` or `AssignVar` with a value of another type than the variable was declared with
+    VariableType {
+        name: String,
+        expected: BuiltinTypes,
+        found: BuiltinTypes,
+    },
+    NotIndexable {
+        found: BuiltinTypes,
+    },
+    IndexType {
+        found: BuiltinTypes,
+    },
+    NotAnObject {
+        field_name: String,
+        found: BuiltinTypes,
+    },
+    MissingField {
+        field_name: String,
+    },
+    /// A `ReturnValue` with another type than the function returns.
+    /// `expected` is `None` for functions without a return type.
+    ReturnType {
+        function: String,
+        expected: Option<BuiltinTypes>,
+        found: BuiltinTypes,
+    },
+    /// A `Return`, or the end of the body, in a function with a return type
+    MissingReturnValue {
+        function: String,
+        expected: BuiltinTypes,
+    },
+}
+
+/// A hint for beginners on how to fix an operator error, worded by `Locale::suggestion`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Suggestion {
+    /// An array, object or map was used where only one of its values fits
+    UsePart,
+    /// Text joined with a value that `+` can not join
+    ConvertToText,
+    RepeatByInt,
+    JoinWithPlus,
+}
+
+/// A problem found by `typecheck`. The location is the one of the closest optcode with a node.
+/// Errors from `TypeStack` have no location, the frontend knows where it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub kind: TypeErrorKind,
+    pub node_id: Option<usize>,
+    pub span: Option<TextSpan>,
+}
+
+impl From<TypeErrorKind> for TypeError {
+    fn from(kind: TypeErrorKind) -> Self {
+        TypeError { kind, node_id: None, span: None }
+    }
+}
+
+impl TypeErrorKind {
+    /// A hint for beginners on how to fix an operator error
+    pub fn suggestion(&self) -> Option<Suggestion> {
+        let TypeErrorKind::Operator { operator, left, right } = self else {
+            return None;
+        };
+        let either = |check: fn(&BuiltinTypes) -> bool| check(left) || check(right);
+        let is_collection = |data_type: &BuiltinTypes| {
+            matches!(
+                data_type,
+                BuiltinTypes::Array { .. } | BuiltinTypes::Object { .. } | BuiltinTypes::Map { .. }
+            )
+        };
+        let is_text = |data_type: &BuiltinTypes| *data_type == BuiltinTypes::String;
+        match operator {
+            _ if either(is_collection) => Some(Suggestion::UsePart),
+            BINOP::Add if either(is_text) => Some(Suggestion::ConvertToText),
+            BINOP::Multiply if either(is_text) => Some(Suggestion::RepeatByInt),
+            BINOP::Subtract | BINOP::Divide | BINOP::Remainder if either(is_text) =>
+                Some(Suggestion::JoinWithPlus),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TypeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeErrorKind::Operator { operator, left, right } => {
+                write!(f, "Cannot do '{}' with {:?} and {:?}", operator.symbol(), left, right)?;
+                match self.suggestion() {
+                    Some(suggestion) => write!(f, ", {}", Locale::English.suggestion(suggestion)),
+                    None => Ok(()),
+                }
+            }
+            TypeErrorKind::StackUnderflow { operator, needed, available } =>
+                write!(
+                    f,
+                    "'{}' needs {} operand types, but the type stack has {}",
+                    operator.symbol(),
+                    needed,
+                    available
+                ),
+            TypeErrorKind::ArgumentType { function, argument, expected, found } =>
+                write!(
+                    f,
+                    "Argument \"{}\" of \"{}\" should be {:?}, but is {:?}",
+                    argument,
+                    function,
+                    expected,
+                    found
+                ),
+            TypeErrorKind::VariableType { name, expected, found } =>
+                write!(f, "Variable \"{}\" is {:?}, but gets a value of {:?}", name, expected, found),
+            TypeErrorKind::NotIndexable { found } => write!(f, "Cannot index into {:?}", found),
+            TypeErrorKind::IndexType { found } => write!(f, "Index should be Int, but is {:?}", found),
+            TypeErrorKind::NotAnObject { field_name, found } =>
+                write!(f, "Cannot get field \"{}\" of {:?}", field_name, found),
+            TypeErrorKind::MissingField { field_name } =>
+                write!(f, "Object has no field named \"{}\"", field_name),
+            TypeErrorKind::ReturnType { function, expected: Some(expected), found } =>
+                write!(f, "Function \"{}\" should return {:?}, but returns {:?}", function, expected, found),
+            TypeErrorKind::ReturnType { function, expected: None, found } =>
+                write!(f, "Function \"{}\" has no return type, but returns {:?}", function, found),
+            TypeErrorKind::MissingReturnValue { function, expected } =>
+                write!(
+                    f,
+                    "Function \"{}\" should return {:?}, but can end without a value",
+                    function,
+                    expected
+                ),
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.span {
+            Some(span) => write!(f, "{} (line {}, column {})", self.kind, span.line, span.col_start),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+impl std::error::Error for TypeError {}
@@ -0,0 +1,64 @@
+use crate::BuiltinTypes;
+
+use super::TypeStack;
+
+fn is_key_type(data_type: &BuiltinTypes) -> bool {
+    matches!(data_type, BuiltinTypes::Int | BuiltinTypes::Bool | BuiltinTypes::String)
+}
+
+impl TypeStack {
+    /// Pops `count` key and value types, each pushed key first, and pushes the map type.
+    /// All keys and all values must have the same type. An empty map has nothing to infer
+    /// the type from, so the declared type has to be pushed instead.
+    pub fn create_map(&mut self, count: usize) -> Option<BuiltinTypes> {
+        let mut key_type = None;
+        let mut value_type = None;
+        for _ in 0..count {
+            let value = self.stack.pop_back()?;
+            let key = self.stack.pop_back()?;
+            if !is_key_type(&key) ||
+                key_type.get_or_insert(key.clone()) != &key ||
+                value_type.get_or_insert(value.clone()) != &value
+            {
+                return None;
+            }
+        }
+        let map_type = BuiltinTypes::Map {
+            key: Box::new(key_type?),
+            value: Box::new(value_type?),
+        };
+        self.stack.push_back(map_type.clone());
+        Some(map_type)
+    }
+    /// `map[key]`, pushes the value type.
+    pub fn map_value(&mut self) -> Option<BuiltinTypes> {
+        let (_, value) = self.pop_map_and_key()?;
+        self.stack.push_back(value.clone());
+        Some(value)
+    }
+    pub fn map_contains(&mut self) -> Option<BuiltinTypes> {
+        self.pop_map_and_key()?;
+        self.stack.push_back(BuiltinTypes::Bool);
+        Some(BuiltinTypes::Bool)
+    }
+    /// Pushes an array of the key type.
+    pub fn map_keys(&mut self) -> Option<BuiltinTypes> {
+        let BuiltinTypes::Map { key, value: _ } = self.stack.pop_back()? else {
+            return None;
+        };
+        let keys = BuiltinTypes::Array { element_type: key, length: None };
+        self.stack.push_back(keys.clone());
+        Some(keys)
+    }
+    /// Pops a key and the map under it. Returns the map's key and value types if the key fits.
+    fn pop_map_and_key(&mut self) -> Option<(BuiltinTypes, BuiltinTypes)> {
+        let key = self.stack.pop_back()?;
+        let BuiltinTypes::Map { key: key_type, value } = self.stack.pop_back()? else {
+            return None;
+        };
+        if *key_type != key {
+            return None;
+        }
+        Some((*key_type, *value))
+    }
+}
@@ -1,155 +1,39 @@
-use crate::BuiltinTypes;
+use crate::{
+    bytecode::BINOP,
+    operators::result_builtin_type,
+    typecheck::{ TypeError, TypeErrorKind },
+    BuiltinTypes,
+};
 
 use super::TypeStack;
 
-impl TypeStack{
-    pub(super) fn add(&mut self) -> Option<BuiltinTypes> {
-        let a = self.stack.pop_back().unwrap();
-        let b = self.stack.pop_back().unwrap();
-        let result = match a {
-            BuiltinTypes::Int =>
-                match b {
-                    BuiltinTypes::Int => Some(BuiltinTypes::Int),
-                    BuiltinTypes::Bool => {
-                        return None;
-                    }
-                    BuiltinTypes::String => Some(BuiltinTypes::String),
-                    BuiltinTypes::Object { fields: _ } => {
-                        return None;
-                    }
-                    BuiltinTypes::Float => Some(BuiltinTypes::Float),
-                    BuiltinTypes::Array { element_type: _, length:_ } => None,
-                }
-            BuiltinTypes::Bool => None,
-            BuiltinTypes::String =>
-                match b {
-                    BuiltinTypes::Int => Some(BuiltinTypes::String),
-                    BuiltinTypes::Bool => {
-                        return None;
-                    }
-                    BuiltinTypes::String => Some(BuiltinTypes::String),
-                    BuiltinTypes::Object { fields: _ } => {
-                        return None;
-                    }
-                    BuiltinTypes::Float => Some(BuiltinTypes::String),
-                    BuiltinTypes::Array { element_type: _,length:_ } => None,
-                }
-            BuiltinTypes::Object { fields: _ } => None,
-            BuiltinTypes::Float =>
-                match b {
-                    BuiltinTypes::Int => Some(BuiltinTypes::Float),
-                    BuiltinTypes::Bool => {
-                        return None;
-                    }
-                    BuiltinTypes::String => Some(BuiltinTypes::String),
-                    BuiltinTypes::Object { fields: _ } => {
-                        return None;
-                    }
-                    BuiltinTypes::Float => Some(BuiltinTypes::Float),
-                    BuiltinTypes::Array { element_type: _,length:_ } => None,
-                }
-            BuiltinTypes::Array { element_type: _,length:_ } => None,
-        };
-        return result;
+impl TypeStack {
+    /// Pops the operand types and pushes the type of the result, see `operators` for the rules.
+    /// On an error the operands stay popped, and on a stack underflow nothing is popped.
+    // Errors end the type checking of an expression, so their size does not matter
+    #[allow(clippy::result_large_err)]
+    pub fn binop(&mut self, binop: BINOP) -> Result<BuiltinTypes, TypeError> {
+        if binop == BINOP::Not {
+            self.stack.push_back(BuiltinTypes::Bool);
+            return Ok(BuiltinTypes::Bool);
+        }
+        if self.stack.len() < 2 {
+            return Err(
+                (TypeErrorKind::StackUnderflow {
+                    operator: binop,
+                    needed: 2,
+                    available: self.stack.len(),
+                }).into()
+            );
+        }
+        let right = self.stack.pop_back().unwrap();
+        let left = self.stack.pop_back().unwrap();
+        match result_builtin_type(binop, &left, &right) {
+            Some(result_type) => {
+                self.stack.push_back(result_type.clone());
+                Ok(result_type)
+            }
+            None => Err((TypeErrorKind::Operator { operator: binop, left, right }).into()),
+        }
     }
-    pub(super)fn subtract(&mut self) -> Option<BuiltinTypes> {
-        let a = self.stack.pop_back().unwrap();
-        let b = self.stack.pop_back().unwrap();
-        let result = match a {
-            BuiltinTypes::Int =>
-                match b {
-                    BuiltinTypes::Int => Some(BuiltinTypes::Int),
-                    BuiltinTypes::Bool => {
-                        return None;
-                    }
-                    BuiltinTypes::String => {
-                        return None;
-                    }
-                    BuiltinTypes::Object { fields: _ } => {
-                        return None;
-                    }
-                    BuiltinTypes::Float => Some(BuiltinTypes::Float),
-                    BuiltinTypes::Array { element_type: _,length:_ } => None,
-                }
-            BuiltinTypes::Bool => None,
-            BuiltinTypes::String => None,
-            BuiltinTypes::Object { fields: _ } => None,
-            BuiltinTypes::Array { element_type: _,length:_ } => None,
-
-            BuiltinTypes::Float =>
-                match b {
-                    BuiltinTypes::Int => Some(BuiltinTypes::Float),
-                    BuiltinTypes::Bool => {
-                        return None;
-                    }
-                    BuiltinTypes::String => {
-                        return None;
-                    }
-                    BuiltinTypes::Object { fields: _ } => {
-                        return None;
-                    }
-                    BuiltinTypes::Float => Some(BuiltinTypes::Float),
-                    BuiltinTypes::Array { element_type: _,length:_ } => None,
-                }
-        };
-        return result;
-    }
-    pub(super)fn compare(&mut self) -> Option<BuiltinTypes> {
-        let a = self.stack.pop_back().unwrap();
-        let b = self.stack.pop_back().unwrap();
-
-        let result = match a {
-            BuiltinTypes::Int =>
-                match b {
-                    BuiltinTypes::Int => Some(BuiltinTypes::Bool),
-                    BuiltinTypes::Bool => {
-                        return None;
-                    }
-                    BuiltinTypes::String => {
-                        return None;
-                    }
-                    BuiltinTypes::Object { fields: _ } => {
-                        return None;
-                    }
-                    BuiltinTypes::Float => Some(BuiltinTypes::Bool),
-                    BuiltinTypes::Array { element_type: _,length:_ } => None,
-                }
-            BuiltinTypes::Bool =>
-                match b {
-                    BuiltinTypes::Int => {
-                        return None;
-                    }
-                    BuiltinTypes::Bool => Some(BuiltinTypes::Bool),
-                    BuiltinTypes::String => {
-                        return None;
-                    }
-                    BuiltinTypes::Object { fields: _ } => {
-                        return None;
-                    }
-                    BuiltinTypes::Float => {
-                        return None;
-                    }
-                    BuiltinTypes::Array { element_type: _,length:_ } => None,
-                }
-            BuiltinTypes::String => None,
-            BuiltinTypes::Object { fields: _ } => None,
-            BuiltinTypes::Float =>
-                match b {
-                    BuiltinTypes::Int => Some(BuiltinTypes::Bool),
-                    BuiltinTypes::Bool => {
-                        return None;
-                    }
-                    BuiltinTypes::String => {
-                        return None;
-                    }
-                    BuiltinTypes::Object { fields: _ } => {
-                        return None;
-                    }
-                    BuiltinTypes::Float => Some(BuiltinTypes::Bool),
-                    BuiltinTypes::Array { element_type: _,length:_ } => None,
-                }
-            BuiltinTypes::Array { element_type: _,length:_ } => None,
-        };
-        return result;
-    }
-}
\ No newline at end of file
+}
@@ -1,11 +1,18 @@
 use std::collections::LinkedList;
 mod mathops;
-use crate::{bytecode::BINOP, BuiltinTypes};
+mod maps;
+use crate::BuiltinTypes;
 
 #[derive(Debug, Clone)]
 pub struct TypeStack {
     stack: LinkedList<BuiltinTypes>,
 }
+impl Default for TypeStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TypeStack {
     pub fn new() -> TypeStack {
         TypeStack {
@@ -24,33 +31,4 @@ impl TypeStack {
     pub fn peek_level(self, depth: usize) -> Option<BuiltinTypes> {
         self.stack.iter().nth_back(depth).cloned()
     }
-    pub fn binop(&mut self, binop: BINOP) -> Option<BuiltinTypes> {
-        /*
-        Subtraction, multiplication, division and getting remainder
-        are identical in the way types ar handled
-        */
-        let result_type = match binop {
-            BINOP::Add => self.add(),
-            BINOP::Subtract => self.subtract(),
-            BINOP::Multiply => self.subtract(),
-            BINOP::Divide => self.subtract(),
-            BINOP::Remainder => self.subtract(),
-            BINOP::LessThan => self.compare(),
-            BINOP::LargerThan => self.compare(),
-            BINOP::LessOrEq => self.compare(),
-            BINOP::LargerOrEq => self.compare(),
-            BINOP::NotEq => self.compare(),
-            BINOP::Eq => self.compare(),
-            BINOP::And => self.compare(),
-            BINOP::Or => self.compare(),
-            BINOP::Xor => self.compare(),
-            BINOP::Not => Some(BuiltinTypes::Bool)
-        };
-        if result_type.is_some() {
-            self.stack.push_back(result_type.clone().unwrap());
-            return result_type;
-        } else {
-            return None;
-        }
-    }
 }
@@ -0,0 +1,389 @@
+//! Static checks for bytecode that did not come from our own compiler.
+//!
+//! Every reachable optcode is visited with an abstract state: how many values are on the
+//! stack and which variables are surely defined. Where paths meet, the smaller depth and the
+//! common variables are kept, so a program is only rejected if it can fail on some path.
+
+use std::{ collections::{ BTreeSet, VecDeque }, fmt };
+
+use crate::{ bytecode::{ PathStep, OPTCODE }, module::FunctionSignature, native::NativeRegistry };
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyErrorKind {
+    /// A jump lands outside of the bytecode. Landing right after the last optcode is allowed.
+    JumpOutOfBounds {
+        target: i64,
+        length: usize,
+    },
+    StackUnderflow {
+        needed: usize,
+        available: usize,
+    },
+    UndefinedVariable {
+        id: usize,
+    },
+    /// A `CallFunction` left by bytecode that was not linked
+    UnlinkedCall {
+        name: String,
+    },
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyError {
+    pub kind: VerifyErrorKind,
+    pub index: usize,
+}
+
+impl fmt::Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyErrorKind::JumpOutOfBounds { target, length } =>
+                write!(f, "Jump to {} is outside of the block of length {}", target, length),
+            VerifyErrorKind::StackUnderflow { needed, available } =>
+                write!(f, "Needs {} values on the stack, but there may be only {}", needed, available),
+            VerifyErrorKind::UndefinedVariable { id } =>
+                write!(f, "Variable with ID {} may be used before it is defined", id),
+            VerifyErrorKind::UnlinkedCall { name } =>
+                write!(f, "Call to \"{}\" was not linked to a function", name),
+            VerifyErrorKind::BreakOutsideLoop => write!(f, "Break outside of a loop"),
+            VerifyErrorKind::ContinueOutsideLoop => write!(f, "Continue outside of a loop"),
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (optcode {})", self.kind, self.index)
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Verifies bytecode on its own. Calls to functions and natives that are not in
+/// the standard library can not be checked, so the stack depth after them is unknown.
+pub fn verify(bytecode: &[OPTCODE]) -> Result<(), Vec<VerifyError>> {
+    verify_with(bytecode, &[], &NativeRegistry::new())
+}
+
+/// Verifies linked bytecode, using the signatures to know what calls do to the stack.
+/// `functions` are in the order of the program, which `JumpToFunction` indexes into.
+pub fn verify_with(
+    bytecode: &[OPTCODE],
+    functions: &[FunctionSignature],
+    natives: &NativeRegistry
+) -> Result<(), Vec<VerifyError>> {
+    let verifier = Verifier { functions, natives };
+    let mut errors = vec![];
+    let roots = verifier.roots(bytecode);
+    verifier.analyze(bytecode, roots, &mut errors);
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Depth {
+    Known(usize),
+    /// After calling something whose signature we do not know
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct State {
+    depth: Depth,
+    defined: BTreeSet<usize>,
+}
+
+impl State {
+    fn merge(&self, other: &State) -> State {
+        let depth = match (self.depth, other.depth) {
+            (Depth::Known(a), Depth::Known(b)) => Depth::Known(a.min(b)),
+            _ => Depth::Unknown,
+        };
+        State {
+            depth,
+            defined: self.defined.intersection(&other.defined).copied().collect(),
+        }
+    }
+}
+
+struct Verifier<'a> {
+    functions: &'a [FunctionSignature],
+    natives: &'a NativeRegistry,
+}
+
+impl Verifier<'_> {
+    /// The start of the main block and the entry point of every called function.
+    fn roots(&self, bytecode: &[OPTCODE]) -> Vec<(usize, State)> {
+        let mut entries = vec![];
+        for optcode in bytecode {
+            if let OPTCODE::JumpToFunction { target, function, .. } = optcode {
+                // A target that overflows is reported by the `JumpToFunction`
+                let signature = self.functions.get(*function);
+                if let (Some(signature), Some(entry)) = (signature, target.checked_add(1)) {
+                    entries.push((entry, signature));
+                }
+            }
+        }
+        // Functions can use globals, which are defined in the main block before the first function
+        let main_end = entries
+            .iter()
+            .map(|(entry, _)| *entry)
+            .min()
+            .unwrap_or(bytecode.len());
+        let mut globals = BTreeSet::new();
+        for optcode in &bytecode[..main_end.min(bytecode.len())] {
+            if let OPTCODE::DefineVar { id, .. } | OPTCODE::DefineObject { id } = optcode {
+                globals.insert(*id);
+            }
+        }
+
+        let mut roots = vec![(0, State { depth: Depth::Known(0), defined: BTreeSet::new() })];
+        for (entry, signature) in entries {
+            let mut defined = globals.clone();
+            let binds_args = signature.args.iter().all(|arg| arg.local_var_id.is_some());
+            if binds_args {
+                defined.extend(signature.args.iter().filter_map(|arg| arg.local_var_id));
+            }
+            // Without ids the arguments stay on the stack for the body to define
+            let depth = if binds_args { 0 } else { signature.args.len() };
+            roots.push((entry, State { depth: Depth::Known(depth), defined }));
+        }
+        roots
+    }
+
+    /// Finds the state before every reachable optcode.
+    /// Then the optcodes are checked against their final states.
+    fn analyze(
+        &self,
+        bytecode: &[OPTCODE],
+        roots: Vec<(usize, State)>,
+        errors: &mut Vec<VerifyError>
+    ) {
+        // One more slot for the end of the bytecode
+        let mut states: Vec<Option<State>> = vec![None; bytecode.len() + 1];
+        let mut worklist = VecDeque::new();
+        for (index, state) in roots {
+            // Entry points outside of the block are reported by the `JumpToFunction`
+            if index < bytecode.len() && merge_into(&mut states, index, state) {
+                worklist.push_back(index);
+            }
+        }
+        while let Some(index) = worklist.pop_front() {
+            if index >= bytecode.len() {
+                continue;
+            }
+            let state = states[index].clone().unwrap();
+            let mut scratch = vec![];
+            for (successor, state) in self.transfer(bytecode, index, state, &mut scratch) {
+                if merge_into(&mut states, successor, state) {
+                    worklist.push_back(successor);
+                }
+            }
+        }
+        for (index, state) in states[..bytecode.len()].iter().enumerate() {
+            if let Some(state) = state {
+                self.transfer(bytecode, index, state.clone(), errors);
+            }
+        }
+    }
+
+    /// Applies one optcode to `state` and returns the states of the optcodes that can run next.
+    fn transfer(
+        &self,
+        bytecode: &[OPTCODE],
+        index: usize,
+        mut state: State,
+        errors: &mut Vec<VerifyError>
+    ) -> Vec<(usize, State)> {
+        let length = bytecode.len();
+        let report = |errors: &mut Vec<VerifyError>, kind| {
+            errors.push(VerifyError { kind, index })
+        };
+        let optcode = &bytecode[index];
+
+        for id in used_variables(optcode) {
+            if !state.defined.contains(&id) {
+                report(errors, VerifyErrorKind::UndefinedVariable { id });
+            }
+        }
+        let (pops, pushes) = match optcode {
+            OPTCODE::CallFunction { name, .. } => {
+                report(errors, VerifyErrorKind::UnlinkedCall { name: name.clone() });
+                (None, 0)
+            }
+            OPTCODE::JumpToFunction { function, .. } =>
+                match self.functions.get(*function) {
+                    Some(signature) =>
+                        (Some(signature.args.len()), signature.return_type.is_some() as usize),
+                    None => (None, 0),
+                }
+            OPTCODE::CallSpecialFunction { function: name } |
+            OPTCODE::CallNativeFunction { function_name: name, .. } =>
+                match self.natives.resolve(name).and_then(|index| self.natives.get(index)) {
+                    Some(native) => (Some(native.arity), native.returns_value as usize),
+                    None => (None, 0),
+                }
+            OPTCODE::PushToTestingStack { duplicate_stackvalue } => {
+                // Does nothing on an empty stack
+                let pops = match state.depth {
+                    Depth::Known(0) => 0,
+                    _ => !duplicate_stackvalue as usize,
+                };
+                (Some(pops), 0)
+            }
+            _ => {
+                let (pops, pushes) = stack_effect(optcode);
+                (Some(pops), pushes)
+            }
+        };
+        state.depth = match (state.depth, pops) {
+            (Depth::Known(depth), Some(pops)) => {
+                if depth < pops {
+                    report(errors, VerifyErrorKind::StackUnderflow { needed: pops, available: depth });
+                }
+                Depth::Known(depth.saturating_sub(pops) + pushes)
+            }
+            _ => Depth::Unknown,
+        };
+        if let OPTCODE::DefineVar { id, .. } | OPTCODE::DefineObject { id } = optcode {
+            state.defined.insert(*id);
+        }
+
+        let jump = |errors: &mut Vec<VerifyError>, target: i64, state: State| {
+            if target < 0 || target > (length as i64) {
+                report(errors, VerifyErrorKind::JumpOutOfBounds { target, length });
+                vec![]
+            } else {
+                vec![(target as usize, state)]
+            }
+        };
+        // Operands too large for an i64 are out of bounds anyway
+        let offset = |steps: usize| i64::try_from(steps).unwrap_or(i64::MAX);
+        let next = (index as i64) + 1;
+        match optcode {
+            OPTCODE::JumpIfFalse { steps, .. } => {
+                let mut successors = jump(errors, next.saturating_add(offset(*steps)), state.clone());
+                successors.push((index + 1, state));
+                successors
+            }
+            OPTCODE::RangeNext { steps } | OPTCODE::ForEachNext { steps } => {
+                // The loop ends with its state popped instead of the next item pushed
+                let mut exit = state.clone();
+                if let Depth::Known(depth) = exit.depth {
+                    exit.depth = Depth::Known(depth.saturating_sub(pushes));
+                }
+                let mut successors = jump(errors, next.saturating_add(offset(*steps)), exit);
+                successors.push((index + 1, state));
+                successors
+            }
+            OPTCODE::Jump { steps } => jump(errors, next.saturating_add(offset(*steps)), state),
+            OPTCODE::JumpBack { steps } => jump(errors, next.saturating_sub(offset(*steps)), state),
+            OPTCODE::JumpToFunction { target, .. } => {
+                // Only checks the target, the body is verified from its own root
+                if target.checked_add(1).is_none_or(|entry| entry >= length) {
+                    let target = offset(*target).saturating_add(1);
+                    report(errors, VerifyErrorKind::JumpOutOfBounds { target, length });
+                }
+                vec![(index + 1, state)]
+            }
+            OPTCODE::Return | OPTCODE::ReturnValue => vec![],
+            OPTCODE::Break { .. } => {
+                report(errors, VerifyErrorKind::BreakOutsideLoop);
+                vec![]
+            }
+            OPTCODE::Continue { .. } => {
+                report(errors, VerifyErrorKind::ContinueOutsideLoop);
+                vec![]
+            }
+            _ => vec![(index + 1, state)],
+        }
+    }
+}
+
+fn merge_into(states: &mut [Option<State>], index: usize, state: State) -> bool {
+    let merged = match &states[index] {
+        Some(existing) => existing.merge(&state),
+        None => state,
+    };
+    if states[index].as_ref() == Some(&merged) {
+        return false;
+    }
+    states[index] = Some(merged);
+    true
+}
+
+/// Variables that have to be defined before the optcode runs.
+fn used_variables(optcode: &OPTCODE) -> Vec<usize> {
+    match optcode {
+        OPTCODE::LoadVar { id, .. } |
+        OPTCODE::AssignVar { id } |
+        OPTCODE::AssignAtArrayIndex { id } |
+        OPTCODE::PushToArray { id } |
+        OPTCODE::GettArrayLength { id } |
+        OPTCODE::SetMapValue { id } |
+        OPTCODE::RemoveMapKey { id } |
+        OPTCODE::AssignAtPath { id, .. } |
+        OPTCODE::SetObjectField { id, .. } => vec![*id],
+        OPTCODE::CopyVariableValue { src_var_id, dst_var_id } => vec![*src_var_id, *dst_var_id],
+        _ => vec![],
+    }
+}
+
+/// How many values an optcode pops and pushes. Calls are handled by the verifier.
+pub(crate) fn stack_effect(optcode: &OPTCODE) -> (usize, usize) {
+    match optcode {
+        OPTCODE::LoadInt { .. } |
+        OPTCODE::LoadBool { .. } |
+        OPTCODE::LoadString { .. } |
+        OPTCODE::LoadFloat { .. } |
+        OPTCODE::LoadDecimal { .. } |
+        OPTCODE::LoadVar { .. } |
+        OPTCODE::GettArrayLength { .. } => (0, 1),
+        OPTCODE::Add { .. } |
+        OPTCODE::Subtract { .. } |
+        OPTCODE::Multiply { .. } |
+        OPTCODE::Divide { .. } |
+        OPTCODE::Remainder { .. } |
+        OPTCODE::LessThan { .. } |
+        OPTCODE::LargerThan { .. } |
+        OPTCODE::LessOrEq { .. } |
+        OPTCODE::LargerOrEq { .. } |
+        OPTCODE::NotEq { .. } |
+        OPTCODE::Eq { .. } |
+        OPTCODE::Or { .. } |
+        OPTCODE::And { .. } |
+        OPTCODE::Xor { .. } |
+        OPTCODE::GetIndex |
+        OPTCODE::GetMapValue |
+        OPTCODE::MapContains => (2, 1),
+        OPTCODE::Not |
+        OPTCODE::GetObjectField { .. } |
+        OPTCODE::GetMapKeys |
+        OPTCODE::CopyValue => (1, 1),
+        OPTCODE::JumpIfFalse { .. } |
+        OPTCODE::DefineVar { .. } |
+        OPTCODE::DefineObject { .. } |
+        OPTCODE::SetObjectField { .. } |
+        OPTCODE::PushToArray { .. } |
+        OPTCODE::RemoveMapKey { .. } |
+        OPTCODE::AssignVar { .. } |
+        OPTCODE::ReturnValue => (1, 0),
+        OPTCODE::AssignAtArrayIndex { .. } | OPTCODE::SetMapValue { .. } => (2, 0),
+        OPTCODE::CreateObject { field_names } => (field_names.len(), 1),
+        OPTCODE::CreateArray { init_values_count } => (*init_values_count, 1),
+        OPTCODE::CreateMap { init_entries_count } => (init_entries_count.saturating_mul(2), 1),
+        OPTCODE::AssignAtPath { path, .. } => {
+            let indexes = path
+                .iter()
+                .filter(|step| matches!(step, PathStep::Index))
+                .count();
+            (indexes + 1, 0)
+        }
+        OPTCODE::Pop { count } => (*count, 0),
+        // The loop state stays on the stack under the next item
+        OPTCODE::RangeNext { .. } => (3, 4),
+        OPTCODE::ForEachNext { .. } => (2, 3),
+        _ => (0, 0),
+    }
+}
@@ -1,75 +1,79 @@
-use super::{ vm::VM, StackValue };
+use super::{ runtime_error::{ RuntimeError, RuntimeErrorKind }, vm::VM, StackValue };
+
+pub(super) fn to_index(index: StackValue, length: usize) -> Result<usize, RuntimeError> {
+    match index {
+        StackValue::Int { value } => {
+            if value < 0 || (value as usize) >= length {
+                Err(RuntimeErrorKind::IndexOutOfBounds { index: value, length }.into())
+            } else {
+                Ok(value as usize)
+            }
+        }
+        other => Err(RuntimeError::type_mismatch("[]", &other, None)),
+    }
+}
 
 impl VM {
-    pub fn get_index(&mut self) {
-        let index_from_stack = self.stack.pop_back().unwrap();
-        let index = match index_from_stack {
-            StackValue::Int { value } => value.to_string().parse::<usize>().unwrap(),
-            _ => panic!("Array index is not an int"),
-        };
-        let indexable_value_from_stack = self.stack.pop_back().unwrap();
+    pub fn get_index(&mut self) -> Result<(), RuntimeError> {
+        let index_from_stack = self.pop()?;
+        let indexable_value_from_stack = self.pop()?;
         let result = match indexable_value_from_stack {
-            StackValue::Array { value } => value[index].clone(),
-            StackValue::String { value } =>
+            StackValue::Array { value } => {
+                let value = value.borrow();
+                let index = to_index(index_from_stack, value.len())?;
+                value[index].clone()
+            }
+            StackValue::String { value } => {
+                let index = to_index(index_from_stack, value.chars().count())?;
                 StackValue::String {
-                    value: value.as_str().chars().nth(index).unwrap().to_string()
-                },
-            _ => panic!("Atempted index non-array"),
+                    value: value.chars().nth(index).unwrap().to_string(),
+                }
+            }
+            other => {
+                return Err(RuntimeError::type_mismatch("[]", &other, None));
+            }
         };
         self.stack.push_back(result);
+        Ok(())
     }
-    pub fn set_at_array(&mut self, id: usize) {
-        let index_stack = self.stack.pop_back().unwrap();
-        let index = match index_stack {
-            StackValue::Int { value } => value.to_string().parse::<usize>().unwrap(),
-            _ => panic!("Array index is not an int"),
-        };
-
-        let getter = self.variables.get(&id);
-        if getter.is_none() {
-            panic!("Cound not found vairable named {}", id);
-        } else {
-            match getter.unwrap().value.to_owned() {
-                StackValue::Array { mut value } => {
-                    let value_to_push = self.stack.pop_back().unwrap();
-                    value[index] = value_to_push;
-                    self.variables.get_mut(&id).unwrap().value = StackValue::Array { value: value };
-                }
-                _ => panic!("{} is not an array", getter.unwrap().id),
+    pub fn set_at_array(&mut self, id: usize) -> Result<(), RuntimeError> {
+        let index_stack = self.pop()?;
+        let value_to_push = self.pop()?;
+        let array = &self.get_var(id)?.value;
+        value_to_push.check_storable_in(array)?;
+        match array {
+            StackValue::Array { value } => {
+                let mut value = value.borrow_mut();
+                let index = to_index(index_stack, value.len())?;
+                value[index] = value_to_push;
+                Ok(())
             }
-            return;
+            other => Err(RuntimeError::type_mismatch("[]", other, None)),
         }
     }
 
-    pub fn push_to_array(&mut self, id: usize) {
-        let getter = self.variables.get(&id);
-        if getter.is_none() {
-            panic!("Cound not found vairable named {}", id);
-        } else {
-            match getter.unwrap().value.to_owned() {
-                StackValue::Array { mut value } => {
-                    let value_to_push = self.stack.pop_back().unwrap();
-                    value.push(value_to_push);
-                }
-                _ => panic!("{} is not an array", getter.unwrap().id),
+    pub fn push_to_array(&mut self, id: usize) -> Result<(), RuntimeError> {
+        let value_to_push = self.pop()?;
+        let array = &self.get_var(id)?.value;
+        value_to_push.check_storable_in(array)?;
+        match array {
+            StackValue::Array { value } => {
+                value.borrow_mut().push(value_to_push);
+                Ok(())
             }
-            return;
+            other => Err(RuntimeError::type_mismatch("push", other, None)),
         }
     }
-    pub fn get_array_length(&mut self, id: usize) {
-        let getter = self.variables.get(&id);
-        if getter.is_none() {
-            panic!("Cound not found vairable named {}", id);
-        } else {
-            match getter.unwrap().value.to_owned() {
-                StackValue::Array { value } => {
-                    self.stack.push_back(StackValue::Int {
-                        value: value.len() as i64,
-                    });
-                }
-                _ => panic!("{} is not an array", getter.unwrap().id),
+    pub fn get_array_length(&mut self, id: usize) -> Result<(), RuntimeError> {
+        let length = match &self.get_var(id)?.value {
+            StackValue::Array { value } => value.borrow().len(),
+            other => {
+                return Err(RuntimeError::type_mismatch("length", other, None));
             }
-            return;
-        }
+        };
+        self.stack.push_back(StackValue::Int {
+            value: length as i64,
+        });
+        Ok(())
     }
 }
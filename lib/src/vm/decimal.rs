@@ -0,0 +1,43 @@
+use num::{ BigInt, BigRational, Signed };
+
+/// Digits after the separator for decimals that do not end, like 1/3
+const MAX_DECIMAL_PLACES: usize = 20;
+
+/// Reads a literal like `12`, `-0.1` or `0,25` exactly. Exponents are not supported.
+pub fn parse_decimal(literal: &str) -> Option<BigRational> {
+    let (negative, unsigned) = match literal.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, literal),
+    };
+    let (whole, fraction) = unsigned.split_once(['.', ',']).unwrap_or((unsigned, ""));
+    let all_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if whole.is_empty() || !all_digits(whole) || !all_digits(fraction) {
+        return None;
+    }
+    let numerator = BigInt::parse_bytes(format!("{}{}", whole, fraction).as_bytes(), 10)?;
+    let denominator = num::pow(BigInt::from(10), fraction.len());
+    let value = BigRational::new(numerator, denominator);
+    Some(if negative { -value } else { value })
+}
+
+/// Writes the decimal expansion with `separator`, exact when it ends within
+/// `MAX_DECIMAL_PLACES` digits and rounded otherwise.
+pub fn format_decimal(value: &BigRational, separator: &str) -> String {
+    let ten = BigRational::from_integer(BigInt::from(10));
+    let mut scaled = value.abs();
+    let mut places = 0;
+    while !scaled.is_integer() && places < MAX_DECIMAL_PLACES {
+        scaled *= &ten;
+        places += 1;
+    }
+    let digits = scaled.round().to_integer().to_string();
+    let digits = format!("{:0>width$}", digits, width = places + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - places);
+    let fraction = fraction.trim_end_matches('0');
+    let sign = if value.is_negative() && !(whole == "0" && fraction.is_empty()) { "-" } else { "" };
+    if fraction.is_empty() {
+        format!("{}{}", sign, whole)
+    } else {
+        format!("{}{}{}{}", sign, whole, separator, fraction)
+    }
+}
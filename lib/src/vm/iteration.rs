@@ -0,0 +1,91 @@
+use super::{ runtime_error::{ RuntimeError, RuntimeErrorKind }, vm::VM, StackValue };
+
+impl VM {
+    /// Advances a `[current, end, step]` range at the top of the stack and pushes the current value.
+    /// Returns `false` without pushing, and with the range popped, once `current` reaches `end`.
+    pub fn range_next(&mut self) -> Result<bool, RuntimeError> {
+        if self.stack.len() < 3 {
+            return Err(RuntimeErrorKind::StackUnderflow.into());
+        }
+        let mut state = self.stack.iter_mut().rev();
+        let (step, end, current) = match (state.next(), state.next(), state.next()) {
+            (
+                Some(StackValue::Int { value: step }),
+                Some(StackValue::Int { value: end }),
+                Some(StackValue::Int { value: current }),
+            ) => (*step, *end, current),
+            (Some(step), Some(end), Some(current)) => {
+                let culprit = [&*current, &*end, &*step]
+                    .into_iter()
+                    .find(|value| !matches!(value, StackValue::Int { .. }))
+                    .unwrap();
+                return Err(RuntimeError::type_mismatch("range", culprit, None));
+            }
+            _ => unreachable!(),
+        };
+        if step == 0 {
+            return Err(RuntimeErrorKind::ZeroRangeStep.into());
+        }
+        let in_range = if step > 0 { *current < end } else { *current > end };
+        if !in_range {
+            for _ in 0..3 {
+                self.pop()?;
+            }
+            return Ok(false);
+        }
+        let value = *current;
+        // An overflowing step can only go past `end`
+        *current = current.checked_add(step).unwrap_or(end);
+        self.push_stackvalue(StackValue::Int { value });
+        Ok(true)
+    }
+
+    /// Advances a `[collection, index]` pair at the top of the stack and pushes the element at `index`.
+    /// Strings are iterated by characters, with `index` as the byte offset of the next one, and maps by keys.
+    /// Returns `false` without pushing, and with the pair popped, after the last element.
+    pub fn foreach_next(&mut self) -> Result<bool, RuntimeError> {
+        if self.stack.len() < 2 {
+            return Err(RuntimeErrorKind::StackUnderflow.into());
+        }
+        let mut state = self.stack.iter_mut().rev();
+        let (index, collection) = (state.next().unwrap(), state.next().unwrap());
+        let position = match index {
+            StackValue::Int { value } => value,
+            other => {
+                return Err(RuntimeError::type_mismatch("for each", other, None));
+            }
+        };
+        let element = match collection {
+            StackValue::Array { value } => value.borrow().get(*position as usize).cloned(),
+            // For strings the index is a byte offset, so each step only looks at the next character
+            StackValue::String { value } =>
+                value
+                    .get(*position as usize..)
+                    .and_then(|rest| rest.chars().next())
+                    .map(|char| {
+                        *position += (char.len_utf8() as i64) - 1;
+                        StackValue::String { value: char.to_string() }
+                    }),
+            StackValue::Map { value } =>
+                value
+                    .borrow()
+                    .get_index(*position as usize)
+                    .map(|entry| entry.key.clone()),
+            other => {
+                return Err(RuntimeError::type_mismatch("for each", other, None));
+            }
+        };
+        match element {
+            Some(element) => {
+                *position += 1;
+                self.push_stackvalue(element);
+                Ok(true)
+            }
+            None => {
+                self.pop()?;
+                self.pop()?;
+                Ok(false)
+            }
+        }
+    }
+}
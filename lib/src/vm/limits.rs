@@ -0,0 +1,62 @@
+use num::BigInt;
+
+use super::StackValue;
+
+/// Caps for running untrusted programs. `None` means unlimited, which is the default.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VmLimits {
+    /// Optcodes executed in total
+    pub max_instructions: Option<u64>,
+    /// Function calls that can be active at the same time
+    pub max_call_depth: Option<usize>,
+    /// Values on the operand stack
+    pub max_stack_size: Option<usize>,
+    /// Length of a single string in bytes, a single array or map in elements,
+    /// or a single big integer or decimal in bytes.
+    /// Each value is capped on its own, `max_total_allocation` caps them together.
+    pub max_single_value_size: Option<usize>,
+    /// Sizes of all the values a program builds added together, measured like
+    /// `max_single_value_size`, with one per element added to an array or map.
+    /// Values that are dropped are not subtracted, so however a program splits or nests
+    /// its values, it can not use more memory than this.
+    pub max_total_allocation: Option<usize>,
+}
+
+impl VmLimits {
+    pub fn unlimited() -> VmLimits {
+        VmLimits::default()
+    }
+}
+
+/// The size of a value for `VmLimits::max_single_value_size`.
+/// Nested values are checked when they are built.
+pub(crate) fn value_size(value: &StackValue) -> usize {
+    match value {
+        StackValue::String { value } => value.len(),
+        StackValue::Array { value } => value.borrow().len(),
+        StackValue::Map { value } => value.borrow().len(),
+        StackValue::BigInt { value } => big_int_size(value),
+        StackValue::Decimal { value } => big_int_size(value.numer()) + big_int_size(value.denom()),
+        _ => 0,
+    }
+}
+
+/// The size of a value and everything nested in it, for copies that build all of it.
+pub(crate) fn deep_size(value: &StackValue) -> usize {
+    let nested = match value {
+        StackValue::Array { value } => value.borrow().iter().map(deep_size).sum(),
+        StackValue::Object { value } => value.borrow().iter().map(|field| deep_size(&field.value)).sum(),
+        StackValue::Map { value } =>
+            value
+                .borrow()
+                .iter()
+                .map(|entry| deep_size(&entry.key) + deep_size(&entry.value))
+                .sum(),
+        _ => 0,
+    };
+    value_size(value) + nested
+}
+
+fn big_int_size(value: &BigInt) -> usize {
+    value.bits().div_ceil(8) as usize
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+
+use num::BigInt;
+use serde::{ Deserialize, Serialize };
+
+use super::{ runtime_error::{ RuntimeError, RuntimeErrorKind }, vm::VM, MapEntry, StackValue };
+
+/// A value that can be a map key. Only values that compare by value can be keys.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Bool(bool),
+    Int(i64),
+    BigInt(BigInt),
+    String(String),
+}
+
+impl TryFrom<StackValue> for MapKey {
+    type Error = RuntimeError;
+
+    fn try_from(key: StackValue) -> Result<MapKey, RuntimeError> {
+        match key {
+            StackValue::Bool { value } => Ok(MapKey::Bool(value)),
+            StackValue::Int { value } => Ok(MapKey::Int(value)),
+            StackValue::BigInt { value } => Ok(MapKey::BigInt(value)),
+            StackValue::String { value } => Ok(MapKey::String(value)),
+            other => Err(RuntimeError::type_mismatch("map key", &other, None)),
+        }
+    }
+}
+
+impl From<MapKey> for StackValue {
+    fn from(key: MapKey) -> StackValue {
+        match key {
+            MapKey::Bool(value) => StackValue::Bool { value },
+            MapKey::Int(value) => StackValue::Int { value },
+            MapKey::BigInt(value) => StackValue::BigInt { value },
+            MapKey::String(value) => StackValue::String { value },
+        }
+    }
+}
+
+/// Entries in insertion order, with the position of every key so lookups do not search them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<MapEntry>", into = "Vec<MapEntry>")]
+pub struct Map {
+    entries: Vec<MapEntry>,
+    positions: HashMap<MapKey, usize>,
+}
+
+impl Map {
+    pub fn new() -> Map {
+        Map::default()
+    }
+    pub fn get(&self, key: &MapKey) -> Option<&StackValue> {
+        self.positions.get(key).map(|&position| &self.entries[position].value)
+    }
+    pub fn contains_key(&self, key: &MapKey) -> bool {
+        self.positions.contains_key(key)
+    }
+    /// Overwrites the value of an existing key or adds the key at the end.
+    pub fn insert(&mut self, key: MapKey, value: StackValue) {
+        match self.positions.get(&key) {
+            Some(&position) => {
+                self.entries[position].value = value;
+            }
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push(MapEntry { key: key.into(), value });
+            }
+        }
+    }
+    /// The entries after the removed one move up, so this takes time linear in the length.
+    pub fn remove(&mut self, key: &MapKey) -> Option<StackValue> {
+        let removed = self.positions.remove(key)?;
+        for position in self.positions.values_mut() {
+            if *position > removed {
+                *position -= 1;
+            }
+        }
+        Some(self.entries.remove(removed).value)
+    }
+    /// The entry at `position` in insertion order.
+    pub fn get_index(&self, position: usize) -> Option<&MapEntry> {
+        self.entries.get(position)
+    }
+    pub fn iter(&self) -> std::slice::Iter<'_, MapEntry> {
+        self.entries.iter()
+    }
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut StackValue> {
+        self.entries.iter_mut().map(|entry| &mut entry.value)
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl FromIterator<(MapKey, StackValue)> for Map {
+    /// A repeated key keeps the position of its first entry and the last value.
+    fn from_iter<I: IntoIterator<Item = (MapKey, StackValue)>>(pairs: I) -> Map {
+        let mut map = Map::new();
+        for (key, value) in pairs {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+impl TryFrom<Vec<MapEntry>> for Map {
+    type Error = RuntimeError;
+
+    fn try_from(entries: Vec<MapEntry>) -> Result<Map, RuntimeError> {
+        entries
+            .into_iter()
+            .map(|entry| Ok((MapKey::try_from(entry.key)?, entry.value)))
+            .collect()
+    }
+}
+
+impl From<Map> for Vec<MapEntry> {
+    fn from(map: Map) -> Vec<MapEntry> {
+        map.entries
+    }
+}
+
+pub(super) fn to_key(key: StackValue) -> Result<MapKey, RuntimeError> {
+    MapKey::try_from(key)
+}
+
+pub(super) fn missing_key(key: &MapKey) -> RuntimeError {
+    let key = match key {
+        MapKey::String(value) => format!("\"{}\"", value),
+        other => StackValue::from(other.clone()).to_string(),
+    };
+    RuntimeErrorKind::MissingKey { key }.into()
+}
+
+impl VM {
+    /// Pops `count` key and value pairs and pushes a map with them in the order they were pushed.
+    /// A repeated key keeps the last value.
+    pub fn create_map(&mut self, count: usize) -> Result<(), RuntimeError> {
+        let mut pairs = vec![];
+        for _ in 0..count {
+            let value = self.pop()?;
+            let key = to_key(self.pop()?)?;
+            pairs.push((key, value));
+        }
+        self.stack.push_back(StackValue::map(pairs.into_iter().rev().collect()));
+        Ok(())
+    }
+    pub fn get_map_value(&mut self) -> Result<(), RuntimeError> {
+        let key = to_key(self.pop()?)?;
+        let result = match self.pop()? {
+            StackValue::Map { value } =>
+                value
+                    .borrow()
+                    .get(&key)
+                    .cloned()
+                    .ok_or_else(|| missing_key(&key))?,
+            other => {
+                return Err(RuntimeError::type_mismatch("[]", &other, None));
+            }
+        };
+        self.stack.push_back(result);
+        Ok(())
+    }
+    /// Overwrites the value of an existing key or adds the key at the end.
+    pub fn set_map_value(&mut self, id: usize) -> Result<(), RuntimeError> {
+        let key = to_key(self.pop()?)?;
+        let new_value = self.pop()?;
+        let map = &self.get_var(id)?.value;
+        new_value.check_storable_in(map)?;
+        match map {
+            StackValue::Map { value } => {
+                value.borrow_mut().insert(key, new_value);
+                Ok(())
+            }
+            other => Err(RuntimeError::type_mismatch("[]", other, None)),
+        }
+    }
+    pub fn remove_map_key(&mut self, id: usize) -> Result<(), RuntimeError> {
+        let key = to_key(self.pop()?)?;
+        match &self.get_var(id)?.value {
+            StackValue::Map { value } => {
+                value
+                    .borrow_mut()
+                    .remove(&key)
+                    .ok_or_else(|| missing_key(&key))?;
+                Ok(())
+            }
+            other => Err(RuntimeError::type_mismatch("remove", other, None)),
+        }
+    }
+    pub fn map_contains(&mut self) -> Result<(), RuntimeError> {
+        let key = to_key(self.pop()?)?;
+        let contains = match self.pop()? {
+            StackValue::Map { value } =>
+                value.borrow().contains_key(&key),
+            other => {
+                return Err(RuntimeError::type_mismatch("contains", &other, None));
+            }
+        };
+        self.stack.push_back(StackValue::Bool { value: contains });
+        Ok(())
+    }
+    /// Pushes the keys as an array, for iterating with a for each loop.
+    pub fn get_map_keys(&mut self) -> Result<(), RuntimeError> {
+        let keys = match self.pop()? {
+            StackValue::Map { value } =>
+                value
+                    .borrow()
+                    .iter()
+                    .map(|entry| entry.key.clone())
+                    .collect(),
+            other => {
+                return Err(RuntimeError::type_mismatch("keys", &other, None));
+            }
+        };
+        self.stack.push_back(StackValue::array(keys));
+        Ok(())
+    }
+}
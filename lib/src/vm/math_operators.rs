@@ -1,472 +1,259 @@
-use crate::vm::vm::VM;
+use num::{ BigInt, BigRational, Signed, ToPrimitive, Zero };
 
-use super::StackValue;
+use crate::{
+    bytecode::BINOP,
+    locale::Locale,
+    operators::{ result_type, OperandType },
+    vm::vm::VM,
+};
 
-fn add_to_int(a: i64, b: StackValue) -> StackValue {
-    match b {
-        StackValue::Bool { value: _ } => panic!("Cannot add Bool to INT"),
-        StackValue::Int { value } => StackValue::Int { value: a + value },
-        StackValue::String { value } => StackValue::String {
-            value: a.to_string() + &value,
-        },
-        StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-        StackValue::Float { value } => {
-            if value.trunc() == value {
-                StackValue::Int {
-                    value: a + value as i64,
-                }
-            } else {
-                StackValue::Float {
-                    value: a as f64 + value,
-                }
-            }
-        },
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-    }
+use super::{ runtime_error::{ RuntimeError, RuntimeErrorKind }, StackValue };
+
+fn mismatch(operation: &'static str, a: &StackValue, b: &StackValue) -> RuntimeError {
+    RuntimeError::type_mismatch(operation, a, Some(b))
 }
-fn add_to_string(a: String, b: StackValue) -> StackValue {
-    match b {
-        StackValue::Bool { value: _ } => panic!("Cannot add Bool to String"),
-        StackValue::Int { value } => StackValue::String {
-            value: a + &value.to_string(),
-        },
-        StackValue::String { value } => StackValue::String { value: a + &value },
-        StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-        StackValue::Float { value } => StackValue::String {
-            value: a + &value.to_string().replace(".", ","),
-        },
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
+
+/// Refuses the operand types that `operators` has no rule for, so the type checker and the VM agree
+fn check_operands(operator: BINOP, a: &StackValue, b: &StackValue) -> Result<(), RuntimeError> {
+    match result_type(operator, OperandType::of_value(a), OperandType::of_value(b)) {
+        Some(_) => Ok(()),
+        None => Err(mismatch(operator.symbol(), a, b)),
     }
 }
-fn add_to_float(a: f64, b: StackValue) -> StackValue {
-    match b {
-        StackValue::Bool { value: _ } => panic!("Cannot add Bool to Float"),
-        StackValue::Int { value } => StackValue::Float {
-            value: a + value as f64,
-        },
-        StackValue::String { value } => StackValue::String {
-            value: a.to_string().replace(".", ",") + &value,
-        },
-        StackValue::Array { value: _ } => panic!("Cannot do math with Arrays"),
-        StackValue::Float { value } => StackValue::Float { value: a + value },
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
+
+/// Runs an operation on two integers, promoting to `BigInt` when `small` overflows.
+/// Returns `None` unless both values are integers.
+fn integer_operation(
+    a: &StackValue,
+    b: &StackValue,
+    small: fn(i64, i64) -> Option<i64>,
+    big: fn(BigInt, BigInt) -> BigInt
+) -> Option<StackValue> {
+    if let (StackValue::Int { value: a }, StackValue::Int { value: b }) = (a, b) {
+        if let Some(value) = small(*a, *b) {
+            return Some(StackValue::Int { value });
+        }
     }
+    Some(StackValue::from_big_int(big(a.to_big_int()?, b.to_big_int()?)))
 }
-pub fn add(a: StackValue, b: StackValue) -> StackValue {
-    match a {
-        StackValue::Bool { value: _ } => panic!("Cannot do addition with bool's"),
-        StackValue::Int { value } => add_to_int(value, b),
-        StackValue::String { value } => add_to_string(value, b),
-        StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-        StackValue::Float { value } => add_to_float(value, b),
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
+
+/// Runs an operation exactly when one value is a decimal and the other an integer or decimal.
+fn decimal_operation(
+    a: &StackValue,
+    b: &StackValue,
+    decimal: fn(BigRational, BigRational) -> BigRational
+) -> Option<StackValue> {
+    if !matches!(a, StackValue::Decimal { .. }) && !matches!(b, StackValue::Decimal { .. }) {
+        return None;
     }
+    Some(StackValue::Decimal { value: decimal(a.to_big_rational()?, b.to_big_rational()?) })
 }
-fn subtract_from_int(a: i64, b: StackValue) -> StackValue {
-    match b {
-        StackValue::Bool { value: _ } => panic!("Cannot subtract Bool to INT"),
-        StackValue::Int { value } => StackValue::Int { value: a - value },
-        StackValue::String { value: _ } => panic!("Cannot subtract string from MAGICINT"),
-        StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
 
-        StackValue::Float { value } => StackValue::Float {
-            value: a as f64 - value,
-        },
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
+/// Runs an operation on two numbers where at least one is a float.
+fn float_operation(
+    operation: &'static str,
+    a: &StackValue,
+    b: &StackValue,
+    float: fn(f64, f64) -> f64
+) -> Result<StackValue, RuntimeError> {
+    match (a.to_f64(), b.to_f64()) {
+        (Some(a), Some(b)) => Ok(StackValue::Float { value: float(a, b) }),
+        _ => Err(mismatch(operation, a, b)),
     }
 }
-fn subtract_from_float(a: f64, b: StackValue) -> StackValue {
-    match b {
-        StackValue::Bool { value: _ } => panic!("Cannot subtract Bool from Float"),
-        StackValue::Int { value } => StackValue::Float {
-            value: a - value as f64,
-        },
-        StackValue::String { value: _ } => panic!("Cannot subtract strings"),
-        StackValue::Array { value: _ } => panic!("Cannot do math with Arrays"),
-        StackValue::Float { value } => StackValue::Float { value: a - value },
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
+
+/// Numbers are joined to text the way `locale` prints them, e.g. with its decimal separator
+pub fn add(a: StackValue, b: StackValue, locale: Locale) -> Result<StackValue, RuntimeError> {
+    check_operands(BINOP::Add, &a, &b)?;
+    if let Some(result) = integer_operation(&a, &b, i64::checked_add, |a, b| a + b) {
+        return Ok(result);
     }
-}
-pub fn subtract(a: StackValue, b: StackValue) -> StackValue {
-    match a {
-        StackValue::Bool { value: _ } => panic!("Cannot do subtraction with Bool's"),
-        StackValue::Int { value } => subtract_from_int(value, b),
-        StackValue::String { value: _ } => panic!("Cannot do subtraction with String's"),
-        StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-        StackValue::Float { value } => subtract_from_float(value, a),
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
+    match (&a, &b) {
+        (StackValue::String { value: a }, StackValue::String { value: b }) =>
+            Ok(StackValue::String { value: a.to_owned() + b }),
+        (StackValue::String { value: a }, number) =>
+            Ok(StackValue::String { value: a.to_owned() + &locale.format_value(number) }),
+        (number, StackValue::String { value: b }) =>
+            Ok(StackValue::String { value: locale.format_value(number) + b }),
+        _ =>
+            match decimal_operation(&a, &b, |a, b| a + b) {
+                Some(result) => Ok(result),
+                None => float_operation("+", &a, &b, |a, b| a + b),
+            }
     }
 }
-fn multiply_with_int(a: i64, b: StackValue) -> StackValue {
-    match b {
-        StackValue::Bool { value: _ } => panic!("Cannot multiply Bool with MAGICINT"),
-        StackValue::Int { value } => StackValue::Int { value: a * value },
-        StackValue::String { value: _ } => panic!("Cannot multiply string with MAGICINT"),
-        StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-        StackValue::Float { value } => StackValue::Float {
-            value: value * a as f64,
-        },
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
+pub fn subtract(a: StackValue, b: StackValue) -> Result<StackValue, RuntimeError> {
+    check_operands(BINOP::Subtract, &a, &b)?;
+    let result = integer_operation(&a, &b, i64::checked_sub, |a, b| a - b).or_else(|| {
+        decimal_operation(&a, &b, |a, b| a - b)
+    });
+    match result {
+        Some(result) => Ok(result),
+        None => float_operation("-", &a, &b, |a, b| a - b),
     }
 }
-fn multiply_with_float(a: f64, b: StackValue) -> StackValue {
-    match b {
-        StackValue::Bool { value: _ } => panic!("Cannot multiply Bool with Float"),
-        StackValue::Int { value } => StackValue::Float {
-            value: a * value as f64,
-        },
-        StackValue::String { value: _ } => panic!("Cannot multiply string with MAGICINT"),
-        StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-        StackValue::Float { value } => StackValue::Float { value: value * a },
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
+/// A repeated text is checked against the VM's `max_single_value_size` before it is built
+pub fn multiply(
+    a: StackValue,
+    b: StackValue,
+    max_single_value_size: Option<usize>
+) -> Result<StackValue, RuntimeError> {
+    check_operands(BINOP::Multiply, &a, &b)?;
+    match (&a, &b) {
+        (StackValue::String { value: text }, count) | (count, StackValue::String { value: text }) => {
+            return repeat(text, count, max_single_value_size);
+        }
+        _ => {}
     }
-}
-pub fn multiply(a: StackValue, b: StackValue) -> StackValue {
-    match a {
-        StackValue::Bool { value: _ } => panic!("Cannot do multiplication with Bool's"),
-        StackValue::Int { value } => multiply_with_int(value, b),
-        StackValue::String { value: _ } => panic!("Cannot do multiplication with String's"),
-        StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-        StackValue::Float { value } => multiply_with_float(value, b),
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
+    let result = integer_operation(&a, &b, i64::checked_mul, |a, b| a * b).or_else(|| {
+        decimal_operation(&a, &b, |a, b| a * b)
+    });
+    match result {
+        Some(result) => Ok(result),
+        None => float_operation("*", &a, &b, |a, b| a * b),
     }
 }
-fn divide_with_int(a: i64, b: StackValue) -> StackValue {
-    match b {
-        StackValue::Bool { value: _ } => panic!("Cannot divide Bool with MAGICINT"),
-        StackValue::Int { value } => StackValue::Int { value: a / value },
-        StackValue::String { value: _ } => panic!("Cannot divide String with MAGICINT"),
-        StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-        StackValue::Float { value } => StackValue::Float {
-            value: value  / a as f64,
-            
-        },
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
+pub fn divide(a: StackValue, b: StackValue) -> Result<StackValue, RuntimeError> {
+    check_operands(BINOP::Divide, &a, &b)?;
+    if is_zero(&b) {
+        return Err(RuntimeErrorKind::DivisionByZero.into());
     }
-}
-fn divide_with_float(a: f64, b: StackValue) -> StackValue {
-    match b {
-        StackValue::Bool { value: _ } => panic!("Cannot multiply Bool with Float"),
-        StackValue::Int { value } => StackValue::Float {
-            value: value as f64 / a,
-        },
-        StackValue::String { value: _ } => panic!("Cannot multiply string with MAGICINT"),
-        StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-        StackValue::Float { value } => StackValue::Float { value: a / value },
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
+    // Both round towards zero, `i64::MIN / -1` is the only overflow
+    let result = integer_operation(&a, &b, i64::checked_div, |a, b| a / b).or_else(|| {
+        decimal_operation(&a, &b, |a, b| a / b)
+    });
+    match result {
+        Some(result) => Ok(result),
+        None => float_operation("/", &a, &b, |a, b| a / b),
     }
 }
-pub fn divide(a: StackValue, b: StackValue) -> StackValue {
-    match a {
-        StackValue::Bool { value: _ } => panic!("Cannot do division with Bool's"),
-        StackValue::Int { value } => divide_with_int(value, b),
-        StackValue::String { value: _ } => panic!("Cannot do division with String's"),
-        StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-        StackValue::Float { value } => divide_with_float(value, b),
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
+pub fn remainder(a: StackValue, b: StackValue) -> Result<StackValue, RuntimeError> {
+    check_operands(BINOP::Remainder, &a, &b)?;
+    if is_zero(&b) {
+        return Err(RuntimeErrorKind::DivisionByZero.into());
     }
-}
-fn get_remainder_with_bigint(a: i64, b: StackValue) -> StackValue {
-    match b {
-        StackValue::Bool { value: _ } => panic!("Cannot divide Bool with MAGICINT"),
-        StackValue::Int { value } => StackValue::Int { value: a % value },
-        StackValue::String { value: _ } => panic!("Cannot divide String with MAGICINT"),
-        StackValue::Array { value: _ } => panic!("Cannot do math with Arrays"),
-        StackValue::Float { value } => StackValue::Float {
-            value: a as f64 % value,
-        },
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
+    let result = integer_operation(&a, &b, i64::checked_rem, |a, b| a % b).or_else(|| {
+        decimal_operation(&a, &b, |a, b| a % b)
+    });
+    match result {
+        Some(result) => Ok(result),
+        None => float_operation("%", &a, &b, |a, b| a % b),
     }
 }
-fn get_remainder_with_float(a: f64, b: StackValue) -> StackValue {
-    match b {
-        StackValue::Bool { value: _ } => panic!("Cannot divide Bool with MAGICINT"),
-        StackValue::Int { value } => StackValue::Float {
-            value: a % value as f64,
-        },
-        StackValue::String { value: _ } => panic!("Cannot divide String with MAGICINT"),
-        StackValue::Array { value: _ } => panic!("Cannot do math with Arrays"),
-        StackValue::Float { value } => StackValue::Float {
-            value: a % value,
-        },
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
+/// `text` repeated `count` times, nothing for a negative count
+fn repeat(
+    text: &str,
+    count: &StackValue,
+    max_single_value_size: Option<usize>
+) -> Result<StackValue, RuntimeError> {
+    let count = count.to_big_int().unwrap_or_default();
+    if count.is_negative() {
+        return Ok(StackValue::String { value: String::new() });
     }
-}
-pub fn remainder(a: StackValue, b: StackValue) -> StackValue {
-    match a {
-        StackValue::Bool { value: _ } => panic!("Cannot do division with Bool's"),
-        StackValue::Int { value } => get_remainder_with_bigint(value, b),
-        StackValue::String { value: _ } => panic!("Cannot do division with String's"),
-        StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-        StackValue::Float { value } => get_remainder_with_float(value, b),
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
+    // Checked before allocating, `VM::check_limits` would only measure the result
+    let limit = max_single_value_size.unwrap_or(isize::MAX as usize);
+    let count = count.to_usize().unwrap_or(usize::MAX);
+    let size = text.len().saturating_mul(count);
+    if size > limit {
+        return Err(RuntimeErrorKind::ValueSizeExceeded { limit, size }.into());
     }
+    Ok(StackValue::String { value: text.repeat(count) })
 }
-pub fn less_than(a: StackValue, b: StackValue) -> StackValue {
-    match a {
-        StackValue::Bool { value: val_a } => match b {
-            StackValue::Bool { value } => StackValue::Bool {
-                value: val_a < value,
-            },
-            StackValue::Int { value: _ } => panic!("Cannot compare MAGICINT with Bool"),
-            StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-            StackValue::String { value: _ } => panic!("Cannot compare MAGICINT with String"),
-            StackValue::Float { value: _ } => panic!("Cannot compare Bool and Float"),
-            StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-        },
-        StackValue::Object { value: _} => panic!("Cannot do math with objects."),
-        StackValue::Int { value: val_a } => match b {
-            StackValue::Bool { value: _ } => panic!("Cannot compare MAGICINT with Bool"),
-            StackValue::Int { value } => StackValue::Bool {
-                value: val_a < value,
-            },
-            StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-            StackValue::String { value: _ } => panic!("Cannot compare MAGICINT with String"),
-            StackValue::Float { value } => StackValue::Bool {
-                value: (val_a as f64) < value,
-            },
-            StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-        },
-        StackValue::String { value: _ } => panic!("Cannot do comparisons  with String's"),
-        StackValue::Array { value: _ } => panic!("Cannot do comparisons with Arrays"),
-        StackValue::Float { value: val_a } => match b {
-            StackValue::Bool { value: _ } => panic!("Cannot do comparison with bool"),
-            StackValue::Int { value } => StackValue::Bool {
-                value: val_a  < value as f64 ,
-            },
-            StackValue::Float { value } => StackValue::Bool {
-                value: val_a < value,
-            },
-            StackValue::String { value: _ } => panic!("Cannot do comparisons with StringS"),
-            StackValue::Array { value: _ } => panic!("Cannot do comparisons with ArrayS"),
-            StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-        },
+fn is_zero(value: &StackValue) -> bool {
+    match value {
+        StackValue::Int { value } => *value == 0,
+        StackValue::BigInt { value } => value.is_zero(),
+        StackValue::Float { value } => *value == 0.0,
+        StackValue::Decimal { value } => value.is_zero(),
+        _ => false,
     }
 }
-pub fn larger_than(a: StackValue, b: StackValue) -> StackValue {
-    match a {
-        StackValue::Bool { value: val_a } => match b {
-            StackValue::Bool { value } => StackValue::Bool {
-                value: val_a > value,
-            },
-            StackValue::Int { value: _ } => panic!("Cannot compare MAGICINT with Bool"),
-            StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-            StackValue::String { value: _ } => panic!("Cannot compare MAGICINT with String"),
-            StackValue::Float { value: _ } => panic!("Cannot compare Bool and Float"),
-            StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-        },
-        StackValue::Int { value: val_a } => match b {
-            StackValue::Bool { value: _ } => panic!("Cannot compare MAGICINT with Bool"),
-            StackValue::Int { value } => StackValue::Bool {
-                value: val_a > value,
-            },
-            StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-            StackValue::String { value: _ } => panic!("Cannot compare MAGICINT with String"),
-            StackValue::Float { value } => StackValue::Bool {
-                value: val_a as f64 > value,
-            },
-            StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-        },
-        StackValue::String { value: _ } => panic!("Cannot do comparisons  with String's"),
-        StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-        StackValue::Float { value: val_a } => match b {
-            StackValue::Bool { value: _ } => panic!("Cannot do comparison with bool"),
-            StackValue::Int { value } => StackValue::Bool {
-                value: val_a > value as f64,
-            },
-            StackValue::Float { value } => StackValue::Bool {
-                value: val_a > value,
-            },
-            StackValue::String { value: _ } => panic!("Cannot do comparisons with StringS"),
-            StackValue::Array { value: _ } => panic!("Cannot do comparisons with ArrayS"),
-            StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-        },
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-    }
+
+/// Orders two values for the comparison operators.
+/// Bools are only comparable with bools, numbers with numbers.
+fn compare(
+    operation: &'static str,
+    a: StackValue,
+    b: StackValue
+) -> Result<std::cmp::Ordering, RuntimeError> {
+    let ordering = match (&a, &b) {
+        (StackValue::Bool { value: a }, StackValue::Bool { value: b }) => Some(a.cmp(b)),
+        (StackValue::Int { value: a }, StackValue::Int { value: b }) => Some(a.cmp(b)),
+        _ =>
+            match (a.to_big_rational(), b.to_big_rational()) {
+                (Some(a), Some(b)) => Some(a.cmp(&b)),
+                _ =>
+                    match (a.to_f64(), b.to_f64()) {
+                        (Some(a), Some(b)) => a.partial_cmp(&b),
+                        _ => {
+                            return Err(mismatch(operation, &a, &b));
+                        }
+                    }
+            }
+    };
+    // NaN is neither smaller nor larger than anything
+    Ok(ordering.unwrap_or(std::cmp::Ordering::Equal))
 }
-pub fn less_or_eq(a: StackValue, b: StackValue) -> StackValue {
-    match a {
-        StackValue::Bool { value: val_a } => match b {
-            StackValue::Bool { value } => StackValue::Bool {
-                value: val_a <= value,
-            },
-            StackValue::Int { value: _ } => panic!("Cannot compare MAGICINT with Bool"),
-            StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-            StackValue::String { value: _ } => panic!("Cannot compare MAGICINT with String"),
-            StackValue::Float { value: _ } => panic!("Cannot compare Bool and Float"),
-            StackValue::Object { value: _ } => panic!("Cannot do math with objects.")
-        },
-        StackValue::Int { value: val_a } => match b {
-            StackValue::Bool { value: _ } => panic!("Cannot compare MAGICINT with Bool"),
-            StackValue::Int { value } => StackValue::Bool {
-                value: val_a <= value,
-            },
-            StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-            StackValue::String { value: _ } => panic!("Cannot compare MAGICINT with String"),
-            StackValue::Float { value } => StackValue::Bool {
-                value: val_a as f64 <= value,
-            },
-            StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-        },
-        StackValue::String { value: _ } => panic!("Cannot do comparisons  with String's"),
-        StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-        StackValue::Float { value: val_a } => match b {
-            StackValue::Bool { value: _ } => panic!("Cannot do comparison with bool"),
-            StackValue::Int { value } => StackValue::Bool {
-                value: val_a <= value as f64,
-            },
-            StackValue::Float { value } => StackValue::Bool {
-                value: val_a <= value,
-            },
-            StackValue::String { value: _ } => panic!("Cannot do comparisons with StringS"),
-            StackValue::Array { value: _ } => panic!("Cannot do comparisons with ArrayS"),
-            StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-        },
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-    }
+pub fn less_than(a: StackValue, b: StackValue) -> Result<StackValue, RuntimeError> {
+    check_operands(BINOP::LessThan, &a, &b)?;
+    Ok(StackValue::Bool { value: compare("<", a, b)?.is_lt() })
 }
-pub fn larger_or_eq(a: StackValue, b: StackValue) -> StackValue {
-    match a {
-        StackValue::Bool { value: val_a } => match b {
-            StackValue::Bool { value } => StackValue::Bool {
-                value: val_a >= value,
-            },
-            StackValue::Int { value: _ } => panic!("Cannot compare MAGICINT with Bool"),
-            StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-            StackValue::String { value: _ } => panic!("Cannot compare MAGICINT with String"),
-            StackValue::Float { value: _ } => panic!("Cannot compare Bool and Float"),
-            StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-        },
-        StackValue::Object { value: _} => panic!("Cannot do math with objects."),
-        StackValue::Int { value: val_a } => match b {
-            StackValue::Bool { value: _ } => panic!("Cannot compare MAGICINT with Bool"),
-            StackValue::Int { value } => StackValue::Bool {
-                value: val_a >= value,
-            },
-            StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-            StackValue::String { value: _ } => panic!("Cannot compare MAGICINT with String"),
-            StackValue::Float { value } => StackValue::Bool {
-                value: val_a as f64 >= value,
-            },
-            StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-        },
-        StackValue::String { value: _ } => panic!("Cannot do comparisons  with String's"),
-        StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-        StackValue::Float { value: val_a } => match b {
-            StackValue::Bool { value: _ } => panic!("Cannot do comparison with bool"),
-            StackValue::Int { value } => StackValue::Bool {
-                value: val_a >= value as f64,
-            },
-            StackValue::Float { value } => StackValue::Bool {
-                value: val_a >= value,
-            },
-            StackValue::String { value: _ } => panic!("Cannot do comparisons with StringS"),
-            StackValue::Array { value: _ } => panic!("Cannot do comparisons with ArrayS"),
-            StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-        },
-    }
+pub fn larger_than(a: StackValue, b: StackValue) -> Result<StackValue, RuntimeError> {
+    check_operands(BINOP::LargerThan, &a, &b)?;
+    Ok(StackValue::Bool { value: compare(">", a, b)?.is_gt() })
 }
-pub fn not_eq(a: StackValue, b: StackValue) -> StackValue {
-    match a {
-        StackValue::Bool { value: val_a } => match b {
-            StackValue::Bool { value } => StackValue::Bool {
-                value: val_a != value,
-            },
-            StackValue::Int { value: _ } => panic!("Cannot compare MAGICINT with Bool"),
-            StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-            StackValue::String { value: _ } => panic!("Cannot compare MAGICINT with String"),
-            StackValue::Float { value: _ } => panic!("Cannot compare Float with Bool"),
-            StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-        },
-        StackValue::Int { value: val_a } => match b {
-            
-            StackValue::Bool { value: _ } => panic!("Cannot compare MAGICINT with Bool"),
-            StackValue::Int { value } => StackValue::Bool {
-                value: val_a != value,
-            },
-            StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-            StackValue::String { value: _ } => panic!("Cannot compare MAGICINT with String"),
-            StackValue::Float { value } => {
-                if value.trunc() == value {
-                    StackValue::Bool {
-                        value: val_a != value as i64,
-                    }
-                } else {
-                    StackValue::Bool { value: true }
-                }
-            },
-            StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-        },
-        StackValue::String { value: val_a } => match b {
-            StackValue::Bool { value: _ } => panic!("Cannot compare String with Bool"),
-            StackValue::Int { value: _ } => panic!("Cannot compare String with Int"),
-            StackValue::String { value } => StackValue::Bool {
-                value: val_a != value,
-            },
-            StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-            StackValue::Float { value: _ } => panic!("Cannot compare String with Float"),
-            StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-        },
-        StackValue::Array { value: _ } => panic!("Cannot do addition with Arrays"),
-        StackValue::Float { value: value_a } => match b {
-            StackValue::Bool { value: _ } => panic!("Cannot compare Float with Bool"),
-            StackValue::Int { value } => {
-                if value_a.trunc() == value_a {
-                    StackValue::Bool {
-                        value: value != value_a as i64,
+pub fn less_or_eq(a: StackValue, b: StackValue) -> Result<StackValue, RuntimeError> {
+    check_operands(BINOP::LessOrEq, &a, &b)?;
+    Ok(StackValue::Bool { value: compare("<=", a, b)?.is_le() })
+}
+pub fn larger_or_eq(a: StackValue, b: StackValue) -> Result<StackValue, RuntimeError> {
+    check_operands(BINOP::LargerOrEq, &a, &b)?;
+    Ok(StackValue::Bool { value: compare(">=", a, b)?.is_ge() })
+}
+pub fn not_eq(a: StackValue, b: StackValue) -> Result<StackValue, RuntimeError> {
+    check_operands(BINOP::NotEq, &a, &b)?;
+    let value = match (&a, &b) {
+        (StackValue::Bool { value: a }, StackValue::Bool { value: b }) => a != b,
+        (StackValue::String { value: a }, StackValue::String { value: b }) => a != b,
+        _ =>
+            match (a.to_big_rational(), b.to_big_rational()) {
+                (Some(a), Some(b)) => a != b,
+                _ =>
+                    match (a.to_f64(), b.to_f64()) {
+                        (Some(a), Some(b)) => a != b,
+                        _ => {
+                            return Err(mismatch("!=", &a, &b));
+                        }
                     }
-                } else {
-                    StackValue::Bool { value: false }
-                }
-            },
-            StackValue::Float { value: _ } => StackValue::Bool {
-                value: value_a != value_a,
-            },
-            StackValue::String { value: _ } => panic!("Cannot compare Float with String"),
-            StackValue::Array { value: _ } => panic!("Cannot compare Float with Array"),
-            StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-        },
-        StackValue::Object { value: _} => panic!("Cannot do math with objects.")
-    }
+            }
+    };
+    Ok(StackValue::Bool { value })
 }
-pub fn eq(a: StackValue, b: StackValue) -> StackValue {
-    match not_eq(a, b) {
-        StackValue::Bool { value } => StackValue::Bool { value: !value },
-        _ => panic!()
+pub fn eq(a: StackValue, b: StackValue) -> Result<StackValue, RuntimeError> {
+    check_operands(BINOP::Eq, &a, &b)?;
+    match not_eq(a.clone(), b.clone()) {
+        Ok(StackValue::Bool { value }) => Ok(StackValue::Bool { value: !value }),
+        _ => Err(mismatch("==", &a, &b)),
     }
 }
 
-pub fn and(a: StackValue, b: StackValue) -> StackValue {
+pub fn and(a: StackValue, b: StackValue) -> Result<StackValue, RuntimeError> {
+    check_operands(BINOP::And, &a, &b)?;
     let a_bool = VM::to_bool(a);
     let b_bool = VM::to_bool(b);
-    return StackValue::Bool { value: a_bool && b_bool };
+    Ok(StackValue::Bool { value: a_bool && b_bool })
 }
 
-pub fn or(a: StackValue, b: StackValue) -> StackValue {
+pub fn or(a: StackValue, b: StackValue) -> Result<StackValue, RuntimeError> {
+    check_operands(BINOP::Or, &a, &b)?;
     let a_bool = VM::to_bool(a);
     let b_bool = VM::to_bool(b);
-    return StackValue::Bool { value: a_bool || b_bool };
+    Ok(StackValue::Bool { value: a_bool || b_bool })
 }
 
-pub fn xor(a: StackValue, b: StackValue) -> StackValue {
+pub fn xor(a: StackValue, b: StackValue) -> Result<StackValue, RuntimeError> {
+    check_operands(BINOP::Xor, &a, &b)?;
     let a_bool = VM::to_bool(a);
     let b_bool = VM::to_bool(b);
-
-    if a_bool {
-        if b_bool {
-            return StackValue::Bool { value: false }
-        }
-        else {
-            return StackValue::Bool { value: true }
-        }
-    }
-
-    else {
-        if b_bool {
-            return StackValue::Bool { value: true }
-        }
-        else {
-            return StackValue::Bool { value: false }
-        }
-    }
+    Ok(StackValue::Bool { value: a_bool != b_bool })
 }
@@ -1,28 +1,213 @@
+#[allow(clippy::module_inception)]
 pub mod vm;
-use std::fmt::{self, Display};
+use std::{ cell::RefCell, fmt, rc::Rc };
 
+use num::{ BigInt, BigRational, ToPrimitive };
 use serde::{Deserialize, Serialize};
 
-use crate::vm::format_for_print::format_for_print;
+use crate::{ vm::format_for_print::format_for_print, BuiltinTypes };
+use runtime_error::{ RuntimeError, RuntimeErrorKind };
 mod math_operators;
 mod array;
+mod map;
+mod path;
+mod iteration;
+pub mod decimal;
 pub mod format_for_print;
+pub mod runtime_error;
+pub mod limits;
+
+pub use map::{ Map, MapKey };
+
+/// Arrays, objects and maps live on the heap. Cloning the value, e.g. by `LoadVar` or passing
+/// it to a function, shares them, and `OPTCODE::CopyValue` makes an independent copy.
+pub type Shared<T> = Rc<RefCell<T>>;
 
 #[derive(Debug, PartialEq, Clone,Serialize, Deserialize)]
 
 pub enum StackValue {
     Bool { value: bool },
     Int { value: i64 },
+    /// An `Int` that does not fit into `i64`, made by arithmetic that overflows.
+    /// Results that fit again are turned back into `Int`, so there is a single way to store a number.
+    BigInt { value: BigInt },
     Float {value: f64},
+    /// An exact fraction, so `0,1 + 0,2` is `0,3`. Made by `OPTCODE::LoadDecimal`.
+    Decimal { value: BigRational },
     String { value: String },
-    Array { value: Vec<StackValue> },
-    Object {value: Vec<ObjectField>}
+    Array { value: Shared<Vec<StackValue>> },
+    Object {value: Shared<Vec<ObjectField>>},
+    /// Entries in insertion order. Keys are `Bool`, `Int` or `String` and appear once.
+    Map { value: Shared<Map> }
 }
 #[derive(Debug, PartialEq, Clone,Serialize, Deserialize)]
 pub struct  ObjectField {
     pub name: String,
     pub value: StackValue
 }
+#[derive(Debug, PartialEq, Clone,Serialize, Deserialize)]
+pub struct MapEntry {
+    pub key: StackValue,
+    pub value: StackValue
+}
+impl StackValue {
+    /// `Int` when the value fits into `i64`, `BigInt` otherwise.
+    pub fn from_big_int(value: BigInt) -> StackValue {
+        match value.to_i64() {
+            Some(value) => StackValue::Int { value },
+            None => StackValue::BigInt { value },
+        }
+    }
+    pub fn to_big_int(&self) -> Option<BigInt> {
+        match self {
+            StackValue::Int { value } => Some(BigInt::from(*value)),
+            StackValue::BigInt { value } => Some(value.clone()),
+            _ => None,
+        }
+    }
+    /// Any number as a float. Big integers lose precision.
+    pub fn to_f64(&self) -> Option<f64> {
+        match self {
+            StackValue::Int { value } => Some(*value as f64),
+            StackValue::BigInt { value } => value.to_f64(),
+            StackValue::Float { value } => Some(*value),
+            StackValue::Decimal { value } => value.to_f64(),
+            _ => None,
+        }
+    }
+    /// Integers and decimals as an exact fraction.
+    pub fn to_big_rational(&self) -> Option<BigRational> {
+        match self {
+            StackValue::Decimal { value } => Some(value.clone()),
+            _ => self.to_big_int().map(BigRational::from_integer),
+        }
+    }
+    pub fn array(elements: Vec<StackValue>) -> StackValue {
+        StackValue::Array { value: Rc::new(RefCell::new(elements)) }
+    }
+    pub fn object(fields: Vec<ObjectField>) -> StackValue {
+        StackValue::Object { value: Rc::new(RefCell::new(fields)) }
+    }
+    pub fn map(map: Map) -> StackValue {
+        StackValue::Map { value: Rc::new(RefCell::new(map)) }
+    }
+    /// A copy that shares nothing with the original, nested arrays, objects and maps included.
+    pub fn deep_copy(&self) -> StackValue {
+        match self {
+            StackValue::Array { value } =>
+                StackValue::array(value.borrow().iter().map(StackValue::deep_copy).collect()),
+            StackValue::Object { value } =>
+                StackValue::object(
+                    value
+                        .borrow()
+                        .iter()
+                        .map(|field| ObjectField {
+                            name: field.name.clone(),
+                            value: field.value.deep_copy(),
+                        })
+                        .collect()
+                ),
+            StackValue::Map { value } => {
+                let mut map = value.borrow().clone();
+                for value in map.values_mut() {
+                    *value = value.deep_copy();
+                }
+                StackValue::map(map)
+            }
+            _ => self.clone(),
+        }
+    }
+    /// Fails if storing this value in `container` would make the container contain itself.
+    /// Rejecting cycles keeps printing, comparing and copying values finite.
+    pub(crate) fn check_storable_in(&self, container: &StackValue) -> Result<(), RuntimeError> {
+        if self.reaches(container) {
+            return Err(RuntimeErrorKind::CyclicValue.into());
+        }
+        Ok(())
+    }
+    /// Whether `container` is this value or is reachable from it.
+    fn reaches(&self, container: &StackValue) -> bool {
+        if self.heap_address().is_some() && self.heap_address() == container.heap_address() {
+            return true;
+        }
+        match self {
+            StackValue::Array { value } =>
+                value
+                    .borrow()
+                    .iter()
+                    .any(|element| element.reaches(container)),
+            StackValue::Object { value } =>
+                value
+                    .borrow()
+                    .iter()
+                    .any(|field| field.value.reaches(container)),
+            StackValue::Map { value } =>
+                value
+                    .borrow()
+                    .iter()
+                    .any(|entry| entry.value.reaches(container)),
+            _ => false,
+        }
+    }
+    fn heap_address(&self) -> Option<*const ()> {
+        match self {
+            StackValue::Array { value } => Some(Rc::as_ptr(value) as *const ()),
+            StackValue::Object { value } => Some(Rc::as_ptr(value) as *const ()),
+            StackValue::Map { value } => Some(Rc::as_ptr(value) as *const ()),
+            _ => None,
+        }
+    }
+    /// Name of the value's type, matching the `BuiltinTypes` variant names.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            StackValue::Bool { value: _ } => "Bool",
+            StackValue::Int { value: _ } | StackValue::BigInt { value: _ } => "Int",
+            StackValue::Float { value: _ } => "Float",
+            StackValue::Decimal { value: _ } => "Decimal",
+            StackValue::String { value: _ } => "String",
+            StackValue::Array { value: _ } => "Array",
+            StackValue::Object { value: _ } => "Object",
+            StackValue::Map { value: _ } => "Map",
+        }
+    }
+    /// Whether the value can be used where `data_type` is expected.
+    /// Ints are accepted as floats and decimals, array lengths are not checked.
+    pub fn is_of_type(&self, data_type: &BuiltinTypes) -> bool {
+        match (self, data_type) {
+            (StackValue::Bool { value: _ }, BuiltinTypes::Bool) => true,
+            (StackValue::Int { value: _ } | StackValue::BigInt { value: _ }, BuiltinTypes::Int) => true,
+            (StackValue::Int { value: _ } | StackValue::BigInt { value: _ }, BuiltinTypes::Float) => true,
+            (StackValue::Int { value: _ } | StackValue::BigInt { value: _ }, BuiltinTypes::Decimal) => true,
+            (StackValue::Float { value: _ }, BuiltinTypes::Float) => true,
+            (StackValue::Decimal { value: _ }, BuiltinTypes::Decimal) => true,
+            (StackValue::String { value: _ }, BuiltinTypes::String) => true,
+            (StackValue::Array { value }, BuiltinTypes::Array { element_type, length: _ }) =>
+                value
+                    .borrow()
+                    .iter()
+                    .all(|element| element.is_of_type(element_type)),
+            (StackValue::Object { value }, BuiltinTypes::Object { fields }) => {
+                let value = value.borrow();
+                value.len() == fields.len() &&
+                    fields.iter().all(|field_type| {
+                        value
+                            .iter()
+                            .any(
+                                |field|
+                                    field.name == field_type.name &&
+                                    field.value.is_of_type(&field_type.data_type)
+                            )
+                    })
+            }
+            (StackValue::Map { value }, BuiltinTypes::Map { key, value: value_type }) =>
+                value
+                    .borrow()
+                    .iter()
+                    .all(|entry| entry.key.is_of_type(key) && entry.value.is_of_type(value_type)),
+            _ => false,
+        }
+    }
+}
 impl fmt::Display for StackValue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", format_for_print(self, false))
@@ -0,0 +1,115 @@
+use crate::bytecode::PathStep;
+
+use super::{
+    array::to_index,
+    map::{ missing_key, to_key },
+    runtime_error::{ RuntimeError, RuntimeErrorKind },
+    vm::VM,
+    StackValue,
+};
+
+fn missing_field(name: &str) -> RuntimeError {
+    RuntimeErrorKind::MissingField { field_name: name.to_string() }.into()
+}
+
+/// The value one step inside `container`. Arrays, objects and maps are shared,
+/// so changing the returned value changes the one in `container`.
+fn step_into(
+    container: &StackValue,
+    step: &PathStep,
+    index: Option<StackValue>
+) -> Result<StackValue, RuntimeError> {
+    match (container, step, index) {
+        (StackValue::Array { value }, PathStep::Index, Some(index)) => {
+            let value = value.borrow();
+            Ok(value[to_index(index, value.len())?].clone())
+        }
+        (StackValue::Map { value }, PathStep::Index, Some(key)) => {
+            let key = to_key(key)?;
+            value
+                .borrow()
+                .get(&key)
+                .cloned()
+                .ok_or_else(|| missing_key(&key))
+        }
+        (StackValue::Object { value }, PathStep::Field { name }, _) =>
+            value
+                .borrow()
+                .iter()
+                .find(|field| &field.name == name)
+                .map(|field| field.value.clone())
+                .ok_or_else(|| missing_field(name)),
+        (other, PathStep::Index, _) => Err(RuntimeError::type_mismatch("[]", other, None)),
+        (other, PathStep::Field { .. }, _) => Err(RuntimeError::type_mismatch(".", other, None)),
+    }
+}
+
+/// Stores `new_value` at the last step. A missing map key is added, a missing field is an error.
+fn assign_in(
+    container: &StackValue,
+    step: &PathStep,
+    index: Option<StackValue>,
+    new_value: StackValue
+) -> Result<(), RuntimeError> {
+    new_value.check_storable_in(container)?;
+    match (container, step, index) {
+        (StackValue::Array { value }, PathStep::Index, Some(index)) => {
+            let mut value = value.borrow_mut();
+            let index = to_index(index, value.len())?;
+            value[index] = new_value;
+        }
+        (StackValue::Map { value }, PathStep::Index, Some(key)) => {
+            value.borrow_mut().insert(to_key(key)?, new_value);
+        }
+        (StackValue::Object { value }, PathStep::Field { name }, _) => {
+            let mut value = value.borrow_mut();
+            let field = value
+                .iter_mut()
+                .find(|field| &field.name == name)
+                .ok_or_else(|| missing_field(name))?;
+            field.value = new_value;
+        }
+        (other, PathStep::Index, _) => {
+            return Err(RuntimeError::type_mismatch("[]", other, None));
+        }
+        (other, PathStep::Field { .. }, _) => {
+            return Err(RuntimeError::type_mismatch(".", other, None));
+        }
+    }
+    Ok(())
+}
+
+impl VM {
+    /// Pops one index or key for every `PathStep::Index`, the last step's on top, and then the value.
+    /// The path is followed from the variable and the value is assigned in place, so
+    /// `matrix[i][j] = x` pushes `x`, `i` and `j` and runs `AssignAtPath { id: matrix, path: [Index, Index] }`.
+    /// An empty path assigns the variable itself.
+    pub fn assign_at_path(&mut self, id: usize, path: &[PathStep]) -> Result<(), RuntimeError> {
+        let index_count = path
+            .iter()
+            .filter(|step| matches!(step, PathStep::Index))
+            .count();
+        let mut indexes = vec![];
+        for _ in 0..index_count {
+            indexes.push(self.pop()?);
+        }
+        let new_value = self.pop()?;
+        let Some((last, steps)) = path.split_last() else {
+            self.push_stackvalue(new_value);
+            return self.assign_var(id);
+        };
+        // Popped from the last step to the first
+        let mut next_index = |step: &PathStep| {
+            match step {
+                PathStep::Index => indexes.pop(),
+                PathStep::Field { .. } => None,
+            }
+        };
+        let mut target = self.get_var(id)?.value.clone();
+        for step in steps {
+            target = step_into(&target, step, next_index(step))?;
+        }
+        assign_in(&target, last, next_index(last), new_value)?;
+        self.check_value_size(&target)
+    }
+}
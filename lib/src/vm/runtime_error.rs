@@ -0,0 +1,199 @@
+use std::fmt;
+
+use crate::{ block::TextSpan, BuiltinTypes };
+
+use super::StackValue;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeErrorKind {
+    /// An operation got values of types it can not work with.
+    /// `right` is `None` for operations with a single operand.
+    TypeMismatch {
+        operation: &'static str,
+        left: &'static str,
+        right: Option<&'static str>,
+    },
+    IndexOutOfBounds {
+        index: i64,
+        length: usize,
+    },
+    DivisionByZero,
+    UnknownVariable {
+        id: usize,
+    },
+    StackUnderflow,
+    MissingField {
+        field_name: String,
+    },
+    /// `key` is the printed key
+    MissingKey {
+        key: String,
+    },
+    UnknownFunction {
+        name: String,
+    },
+    /// A function returned something other than its `return_type`.
+    /// `expected` is `None` for functions without a return value,
+    /// `found` is `None` when a function with a return type ended without returning a value.
+    ReturnTypeMismatch {
+        expected: Option<BuiltinTypes>,
+        found: Option<&'static str>,
+    },
+    /// Limits from `VmLimits`, `limit` is the configured maximum
+    InstructionLimitExceeded {
+        limit: u64,
+    },
+    CallDepthExceeded {
+        limit: usize,
+    },
+    StackSizeExceeded {
+        limit: usize,
+    },
+    ValueSizeExceeded {
+        limit: usize,
+        size: usize,
+    },
+    AllocationLimitExceeded {
+        limit: usize,
+    },
+    /// A range loop with a step of 0 would never end
+    ZeroRangeStep,
+    /// An array, object or map was stored inside itself
+    CyclicValue,
+    /// `OPTCODE::LoadDecimal` with a literal that is not a number
+    InvalidDecimal {
+        literal: String,
+    },
+    /// `VM::push` with text that is not a literal of the type. Arrays, objects and maps have none.
+    InvalidLiteral {
+        data_type: BuiltinTypes,
+        literal: String,
+    },
+    /// `VM::aritmethics` with a symbol that is not a binary operator
+    UnknownOperator {
+        operator: String,
+    },
+    /// `nejaušs_robežās` with no integer from `min` up to, but not including, `max`
+    EmptyRandomRange {
+        min: i64,
+        max: i64,
+    },
+}
+
+/// An error that stopped the execution of a program.
+/// The location is filled in by the interpreter loop, so VM helpers only have to provide the `kind`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub optcode_index: Option<usize>,
+    pub node_id: Option<usize>,
+    pub span: Option<TextSpan>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind) -> RuntimeError {
+        RuntimeError {
+            kind,
+            optcode_index: None,
+            node_id: None,
+            span: None,
+        }
+    }
+    pub fn type_mismatch(
+        operation: &'static str,
+        left: &StackValue,
+        right: Option<&StackValue>
+    ) -> RuntimeError {
+        RuntimeError::new(RuntimeErrorKind::TypeMismatch {
+            operation,
+            left: left.type_name(),
+            right: right.map(|value| value.type_name()),
+        })
+    }
+    /// Attaches the location of the failing optcode, unless the error already has one.
+    pub(crate) fn locate(
+        mut self,
+        optcode_index: usize,
+        node_id: Option<usize>,
+        span: Option<TextSpan>
+    ) -> RuntimeError {
+        if self.optcode_index.is_none() {
+            self.optcode_index = Some(optcode_index);
+            self.node_id = node_id;
+            self.span = span;
+        }
+        self
+    }
+}
+
+impl From<RuntimeErrorKind> for RuntimeError {
+    fn from(kind: RuntimeErrorKind) -> RuntimeError {
+        RuntimeError::new(kind)
+    }
+}
+
+impl fmt::Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeErrorKind::TypeMismatch { operation, left, right: Some(right) } =>
+                write!(f, "Cannot do '{}' with {} and {}", operation, left, right),
+            RuntimeErrorKind::TypeMismatch { operation, left, right: None } =>
+                write!(f, "Cannot do '{}' with {}", operation, left),
+            RuntimeErrorKind::IndexOutOfBounds { index, length } =>
+                write!(f, "Index {} is out of bounds for length {}", index, length),
+            RuntimeErrorKind::DivisionByZero => write!(f, "Division by zero"),
+            RuntimeErrorKind::UnknownVariable { id } =>
+                write!(f, "Could not find variable with ID {}", id),
+            RuntimeErrorKind::StackUnderflow => write!(f, "Stack underflow"),
+            RuntimeErrorKind::MissingField { field_name } =>
+                write!(f, "Object has no field named \"{}\"", field_name),
+            RuntimeErrorKind::MissingKey { key } => write!(f, "Map has no key {}", key),
+            RuntimeErrorKind::UnknownFunction { name } =>
+                write!(f, "Could not find function \"{}\"", name),
+            RuntimeErrorKind::ReturnTypeMismatch { expected: Some(expected), found: Some(found) } =>
+                write!(f, "Function should return {:?}, but returned {}", expected, found),
+            RuntimeErrorKind::ReturnTypeMismatch { expected: Some(expected), found: None } =>
+                write!(f, "Function should return {:?}, but did not return a value", expected),
+            RuntimeErrorKind::ReturnTypeMismatch { expected: None, found } =>
+                write!(
+                    f,
+                    "Function has no return type, but returned {}",
+                    found.unwrap_or("nothing")
+                ),
+            RuntimeErrorKind::InstructionLimitExceeded { limit } =>
+                write!(f, "Program did not finish within {} instructions", limit),
+            RuntimeErrorKind::CallDepthExceeded { limit } =>
+                write!(f, "More than {} nested function calls", limit),
+            RuntimeErrorKind::StackSizeExceeded { limit } =>
+                write!(f, "More than {} values on the stack", limit),
+            RuntimeErrorKind::ValueSizeExceeded { limit, size } =>
+                write!(f, "Value of size {} is larger than the limit of {}", size, limit),
+            RuntimeErrorKind::AllocationLimitExceeded { limit } =>
+                write!(f, "Program built values of more than {} in total", limit),
+            RuntimeErrorKind::ZeroRangeStep => write!(f, "Range step can not be 0"),
+            RuntimeErrorKind::CyclicValue => write!(f, "A value can not be stored inside itself"),
+            RuntimeErrorKind::InvalidDecimal { literal } =>
+                write!(f, "\"{}\" is not a decimal number", literal),
+            RuntimeErrorKind::InvalidLiteral { data_type, literal } =>
+                write!(f, "\"{}\" is not a literal of {:?}", literal, data_type),
+            RuntimeErrorKind::UnknownOperator { operator } =>
+                write!(f, "Unknown operator \"{}\"", operator),
+            RuntimeErrorKind::EmptyRandomRange { min, max } =>
+                write!(f, "There are no numbers from {} up to {} to choose from", min, max),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(span) = &self.span {
+            write!(f, " (line {}, column {})", span.line, span.col_start)?;
+        } else if let Some(index) = self.optcode_index {
+            write!(f, " (optcode {})", index)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RuntimeError {}
@@ -1,12 +1,33 @@
-use serde::de::value;
+use num::{ BigInt, Zero };
 
-use super::{ format_for_print::format_for_print, math_operators::*, StackValue };
-use crate::{ bytecode::OPTCODE, CelsiumProgram, BuiltinTypes };
-use std::{ collections::{ HashMap, LinkedList }, io::{ self, BufRead, Write } };
+use super::{
+    decimal::parse_decimal,
+    format_for_print::format_in_locale,
+    limits::{ deep_size, value_size, VmLimits },
+    math_operators::*,
+    runtime_error::{ RuntimeError, RuntimeErrorKind },
+    StackValue,
+};
+use crate::{
+    bytecode::OPTCODE,
+    io::{ default_io, SharedIo },
+    locale::Locale,
+    module::FunctionSignature,
+    BuiltinTypes,
+};
+use std::collections::{ HashMap, LinkedList };
 
+/// A frame of a running user defined function.
+/// Variables defined while the frame is on top of the call stack live in `locals`,
+/// so every (recursive) call has its own copies.
 pub struct CallStackItem {
-    pub(crate) optode_index: usize,
-    pub(crate) function_name: Option<String>,
+    pub optode_index: usize,
+    pub function_name: Option<String>,
+    pub locals: HashMap<usize, Variable>,
+    pub return_type: Option<BuiltinTypes>,
+    /// Height of the operand stack when the function was entered (after popping the arguments).
+    /// Anything above it is removed when the function returns.
+    pub stack_base: usize,
 }
 
 pub struct VM {
@@ -14,14 +35,24 @@ pub struct VM {
     pub(crate) variables: HashMap<usize, Variable>,
     pub(crate) testing_stack: Vec<StackValue>,
     pub(crate) call_stack: LinkedList<CallStackItem>,
+    pub(crate) limits: VmLimits,
+    pub(crate) executed_instructions: u64,
+    /// Running total for `VmLimits::max_total_allocation`
+    allocated: usize,
+    pub(crate) io: SharedIo,
+    pub(crate) locale: Locale,
 }
 #[derive(Clone, Debug)]
 pub struct Variable {
-    pub(crate) id: usize,
-    pub(crate) value: StackValue,
+    pub id: usize,
+    pub value: StackValue,
 }
 
-impl StackValue {}
+impl Default for VM {
+    fn default() -> Self {
+        VM::new()
+    }
+}
 
 impl VM {
     pub fn new() -> VM {
@@ -30,28 +61,150 @@ impl VM {
             variables: HashMap::new(),
             testing_stack: vec![],
             call_stack: LinkedList::new(),
+            limits: VmLimits::default(),
+            executed_instructions: 0,
+            allocated: 0,
+            io: default_io(),
+            locale: Locale::default(),
         }
     }
-    pub fn push(&mut self, data_type: &BuiltinTypes, data: &String) {
-        match data_type {
-            BuiltinTypes::Int => panic!(),
-            BuiltinTypes::Bool => {
-                if data == "1" {
-                    self.stack.push_back(StackValue::Bool { value: true })
-                } else if data == "0" {
-                    self.stack.push_back(StackValue::Bool { value: false })
-                }
+    pub fn set_io(&mut self, io: SharedIo) {
+        self.io = io;
+    }
+    pub fn write_output(&self, text: &str) {
+        self.io.borrow_mut().write(text);
+    }
+    /// Language of the values printed by `izvade`.
+    pub fn set_locale(&mut self, locale: Locale) {
+        self.locale = locale;
+    }
+    pub fn with_limits(limits: VmLimits) -> VM {
+        let mut vm = VM::new();
+        vm.limits = limits;
+        vm
+    }
+    pub fn set_limits(&mut self, limits: VmLimits) {
+        self.limits = limits;
+    }
+    /// Operand stack from the bottom to the top.
+    pub fn stack(&self) -> impl Iterator<Item = &StackValue> {
+        self.stack.iter()
+    }
+    pub fn globals(&self) -> impl Iterator<Item = &Variable> {
+        self.variables.values()
+    }
+    /// Frames of the running functions, the innermost call last.
+    pub fn call_stack(&self) -> impl Iterator<Item = &CallStackItem> {
+        self.call_stack.iter()
+    }
+    /// Looks a variable up the same way `LoadVar` does.
+    pub fn variable(&self, id: usize) -> Option<&Variable> {
+        self.get_var(id).ok()
+    }
+    pub fn testing_stack(&self) -> &[StackValue] {
+        &self.testing_stack
+    }
+    pub fn executed_instructions(&self) -> u64 {
+        self.executed_instructions
+    }
+    /// Sizes of the values built so far, see `VmLimits::max_total_allocation`.
+    pub fn allocated(&self) -> usize {
+        self.allocated
+    }
+    /// Called before every optcode.
+    pub(crate) fn count_instruction(&mut self) -> Result<(), RuntimeError> {
+        self.executed_instructions += 1;
+        match self.limits.max_instructions {
+            Some(limit) if self.executed_instructions > limit =>
+                Err(RuntimeErrorKind::InstructionLimitExceeded { limit }.into()),
+            _ => Ok(()),
+        }
+    }
+    /// Called after every optcode. Only values that `optcode` can have built or grown are measured.
+    pub(crate) fn check_limits(&mut self, optcode: &OPTCODE) -> Result<(), RuntimeError> {
+        if let Some(limit) = self.limits.max_stack_size {
+            if self.stack.len() > limit {
+                return Err(RuntimeErrorKind::StackSizeExceeded { limit }.into());
             }
-            BuiltinTypes::String =>
-                self.stack.push_back(StackValue::String {
-                    value: data.to_string(),
-                }),
-            BuiltinTypes::Object { fields: _ } => panic!("object should not appear in bytecode"),
-            BuiltinTypes::Float =>
-                self.stack.push_back(StackValue::Float { value: data.parse().unwrap() }),
-            BuiltinTypes::Array { element_type: _, length: _ } => todo!(),
+        }
+        // `AssignAtPath` can grow a nested container, so it measures its target itself.
+        // Natives count what they return in `NativeRegistry::call`.
+        let (grown, allocated) = match optcode {
+            OPTCODE::PushToArray { id } | OPTCODE::SetMapValue { id } =>
+                (Some(&self.get_var(*id)?.value), 1),
+            OPTCODE::AssignAtPath { .. } => (None, 1),
+            OPTCODE::CopyValue => (None, self.stack.back().map_or(0, deep_size)),
+            OPTCODE::CopyVariableValue { dst_var_id, .. } =>
+                (None, deep_size(&self.get_var(*dst_var_id)?.value)),
+            OPTCODE::LoadString { .. } |
+            OPTCODE::Add { .. } |
+            OPTCODE::Subtract { .. } |
+            OPTCODE::Multiply { .. } |
+            OPTCODE::Divide { .. } |
+            OPTCODE::Remainder { .. } |
+            OPTCODE::CreateArray { .. } |
+            OPTCODE::CreateMap { .. } |
+            OPTCODE::GetMapKeys => (self.stack.back(), self.stack.back().map_or(0, value_size)),
+            OPTCODE::CallSpecialFunction { .. } | OPTCODE::CallNativeFunction { .. } =>
+                (self.stack.back(), 0),
+            _ => (None, 0),
+        };
+        if let Some(value) = grown {
+            self.check_value_size(value)?;
+        }
+        self.allocate(allocated)
+    }
+    /// Counts the value on top of the stack, e.g. the result of a native function.
+    pub(crate) fn allocate_top(&mut self) -> Result<(), RuntimeError> {
+        let size = self.stack.back().map_or(0, value_size);
+        self.allocate(size)
+    }
+    /// Adds `size` to the running total and fails if it is over `VmLimits::max_total_allocation`.
+    pub(crate) fn allocate(&mut self, size: usize) -> Result<(), RuntimeError> {
+        self.allocated = self.allocated.saturating_add(size);
+        match self.limits.max_total_allocation {
+            Some(limit) if self.allocated > limit =>
+                Err(RuntimeErrorKind::AllocationLimitExceeded { limit }.into()),
+            _ => Ok(()),
         }
     }
+    /// Fails if `value` is larger than `VmLimits::max_single_value_size`.
+    pub(crate) fn check_value_size(&self, value: &StackValue) -> Result<(), RuntimeError> {
+        if let Some(limit) = self.limits.max_single_value_size {
+            let size = value_size(value);
+            if size > limit {
+                return Err(RuntimeErrorKind::ValueSizeExceeded { limit, size }.into());
+            }
+        }
+        Ok(())
+    }
+    /// Pushes a value written as text, e.g. `"1"` for a true `Bool`.
+    pub fn push(&mut self, data_type: &BuiltinTypes, data: &String) -> Result<(), RuntimeError> {
+        let value = match data_type {
+            BuiltinTypes::Int => data.parse::<BigInt>().ok().map(StackValue::from_big_int),
+            BuiltinTypes::Bool =>
+                match data.as_str() {
+                    "1" => Some(StackValue::Bool { value: true }),
+                    "0" => Some(StackValue::Bool { value: false }),
+                    _ => None,
+                }
+            BuiltinTypes::String => Some(StackValue::String { value: data.to_string() }),
+            BuiltinTypes::Float =>
+                data
+                    .parse()
+                    .ok()
+                    .map(|value| StackValue::Float { value }),
+            BuiltinTypes::Decimal => parse_decimal(data).map(|value| StackValue::Decimal { value }),
+            BuiltinTypes::Object { .. } | BuiltinTypes::Array { .. } | BuiltinTypes::Map { .. } =>
+                None,
+        };
+        let value = value.ok_or_else(|| RuntimeErrorKind::InvalidLiteral {
+            data_type: data_type.clone(),
+            literal: data.to_string(),
+        })?;
+        self.push_stackvalue(value);
+        Ok(())
+    }
     pub fn push_stackvalue(&mut self, stackvalue: StackValue) {
         self.stack.push_back(stackvalue);
     }
@@ -64,20 +217,27 @@ impl VM {
         } else {
             self.stack.pop_back().unwrap()
         };
-        self.testing_stack.push(value);
+        // A snapshot, later changes to a shared array must not show up in the results
+        self.testing_stack.push(value.deep_copy());
+    }
+    pub fn pop(&mut self) -> Result<StackValue, RuntimeError> {
+        self.stack.pop_back().ok_or(RuntimeErrorKind::StackUnderflow.into())
     }
-    pub fn pop(&mut self) -> StackValue {
-        return self.stack.pop_back().unwrap();
+    pub fn peek(&self) -> Result<&StackValue, RuntimeError> {
+        self.stack.back().ok_or(RuntimeErrorKind::StackUnderflow.into())
     }
-    pub fn aritmethics(&mut self, action: &str) -> (StackValue, StackValue, StackValue) {
-        let b = self.stack.pop_back().unwrap();
+    pub fn aritmethics(
+        &mut self,
+        action: &str
+    ) -> Result<(StackValue, StackValue, StackValue), RuntimeError> {
+        let b = self.pop()?;
         let b_clone = b.clone();
-        let a = self.stack.pop_back().unwrap();
+        let a = self.pop()?;
         let a_clone = a.clone();
         let result = match action {
-            "+" => add(a, b),
+            "+" => add(a, b, self.locale),
             "-" => subtract(a, b),
-            "*" => multiply(a, b),
+            "*" => multiply(a, b, self.limits.max_single_value_size),
             "/" => divide(a, b),
             "%" => remainder(a, b),
             "<" => less_than(a, b),
@@ -90,145 +250,221 @@ impl VM {
             "or" => or(a, b),
             "xor" => xor(a, b),
 
-            _ => panic!("Unknown arithmetics operator"),
-        };
+            _ => Err(RuntimeErrorKind::UnknownOperator { operator: action.to_string() }.into()),
+        }?;
         self.stack.push_back(result.clone());
-        return (a_clone, b_clone, result);
+        Ok((a_clone, b_clone, result))
     }
-    pub fn not(&mut self) {
+    pub fn not(&mut self) -> Result<(), RuntimeError> {
         //Pops a stackvalue and pushes a bool value that is inverted
-        let stackvalue = self.pop();
+        let stackvalue = self.pop()?;
         let return_val = match stackvalue {
             StackValue::Bool { value } => !value,
             StackValue::Int { value } => value == 0,
+            StackValue::BigInt { value: _ } => false,
             StackValue::Float { value } => value == 0.0,
-            StackValue::String { value } => value == "",
-            StackValue::Array { value } => value.len() == 0,
+            StackValue::Decimal { value } => value.is_zero(),
+            StackValue::String { value } => value.is_empty(),
+            StackValue::Array { value } => value.borrow().is_empty(),
             StackValue::Object { value: _ } => false,
+            StackValue::Map { value } => value.borrow().is_empty(),
         };
         self.push_stackvalue(StackValue::Bool { value: return_val });
+        Ok(())
     }
     pub fn format_for_print(&mut self, newline: bool) -> String {
         if self.stack.back().is_none() {
             return "".to_string();
         }
-        return format_for_print(&self.stack.pop_back().unwrap(), newline);
+        format_in_locale(&self.stack.pop_back().unwrap(), newline, self.locale)
     }
 
-    pub fn to_bool(value: StackValue) ->bool {
-        return match value {
+    pub fn to_bool(value: StackValue) -> bool {
+        match value {
             StackValue::Bool { value } => value,
             StackValue::Int { value } => value != 0,
+            // Never 0, that would be an `Int`
+            StackValue::BigInt { value: _ } => true,
             StackValue::Float { value } => value != 0.0,
-            StackValue::String { value } => value != "",
-            StackValue::Array { value } => value.len() != 0,
-            StackValue::Object { value } => value.len() != 0,
-        }        
+            StackValue::Decimal { value } => !value.is_zero(),
+            StackValue::String { value } => !value.is_empty(),
+            StackValue::Array { value } => !value.borrow().is_empty(),
+            StackValue::Object { value } => !value.borrow().is_empty(),
+            StackValue::Map { value } => !value.borrow().is_empty(),
+        }
     }
 
-    pub fn must_jump(&mut self) -> bool {
-        let value = self.stack.pop_back().unwrap();
-        return !VM::to_bool(value);
+    pub fn must_jump(&mut self) -> Result<bool, RuntimeError> {
+        let value = self.pop()?;
+        Ok(!VM::to_bool(value))
     }
 
-    
-
-    pub fn define_var(&mut self, id: usize, name: String) {
+    pub fn define_var(&mut self, id: usize) -> Result<StackValue, RuntimeError> {
+        let value = self.pop()?;
+        let variable = Variable {
+            id,
+            value: value.clone(),
+        };
+        match self.call_stack.back_mut() {
+            Some(frame) => frame.locals.insert(id, variable),
+            None => self.variables.insert(id, variable),
+        };
+        Ok(value)
     }
 
-    pub fn assign_var(&mut self, id: usize) {
-        let value = self.stack.pop_back().unwrap();
-        let getter = self.variables.get(&id);
-        if getter.is_none() {
-            panic!("Cound not found vairable with ID {}", id);
-        } else {
-            self.variables.get_mut(&id).unwrap().value = value;
-        }
+    pub fn assign_var(&mut self, id: usize) -> Result<(), RuntimeError> {
+        let value = self.pop()?;
+        self.get_var_mut(id)?.value = value;
+        Ok(())
     }
 
-    pub fn copy_var_value(&mut self, src_id: usize, dst_id: usize) {
-        let src = self.variables.get(&src_id).unwrap().value.clone();
-        let dst = self.variables.get_mut(&dst_id).unwrap();
+    pub fn copy_var_value(&mut self, src_id: usize, dst_id: usize) -> Result<(), RuntimeError> {
+        let src = self.get_var(src_id)?.value.clone();
+        let dst = self.get_var_mut(dst_id)?;
         *dst = Variable { id: dst_id, value: src };
+        Ok(())
     }
 
-    pub fn load_var(&mut self, id: usize) -> StackValue {
-        let getter = self.variables.get(&id);
-        if getter.is_none() {
-            panic!("Cound not found vairable id {}", id);
-        } else {
-            let value = getter.unwrap().value.clone();
-            self.stack.push_back(value.clone());
-            return value;
-        }
+    pub fn load_var(&mut self, id: usize) -> Result<StackValue, RuntimeError> {
+        let value = self.get_var(id)?.value.clone();
+        self.stack.push_back(value.clone());
+        Ok(value)
+    }
 
+    /// Looks the variable up in the current frame first and then in the globals.
+    pub(crate) fn get_var(&self, id: usize) -> Result<&Variable, RuntimeError> {
+        if let Some(variable) = self.call_stack.back().and_then(|frame| frame.locals.get(&id)) {
+            return Ok(variable);
+        }
+        self.variables.get(&id).ok_or(RuntimeErrorKind::UnknownVariable { id }.into())
     }
 
-    pub fn input(&mut self, prompt: &str) {
-        print!("{}", prompt);
-        let _ = io::stdout().flush();
-        let res = io
-            ::stdin()
-            .lock()
-            .lines()
-            .next()
-            .unwrap()
-            .map(|x| x.trim_end().to_owned());
-        self.stack.push_back(StackValue::String {
-            value: res.unwrap(),
-        });
+    pub(crate) fn get_var_mut(&mut self, id: usize) -> Result<&mut Variable, RuntimeError> {
+        let is_local = self.call_stack
+            .back()
+            .is_some_and(|frame| frame.locals.contains_key(&id));
+        let variable = if is_local {
+            self.call_stack.back_mut().and_then(|frame| frame.locals.get_mut(&id))
+        } else {
+            self.variables.get_mut(&id)
+        };
+        variable.ok_or(RuntimeErrorKind::UnknownVariable { id }.into())
     }
 
-    pub fn call_function(&mut self, name: &String, program: &mut CelsiumProgram) {
-        for function in program.functions.clone() {
-            if function.signature.name == name.to_string() {
-                program.run(self, &function.body.bytecode);
+    /// Pushes a new frame and binds the arguments from the stack to their local variable slots.
+    /// Arguments are only popped when every one of them has a `local_var_id`,
+    /// otherwise the function body is expected to take them from the stack itself.
+    pub(crate) fn enter_function(
+        &mut self,
+        return_index: usize,
+        signature: &FunctionSignature
+    ) -> Result<(), RuntimeError> {
+        if let Some(limit) = self.limits.max_call_depth {
+            if self.call_stack.len() >= limit {
+                return Err(RuntimeErrorKind::CallDepthExceeded { limit }.into());
+            }
+        }
+        let mut locals = HashMap::new();
+        if signature.args.iter().all(|arg| arg.local_var_id.is_some()) {
+            for arg in signature.args.iter().rev() {
+                let id = arg.local_var_id.unwrap();
+                locals.insert(id, Variable { id, value: self.pop()? });
             }
         }
+        self.call_stack.push_back(CallStackItem {
+            optode_index: return_index,
+            function_name: Some(signature.name.clone()),
+            locals,
+            return_type: signature.return_type.clone(),
+            stack_base: self.stack.len(),
+        });
+        Ok(())
     }
-    pub fn simple_loop(&mut self, program: &mut CelsiumProgram, loop_block: Vec<OPTCODE>) {
-        let count = self.stack.pop_back().unwrap();
-        match count {
-            StackValue::Int { value } => {
-                let mut counter = 0;
-                while counter < value {
-                    program.run(self, &loop_block);
-                    counter += 1;
-                }
+
+    /// Pops the current frame, clears whatever the callee left on the stack
+    /// and pushes `value` for the caller after checking it against the return type.
+    /// A plain return from a function with a return type uses the callee's only stack value, if it left one.
+    /// Returns the index to continue from, or `None` if there is no frame, meaning the program has ended.
+    pub(crate) fn return_from_function(
+        &mut self,
+        value: Option<StackValue>
+    ) -> Result<Option<usize>, RuntimeError> {
+        let frame = match self.call_stack.pop_back() {
+            Some(frame) => frame,
+            None => {
+                return Ok(None);
+            }
+        };
+        // Frontends from before `ReturnValue` leave the result on the stack and use a plain `Return`
+        let value = match value {
+            None if frame.return_type.is_some() && self.stack.len() == frame.stack_base + 1 =>
+                self.stack.pop_back(),
+            value => value,
+        };
+        let _ = self.stack.split_off(frame.stack_base.min(self.stack.len()));
+        match (&frame.return_type, value) {
+            (None, None) => (),
+            (Some(return_type), Some(value)) if value.is_of_type(return_type) => {
+                self.stack.push_back(value);
+            }
+            (expected, found) => {
+                return Err(
+                    (RuntimeErrorKind::ReturnTypeMismatch {
+                        expected: expected.clone(),
+                        found: found.map(|value| value.type_name()),
+                    }).into()
+                );
             }
-            _ => panic!(),
         }
+        Ok(Some(frame.optode_index))
     }
-    pub fn get_object_field(&mut self, field_name: &str) {
-        let object = self.stack.pop_back().unwrap();
+
+    /// Writes the prompt and pushes the line read from the I/O host, or an empty string at the end of input.
+    pub fn input(&mut self, prompt: &str) {
+        let line = {
+            let mut io = self.io.borrow_mut();
+            io.write(prompt);
+            io.flush();
+            io.read_line()
+        };
+        self.stack.push_back(StackValue::String {
+            value: line.unwrap_or_default(),
+        });
+    }
+
+    pub fn get_object_field(&mut self, field_name: &str) -> Result<(), RuntimeError> {
+        let object = self.pop()?;
         match object {
             StackValue::Object { value } => {
-                for field in value {
-                    if field.name == field_name {
-                        self.stack.push_back(field.value);
-                        break;
-                    }
+                let field_value = value
+                    .borrow()
+                    .iter()
+                    .find(|field| field.name == field_name)
+                    .map(|field| field.value.clone());
+                if let Some(field_value) = field_value {
+                    self.stack.push_back(field_value);
+                    return Ok(());
                 }
+                Err(RuntimeErrorKind::MissingField { field_name: field_name.to_string() }.into())
             }
-            _ => panic!("not an object"),
+            other => Err(RuntimeError::type_mismatch(".", &other, None)),
         }
     }
-    pub fn set_object_field(&mut self, id: usize, field_name: &str) {
-        let new_field_value = self.stack.pop_back().unwrap();
-        let getter = self.variables.get_mut(&id);
-        if getter.is_none() {
-            panic!("Cound not found vairable with ID {}", id);
-        } else {
-            match &mut getter.unwrap().value {
-                StackValue::Object { value } => {
-                    for field in value {
-                        if field.name == field_name {
-                            field.value = new_field_value.clone();
-                        }
+    pub fn set_object_field(&mut self, id: usize, field_name: &str) -> Result<(), RuntimeError> {
+        let new_field_value = self.pop()?;
+        let object = &self.get_var(id)?.value;
+        new_field_value.check_storable_in(object)?;
+        match object {
+            StackValue::Object { value } => {
+                for field in value.borrow_mut().iter_mut() {
+                    if field.name == field_name {
+                        field.value = new_field_value;
+                        return Ok(());
                     }
                 }
-                _ => panic!(),
+                Err(RuntimeErrorKind::MissingField { field_name: field_name.to_string() }.into())
             }
+            other => Err(RuntimeError::type_mismatch(".", other, None)),
         }
     }
 }
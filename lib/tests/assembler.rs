@@ -0,0 +1,121 @@
+//! `print_program` writes assembly that assembles back into the same program.
+
+use celsium::{
+    assembler::{ assemble, assemble_program, format_optcode, print_bytecode, print_program },
+    bytecode::OPTCODE,
+    CelsiumProgram,
+};
+
+const PROGRAM: &str = r#"
+    LoadString "say \"hi\"\n\tand\\ leave"
+    LoadDecimal 0,25
+    LoadFloat 1.5
+    LoadBool false
+    CreateObject a b c
+    DefineVar o #1
+    LoadInt 3
+    CallFunction sum_to
+    PushToTestingStack duplicate
+    LoadString "x"
+    CallSpecialFunction lielie_burti
+    PushToTestingStack
+    LoadString "k"
+    LoadInt 1
+    CreateMap 1
+    DefineVar m #2
+    LoadInt 7
+    LoadString "k"
+    AssignAtPath #2 []
+    LoadVar m #2
+    CallFunction show
+    LoadInt 0
+    DefineVar copy #3
+    CopyVariableValue #2 -> #3
+    LoadVar o #1
+    GetObjectField a
+    JumpIfFalse end
+    LoadInt 1
+    PushToTestingStack
+end:
+.function sum_to(n: Int #10) -> Int
+    LoadInt 0
+    DefineVar total #11
+loop:
+    LoadVar n #10
+    LoadInt 0
+    LargerThan
+    JumpIfFalse done
+    LoadVar total #11
+    LoadVar n #10
+    Add
+    AssignVar #11
+    LoadVar n #10
+    LoadInt 1
+    Subtract
+    AssignVar #10
+    JumpBack loop
+done:
+    LoadVar total #11
+    ReturnValue
+.end
+.function show(values: {String: Int} #20)
+    LoadVar values #20
+    PushToTestingStack
+.end
+"#;
+
+fn results(program: &mut CelsiumProgram) -> Vec<String> {
+    program
+        .run_program()
+        .unwrap()
+        .iter()
+        .map(|value| value.to_string())
+        .collect()
+}
+
+#[test]
+fn listing_assembles_back_into_the_program() {
+    let mut program = assemble_program(PROGRAM).unwrap();
+    let listing = print_program(&program);
+    let mut reassembled = assemble_program(&listing).unwrap_or_else(|error| {
+        panic!("{}\n{}", error, listing)
+    });
+    assert_eq!(print_program(&reassembled), listing);
+    assert_eq!(results(&mut reassembled), results(&mut program));
+    // Running links the natives in both
+    assert_eq!(reassembled.clone().get_linked_bytecode_json(), program.clone().get_linked_bytecode_json());
+}
+
+#[test]
+fn linked_calls_are_written_by_name() {
+    let mut program = assemble_program(PROGRAM).unwrap();
+    results(&mut program);
+    let listing = print_program(&program);
+    assert!(listing.contains("CallFunction sum_to"), "{}", listing);
+    assert!(listing.contains("CallSpecialFunction lielie_burti"), "{}", listing);
+    assert!(!listing.contains("JumpToFunction") && !listing.contains("CallNativeFunction"));
+    let mut reassembled = assemble_program(&listing).unwrap();
+    assert_eq!(results(&mut reassembled), results(&mut program));
+}
+
+#[test]
+fn jumps_get_labels_and_indexes_are_comments() {
+    let listing = print_bytecode(&assemble("LoadBool true\nJumpIfFalse +1\nJumpBack -3").unwrap());
+    assert_eq!(
+        listing,
+        [
+            "L0000:",
+            "    LoadBool true                    ; 0000",
+            "    JumpIfFalse L0003                ; 0001",
+            "    JumpBack L0000                   ; 0002",
+            "L0003:",
+            "",
+        ].join("\n")
+    );
+    // Jumps out of the listing keep their step count
+    assert_eq!(format_optcode(&OPTCODE::Jump { steps: 40 }), "Jump +40");
+    assert_eq!(
+        print_bytecode(&[OPTCODE::Jump { steps: 40 }]),
+        "    Jump +40                         ; 0000\n"
+    );
+}
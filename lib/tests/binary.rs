@@ -0,0 +1,121 @@
+//! `to_bytes` and `from_bytes` round trip programs and reject corrupt input without panicking.
+
+use celsium::{
+    assembler::assemble_program,
+    binary::{ DecodeError, FORMAT_VERSION, MAGIC, MAX_TYPE_DEPTH },
+    CelsiumProgram,
+};
+
+const PROGRAM: &str = r#"
+    LoadString "sveiki, pasaule"
+    LoadDecimal 1,25
+    Add
+    PushToTestingStack
+    LoadString "a"
+    LoadInt 1
+    CreateMap 1
+    CallFunction keys
+    PushToTestingStack
+    LoadInt 6
+    CallFunction half
+    PushToTestingStack
+.function keys(map: {String: Int} #10) -> [String]
+    LoadVar map #10
+    GetMapKeys
+    ReturnValue
+.end
+.function half(n: Int #20) -> Decimal
+    LoadVar n #20
+    LoadDecimal 0,5
+    Multiply
+    ReturnValue
+.end
+"#;
+
+fn results(program: &mut CelsiumProgram) -> Vec<String> {
+    program
+        .run_program()
+        .unwrap()
+        .iter()
+        .map(|value| value.to_string())
+        .collect()
+}
+
+#[test]
+fn programs_round_trip() {
+    let mut program = assemble_program(PROGRAM).unwrap();
+    let bytes = program.to_bytes();
+    assert_eq!(&bytes[..4], MAGIC);
+    let mut decoded = CelsiumProgram::from_bytes(&bytes).unwrap();
+    assert_eq!(decoded.to_bytes(), bytes);
+    assert_eq!(results(&mut decoded), results(&mut program));
+    assert_eq!(results(&mut decoded), vec!["sveiki, pasaule1,25", "[\"a\"]", "3"]);
+}
+
+#[test]
+fn wrong_magic_and_version_are_rejected() {
+    let bytes = assemble_program(PROGRAM).unwrap().to_bytes();
+    let mut wrong_magic = bytes.clone();
+    wrong_magic[0] = b'X';
+    assert_eq!(CelsiumProgram::from_bytes(&wrong_magic).unwrap_err(), DecodeError::InvalidMagic);
+
+    let mut newer = bytes.clone();
+    newer[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    assert_eq!(
+        CelsiumProgram::from_bytes(&newer).unwrap_err(),
+        DecodeError::UnsupportedVersion { version: FORMAT_VERSION + 1 }
+    );
+}
+
+#[test]
+fn truncated_and_padded_input_is_rejected() {
+    let bytes = assemble_program(PROGRAM).unwrap().to_bytes();
+    for length in 0..bytes.len() {
+        assert!(CelsiumProgram::from_bytes(&bytes[..length]).is_err(), "length {}", length);
+    }
+    let mut padded = bytes.clone();
+    padded.push(0);
+    assert_eq!(CelsiumProgram::from_bytes(&padded).unwrap_err(), DecodeError::TrailingBytes);
+}
+
+#[test]
+fn corrupt_bytes_never_panic() {
+    let bytes = assemble_program(PROGRAM).unwrap().to_bytes();
+    for index in 0..bytes.len() {
+        for flip in [0x01, 0x80, 0xff] {
+            let mut corrupt = bytes.clone();
+            corrupt[index] ^= flip;
+            // Some flips still give a valid program, the point is that decoding returns
+            let _ = CelsiumProgram::from_bytes(&corrupt);
+        }
+    }
+}
+
+#[test]
+fn decoded_programs_are_verified() {
+    let bytes = assemble_program("Add").unwrap().to_bytes();
+    assert!(
+        matches!(CelsiumProgram::from_bytes(&bytes), Err(DecodeError::InvalidBytecode { .. }))
+    );
+}
+
+#[test]
+fn programs_round_trip_through_json() {
+    let bytes = assemble_program(PROGRAM).unwrap().to_bytes();
+    let json = CelsiumProgram::from_bytes(&bytes).unwrap().get_linked_bytecode_json();
+    assert_eq!(CelsiumProgram::from_bytecode_json(json).unwrap().to_bytes(), bytes);
+}
+
+#[test]
+fn deeply_nested_types_are_rejected() {
+    let nested = |depth: usize| {
+        let source = format!(
+            ".function f(x: {}Int{} #10)\n.end",
+            "[".repeat(depth),
+            "]".repeat(depth)
+        );
+        CelsiumProgram::from_bytes(&assemble_program(&source).unwrap().to_bytes())
+    };
+    assert!(nested(MAX_TYPE_DEPTH).is_ok());
+    assert_eq!(nested(MAX_TYPE_DEPTH + 1).unwrap_err(), DecodeError::TypeTooDeep);
+}
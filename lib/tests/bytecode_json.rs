@@ -0,0 +1,91 @@
+//! `get_linked_bytecode_json` and `parse_bytecode` with `from_bytecode` give back a runnable program.
+
+use celsium::{
+    assembler::assemble_program,
+    bytecode_parser::{ parse_bytecode, LoadError },
+    CelsiumProgram,
+};
+
+const FACTORIAL: &str = r#"
+    LoadInt 5
+    CallFunction fakt
+    PushToTestingStack
+.function fakt(n: Int #10) -> Int
+    LoadVar n #10
+    LoadInt 1
+    LessOrEq
+    JumpIfFalse recurse
+    LoadInt 1
+    ReturnValue
+recurse:
+    LoadVar n #10
+    LoadVar n #10
+    LoadInt 1
+    Subtract
+    CallFunction fakt
+    Multiply
+    ReturnValue
+.end
+"#;
+
+fn results(program: &mut CelsiumProgram) -> Vec<String> {
+    program
+        .run_program()
+        .unwrap()
+        .iter()
+        .map(|value| value.to_string())
+        .collect()
+}
+
+#[test]
+fn program_with_functions_round_trips() {
+    let mut program = assemble_program(FACTORIAL).unwrap();
+    let json = program.clone().get_linked_bytecode_json();
+    let mut loaded = CelsiumProgram::from_bytecode(parse_bytecode(json.clone()).unwrap()).unwrap();
+    assert_eq!(results(&mut loaded), results(&mut program));
+    assert_eq!(results(&mut loaded), vec!["120"]);
+    assert_eq!(loaded.get_linked_bytecode_json(), json);
+}
+
+#[test]
+fn plain_bytecode_is_accepted() {
+    let json = r#"[{"LoadInt":{"value":2}},{"PushToTestingStack":{"duplicate_stackvalue":false}}]"#;
+    let mut program = CelsiumProgram::from_bytecode_json(json.to_string()).unwrap();
+    assert_eq!(results(&mut program), vec!["2"]);
+    // The old method still writes only the array
+    assert_eq!(program.get_bytecode_json(), json);
+}
+
+#[test]
+fn function_outside_of_the_bytecode_is_rejected() {
+    // `f` starts at its `Return`, but claims five optcodes
+    let json = r#"{
+        "bytecode": [
+            {"JumpToFunction": {"target": 1, "function": 0, "function_name": "f"}},
+            "Return",
+            "Return"
+        ],
+        "functions": [
+            {"id": 0, "signature": {"name": "f", "return_type": null, "args": []}, "offset": 2, "length": 5}
+        ]
+    }"#;
+    assert_eq!(
+        CelsiumProgram::from_bytecode_json(json.to_string()).unwrap_err(),
+        LoadError::InvalidFunctionRange { name: "f".to_string() }
+    );
+}
+
+#[test]
+fn malformed_json_is_an_error() {
+    for json in ["", "[{\"LoadInt\":", "{\"bytecode\": 5}", "[{\"NoSuchOptcode\":{}}]"] {
+        let error = CelsiumProgram::from_bytecode_json(json.to_string()).unwrap_err();
+        assert!(matches!(error, LoadError::InvalidJson { .. }), "{}: {:?}", json, error);
+    }
+}
+
+#[test]
+fn unverifiable_bytecode_is_an_error() {
+    let json = r#"[{"Add":{"node_id":0}}]"#;
+    let error = CelsiumProgram::from_bytecode_json(json.to_string()).unwrap_err();
+    assert!(matches!(error, LoadError::InvalidBytecode { .. }));
+}
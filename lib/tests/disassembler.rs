@@ -0,0 +1,76 @@
+//! The disassembler prints the linked bytecode as an indexed listing.
+
+use celsium::{
+    assembler::{ assemble, assemble_program },
+    bytecode::OPTCODE,
+    disassembler::{ disassemble_bytecode, format_optcode },
+};
+
+#[test]
+fn listing_shows_indexes_jump_targets_and_functions() {
+    let program = assemble_program(
+        r#"
+        LoadInt 3
+        DefineVar n #1
+        LoadVar n #1
+        CallFunction double
+        JumpIfFalse +1
+        LoadString "liels"
+        PushToTestingStack
+    .function double(x: Int #10) -> Int
+        LoadVar x #10
+        LoadInt 2
+        Multiply
+        ReturnValue
+    .end
+        "#
+    ).unwrap();
+    assert_eq!(
+        program.disassemble(),
+        [
+            "0000  LoadInt 3",
+            "0001  DefineVar n (#1)",
+            "0002  LoadVar n (#1)",
+            "0003  JumpToFunction double -> 0008",
+            "0004  JumpIfFalse +1 -> 0006",
+            "0005  LoadString \"liels\"",
+            "0006  PushToTestingStack",
+            "0007  Return",
+            "",
+            "double:",
+            "0008  LoadVar x (#10)",
+            "0009  LoadInt 2",
+            "0010  Multiply",
+            "0011  ReturnValue",
+            "0012  Return",
+            "",
+        ].join("\n")
+    );
+}
+
+#[test]
+fn jumps_show_the_index_they_land_on() {
+    let listing = disassemble_bytecode(&assemble("LoadBool true\nJumpIfFalse +1\nJumpBack -3").unwrap());
+    assert_eq!(
+        listing,
+        "0000  LoadBool true\n0001  JumpIfFalse +1 -> 0003\n0002  JumpBack -3 -> 0000\n"
+    );
+    assert_eq!(format_optcode(&OPTCODE::Jump { steps: 5 }, 12), "Jump +5 -> 0018");
+    // Bytecode that was not verified still prints
+    assert_eq!(
+        format_optcode(&OPTCODE::Jump { steps: usize::MAX }, 1),
+        format!("Jump +{} -> {:04}", usize::MAX, usize::MAX)
+    );
+}
+
+#[test]
+fn listing_reads_back_with_the_assembler() {
+    // Without the index column the lines are assembly, ids in parentheses included
+    let listing = disassemble_bytecode(&assemble("LoadInt 1\nDefineVar a #4\nLoadVar a #4").unwrap());
+    let lines: Vec<&str> = listing
+        .lines()
+        .map(|line| &line[6..])
+        .collect();
+    assert_eq!(lines, vec!["LoadInt 1", "DefineVar a (#4)", "LoadVar a (#4)"]);
+    assert_eq!(assemble(&lines.join("\n")).unwrap().len(), 3);
+}
@@ -0,0 +1,119 @@
+//! An `Execution` runs a program one optcode at a time and stops at breakpoints,
+//! with the stack, variables and calls visible in between.
+
+use celsium::{
+    assembler::assemble_program,
+    bytecode::OPTCODE,
+    execution::{ Execution, StepResult, StopReason },
+    vm::{ runtime_error::RuntimeErrorKind, StackValue },
+};
+
+const PROGRAM: &str = r#"
+    LoadInt 4
+    DefineVar x #1
+    LoadVar x #1
+    CallFunction double
+    PushToTestingStack
+.function double(n: Int #10) -> Int
+    LoadVar n #10
+    LoadVar n #10
+    Add
+    ReturnValue
+.end
+"#;
+
+/// Index of the first `Add`, inside `double`
+const ADD: usize = 8;
+
+fn start(source: &str) -> Execution {
+    assemble_program(source).unwrap().start_execution()
+}
+
+fn ints(values: Vec<&StackValue>) -> Vec<i64> {
+    values
+        .into_iter()
+        .map(|value| match value {
+            StackValue::Int { value } => *value,
+            other => panic!("expected an Int, got {}", other),
+        })
+        .collect()
+}
+
+#[test]
+fn stepping_runs_one_optcode_at_a_time() {
+    let mut execution = start(PROGRAM);
+    assert_eq!(execution.index(), 0);
+    assert!(matches!(execution.current_optcode(), Some(OPTCODE::LoadInt { value: 4 })));
+    assert_eq!(execution.step().unwrap(), StepResult::Paused);
+    assert_eq!(ints(execution.stack()), vec![4]);
+    assert_eq!(execution.step().unwrap(), StepResult::Paused);
+    assert!(execution.stack().is_empty());
+    assert_eq!(execution.variables()[0].id, 1);
+
+    let mut steps = 2;
+    while execution.step().unwrap() == StepResult::Paused {
+        steps += 1;
+    }
+    // Six optcodes in main, with the linker's Return, and four in the function
+    assert_eq!(steps + 1, 10);
+    assert!(execution.is_finished());
+    assert!(execution.current_optcode().is_none());
+    assert_eq!(execution.vm().testing_stack(), vec![StackValue::Int { value: 8 }]);
+    // Stepping past the end does nothing
+    assert_eq!(execution.step().unwrap(), StepResult::Finished);
+}
+
+#[test]
+fn breakpoints_stop_inside_functions() {
+    let mut execution = start(PROGRAM);
+    execution.add_breakpoint(ADD);
+    assert_eq!(execution.run_until_breakpoint().unwrap(), StopReason::Breakpoint { index: ADD });
+    assert!(matches!(execution.current_optcode(), Some(OPTCODE::Add { .. })));
+    assert_eq!(ints(execution.stack()), vec![4, 4]);
+
+    let call_stack = execution.call_stack();
+    assert_eq!(call_stack.len(), 1);
+    assert_eq!(call_stack[0].function_name.as_deref(), Some("double"));
+    // The argument shadows nothing, so the global is still visible next to it
+    let ids: Vec<usize> = execution
+        .variables()
+        .iter()
+        .map(|variable| variable.id)
+        .collect();
+    assert_eq!(ids, vec![1, 10]);
+
+    assert_eq!(execution.run_until_breakpoint().unwrap(), StopReason::Finished);
+    assert!(execution.call_stack().is_empty());
+    assert_eq!(execution.vm().testing_stack(), vec![StackValue::Int { value: 8 }]);
+}
+
+#[test]
+fn breakpoints_hit_every_time_until_removed() {
+    let source = format!(
+        "LoadInt 1\nCallFunction double\nCallFunction double\nCallFunction double\nPushToTestingStack\n{}",
+        &PROGRAM[PROGRAM.find(".function").unwrap()..]
+    );
+    let mut execution = start(&source);
+    // Main has as many optcodes as in `PROGRAM`, so the function starts at the same index
+    execution.add_breakpoint(ADD);
+    let mut sums = vec![];
+    while execution.run_until_breakpoint().unwrap() != StopReason::Finished {
+        sums.push(ints(execution.stack()));
+        if sums.len() == 2 {
+            execution.remove_breakpoint(ADD);
+        }
+    }
+    assert_eq!(sums, vec![vec![1, 1], vec![2, 2]]);
+    assert_eq!(execution.vm().testing_stack(), vec![StackValue::Int { value: 8 }]);
+}
+
+#[test]
+fn errors_finish_the_execution() {
+    let mut execution = start("LoadInt 1\nLoadInt 0\nDivide\nPushToTestingStack");
+    execution.add_breakpoint(3);
+    let error = execution.run_until_breakpoint().unwrap_err();
+    assert_eq!(error.kind, RuntimeErrorKind::DivisionByZero);
+    assert_eq!(error.optcode_index, Some(2));
+    assert!(execution.is_finished());
+    assert_eq!(execution.step().unwrap(), StepResult::Finished);
+}
@@ -0,0 +1,180 @@
+//! Every call gets its own frame of local variables, so functions can recurse,
+//! and returns are checked against the function's return type.
+
+use celsium::{
+    assembler::assemble_program,
+    vm::runtime_error::{ RuntimeError, RuntimeErrorKind },
+    BuiltinTypes,
+};
+
+fn run(source: &str) -> Result<Vec<String>, RuntimeError> {
+    let results = assemble_program(source).unwrap().run_program()?;
+    Ok(
+        results
+            .iter()
+            .map(|value| value.to_string())
+            .collect()
+    )
+}
+
+#[test]
+fn functions_recurse() {
+    let source = r#"
+        LoadInt 15
+        CallFunction fib
+        PushToTestingStack
+    .function fib(n: Int #10) -> Int
+        LoadVar n #10
+        LoadInt 2
+        LessThan
+        JumpIfFalse recurse
+        LoadVar n #10
+        ReturnValue
+    recurse:
+        LoadVar n #10
+        LoadInt 1
+        Subtract
+        CallFunction fib
+        LoadVar n #10
+        LoadInt 2
+        Subtract
+        CallFunction fib
+        Add
+        ReturnValue
+    .end
+    "#;
+    assert_eq!(run(source).unwrap(), vec!["610"]);
+}
+
+#[test]
+fn locals_survive_nested_calls() {
+    let source = r#"
+        LoadInt 3
+        CallFunction count
+        PushToTestingStack
+    .function count(n: Int #10) -> Int
+        LoadVar n #10
+        LoadInt 10
+        Multiply
+        DefineVar tens #11
+        LoadVar n #10
+        LoadInt 0
+        LargerThan
+        JumpIfFalse done
+        LoadVar n #10
+        LoadInt 1
+        Subtract
+        CallFunction count
+        PushToTestingStack
+    done:
+        LoadVar tens #11
+        ReturnValue
+    .end
+    "#;
+    assert_eq!(run(source).unwrap(), vec!["0", "10", "20", "30"]);
+}
+
+#[test]
+fn functions_see_globals_but_locals_do_not_leak() {
+    let source = r#"
+        LoadInt 5
+        DefineVar g #1
+        CallFunction f
+        LoadVar g #1
+        PushToTestingStack
+        LoadVar local #11
+    .function f()
+        LoadVar g #1
+        LoadInt 1
+        Add
+        AssignVar #1
+        LoadInt 0
+        DefineVar local #11
+    .end
+    "#;
+    let error = run(source).unwrap_err();
+    assert_eq!(error.kind, RuntimeErrorKind::UnknownVariable { id: 11 });
+    let without_leak = source.replace("LoadVar local #11\n", "");
+    assert_eq!(run(&without_leak).unwrap(), vec!["6"]);
+}
+
+#[test]
+fn arguments_without_ids_stay_on_the_stack() {
+    let source = r#"
+        LoadInt 10
+        LoadInt 4
+        CallFunction minus
+        PushToTestingStack
+    .function minus(a: Int, b: Int) -> Int
+        Subtract
+        ReturnValue
+    .end
+    "#;
+    assert_eq!(run(source).unwrap(), vec!["6"]);
+}
+
+#[test]
+fn values_left_by_the_callee_are_dropped() {
+    let source = r#"
+        LoadInt 1
+        CallFunction junk
+        PushToTestingStack
+        Add
+    .function junk()
+        LoadInt 8
+        LoadInt 9
+    .end
+    "#;
+    let error = run(source).unwrap_err();
+    assert_eq!(error.kind, RuntimeErrorKind::StackUnderflow);
+    assert_eq!(run(&source.replace("Add\n", "")).unwrap(), vec!["1"]);
+}
+
+/// `main` calls `f` with the given header and body and keeps the result
+fn call(header: &str, body: &str) -> Result<Vec<String>, RuntimeError> {
+    run(&format!("CallFunction f\nPushToTestingStack\n.function f(){}\n{}\n.end", header, body))
+}
+
+#[test]
+fn results_are_checked_against_the_return_type() {
+    assert_eq!(call(" -> Int", "LoadInt 2\nReturnValue").unwrap(), vec!["2"]);
+    // Ints are accepted as floats, like in variables
+    assert_eq!(call(" -> Float", "LoadInt 2\nReturnValue").unwrap(), vec!["2"]);
+    assert_eq!(
+        call(" -> Int", "LoadString \"2\"\nReturnValue").unwrap_err().kind,
+        RuntimeErrorKind::ReturnTypeMismatch {
+            expected: Some(BuiltinTypes::Int),
+            found: Some("String"),
+        }
+    );
+}
+
+#[test]
+fn plain_returns_still_return_the_value_left_on_the_stack() {
+    // Frontends from before `ReturnValue` end every function with a plain `Return`
+    assert_eq!(call(" -> Int", "LoadInt 2\nReturn").unwrap(), vec!["2"]);
+    assert_eq!(call(" -> Int", "LoadInt 2").unwrap(), vec!["2"]);
+    assert_eq!(
+        call(" -> Int", "LoadString \"2\"\nReturn").unwrap_err().kind,
+        RuntimeErrorKind::ReturnTypeMismatch {
+            expected: Some(BuiltinTypes::Int),
+            found: Some("String"),
+        }
+    );
+}
+
+#[test]
+fn return_values_must_match_the_declaration() {
+    assert_eq!(
+        call(" -> Int", "Return").unwrap_err().kind,
+        RuntimeErrorKind::ReturnTypeMismatch { expected: Some(BuiltinTypes::Int), found: None }
+    );
+    assert_eq!(
+        call(" -> Int", "LoadInt 2\nLoadInt 3").unwrap_err().kind,
+        RuntimeErrorKind::ReturnTypeMismatch { expected: Some(BuiltinTypes::Int), found: None }
+    );
+    assert_eq!(
+        call("", "LoadInt 2\nReturnValue").unwrap_err().kind,
+        RuntimeErrorKind::ReturnTypeMismatch { expected: None, found: Some("Int") }
+    );
+}
@@ -0,0 +1,74 @@
+//! `izvade` and `ievade` go through the program's `IoHost`, so output can be captured and input scripted.
+
+use std::{ cell::RefCell, rc::Rc };
+
+use celsium::{ assembler::assemble_program, io::{ BufferIo, IoHost } };
+
+#[test]
+fn output_is_written_to_the_host() {
+    let io = BufferIo::new(Vec::<String>::new()).shared();
+    let mut program = assemble_program(
+        r#"
+        LoadString "Sveiki"
+        CallSpecialFunction izvade
+        LoadInt 1
+        CallSpecialFunction izvadetp
+        LoadDecimal 2,5
+        CallSpecialFunction izvade
+        "#
+    ).unwrap();
+    program.set_io(io.clone());
+    program.run_program().unwrap();
+    assert_eq!(io.borrow_mut().take_output(), "Sveiki\n12,5\n");
+    assert_eq!(io.borrow().output, "");
+}
+
+#[test]
+fn input_is_read_line_by_line() {
+    let io = BufferIo::new(["Anna", "Bērziņa"]).shared();
+    let mut program = assemble_program(
+        r#"
+        CallSpecialFunction ievade
+        PushToTestingStack
+        CallSpecialFunction ievade
+        PushToTestingStack
+        CallSpecialFunction ievade
+        PushToTestingStack
+        "#
+    ).unwrap();
+    program.set_io(io.clone());
+    let lines: Vec<String> = program
+        .run_program()
+        .unwrap()
+        .iter()
+        .map(|value| value.to_string())
+        .collect();
+    // Past the end of the input lines are empty
+    assert_eq!(lines, vec!["Anna", "Bērziņa", ""]);
+    assert!(io.borrow().input.is_empty());
+}
+
+/// Counts the lines written, to check that any `IoHost` can be used
+#[derive(Debug, Default)]
+struct LineCounter {
+    lines: usize,
+}
+
+impl IoHost for LineCounter {
+    fn write(&mut self, text: &str) {
+        self.lines += text.matches('\n').count();
+    }
+    fn read_line(&mut self) -> Option<String> {
+        None
+    }
+}
+
+#[test]
+fn any_host_can_be_used() {
+    let counter = Rc::new(RefCell::new(LineCounter::default()));
+    let mut program = assemble_program(&"LoadInt 1\nCallSpecialFunction izvade\n".repeat(3)).unwrap();
+    program.set_io(counter.clone());
+    program.run_program().unwrap();
+    program.run_program().unwrap();
+    assert_eq!(counter.borrow().lines, 6);
+}
@@ -0,0 +1,237 @@
+//! Each `VmLimits` limit stops a program that exceeds it with its own error.
+
+use celsium::{
+    assembler::assemble_program,
+    vm::{ limits::VmLimits, runtime_error::RuntimeErrorKind },
+};
+
+fn run_limited(source: &str, limits: VmLimits) -> Result<usize, RuntimeErrorKind> {
+    let mut program = assemble_program(source).unwrap();
+    program.set_limits(limits);
+    program
+        .run_program()
+        .map(|results| results.len())
+        .map_err(|error| error.kind)
+}
+
+fn value_size(limit: usize) -> VmLimits {
+    VmLimits { max_single_value_size: Some(limit), ..VmLimits::default() }
+}
+
+/// `x = x op x` for `x` starting from `start`, `times` times
+fn repeated_operation(start: &str, operator: &str, times: usize) -> String {
+    let mut source = format!("{}\nDefineVar x #1\n", start);
+    for _ in 0..times {
+        source += &format!("LoadVar x #1\nLoadVar x #1\n{}\nAssignVar #1\n", operator);
+    }
+    source
+}
+
+#[test]
+fn big_integers_count_towards_the_value_size() {
+    let source = repeated_operation("LoadInt 3", "Multiply", 30);
+    assert_eq!(
+        run_limited(&source, value_size(100)),
+        Err(RuntimeErrorKind::ValueSizeExceeded { limit: 100, size: 102 })
+    );
+}
+
+#[test]
+fn decimals_count_towards_the_value_size() {
+    let source = repeated_operation("LoadDecimal 0,3", "Multiply", 30);
+    assert!(
+        matches!(
+            run_limited(&source, value_size(100)),
+            Err(RuntimeErrorKind::ValueSizeExceeded { limit: 100, .. })
+        )
+    );
+}
+
+#[test]
+fn differences_and_quotients_are_measured() {
+    // 2^127 fits in 16 bytes, the results need 17 for the numerator and 1 for the denominator
+    let big = format!(
+        "LoadInt {}\nLoadInt {}\nMultiply\nLoadInt 8\nMultiply\n",
+        1_i64 << 62,
+        1_i64 << 62
+    );
+    for (operator, operand) in [("Subtract", "-0,5"), ("Divide", "0,5")] {
+        let source = format!("{}LoadDecimal {}\n{}\nPushToTestingStack", big, operand, operator);
+        assert_eq!(run_limited(&source, value_size(18)), Ok(1), "{}", operator);
+        assert_eq!(
+            run_limited(&source, value_size(17)),
+            Err(RuntimeErrorKind::ValueSizeExceeded { limit: 17, size: 18 }),
+            "{}",
+            operator
+        );
+    }
+}
+
+/// Adds `i: true` at `path` of variable #1 for i = 0, 1, 2, ... until the program fails
+fn fill_at_path(container: &str, indexes: &str, path: &str) -> String {
+    format!(
+        "
+        {container}
+        DefineVar m #1
+        LoadInt 0
+        DefineVar i #2
+    loop:
+        LoadBool true
+        {indexes}
+        LoadVar i #2
+        AssignAtPath #1 {path}
+        LoadVar i #2
+        LoadInt 1
+        Add
+        AssignVar #2
+        JumpBack loop
+        "
+    )
+}
+
+#[test]
+fn assigning_at_a_path_is_measured() {
+    let top_level = fill_at_path("CreateMap 0", "", "[]");
+    let nested = fill_at_path("CreateMap 0\nCreateArray 1", "LoadInt 0", "[] []");
+    for source in [top_level, nested] {
+        assert_eq!(
+            run_limited(&source, value_size(100)),
+            Err(RuntimeErrorKind::ValueSizeExceeded { limit: 100, size: 101 })
+        );
+    }
+}
+
+fn limits(change: impl FnOnce(&mut VmLimits)) -> VmLimits {
+    let mut limits = VmLimits::default();
+    change(&mut limits);
+    limits
+}
+
+#[test]
+fn instructions_are_counted() {
+    // Two optcodes and the `Return` that ends the main block
+    let source = "LoadInt 1\nPushToTestingStack";
+    assert_eq!(run_limited(source, limits(|limits| limits.max_instructions = Some(3))), Ok(1));
+    assert_eq!(
+        run_limited(source, limits(|limits| limits.max_instructions = Some(2))),
+        Err(RuntimeErrorKind::InstructionLimitExceeded { limit: 2 })
+    );
+    assert_eq!(
+        run_limited("forever:\nJumpBack forever", limits(|limits| limits.max_instructions = Some(1000))),
+        Err(RuntimeErrorKind::InstructionLimitExceeded { limit: 1000 })
+    );
+}
+
+/// Calls `down` with `depth`, which calls itself until its argument is 1
+fn recursion(depth: usize) -> String {
+    format!(
+        "
+        LoadInt {depth}
+        CallFunction down
+        PushToTestingStack
+    .function down(n: Int #10) -> Int
+        LoadVar n #10
+        LoadInt 1
+        LessOrEq
+        JumpIfFalse deeper
+        LoadInt 0
+        ReturnValue
+    deeper:
+        LoadVar n #10
+        LoadInt 1
+        Subtract
+        CallFunction down
+        ReturnValue
+    .end
+        "
+    )
+}
+
+#[test]
+fn call_depth_is_limited() {
+    let call_depth = |limit| limits(|limits| limits.max_call_depth = Some(limit));
+    assert_eq!(run_limited(&recursion(10), call_depth(10)), Ok(1));
+    assert_eq!(
+        run_limited(&recursion(11), call_depth(10)),
+        Err(RuntimeErrorKind::CallDepthExceeded { limit: 10 })
+    );
+    assert_eq!(run_limited(&recursion(1000), VmLimits::unlimited()), Ok(1));
+}
+
+#[test]
+fn stack_size_is_limited() {
+    let source = "LoadInt 1\n".repeat(5);
+    let stack_size = |limit| limits(|limits| limits.max_stack_size = Some(limit));
+    assert_eq!(run_limited(&source, stack_size(5)), Ok(0));
+    assert_eq!(
+        run_limited(&source, stack_size(4)),
+        Err(RuntimeErrorKind::StackSizeExceeded { limit: 4 })
+    );
+}
+
+#[test]
+fn strings_and_arrays_are_measured() {
+    let text = repeated_operation("LoadString \"ab\"", "Add", 10);
+    assert_eq!(
+        run_limited(&text, value_size(100)),
+        Err(RuntimeErrorKind::ValueSizeExceeded { limit: 100, size: 128 })
+    );
+    let mut pushes = "CreateArray 0\nDefineVar a #1\n".to_string();
+    pushes += &"LoadInt 1\nPushToArray #1\n".repeat(4);
+    assert_eq!(run_limited(&pushes, value_size(4)), Ok(0));
+    assert_eq!(
+        run_limited(&(pushes + "LoadInt 1\nPushToArray #1\n"), value_size(4)),
+        Err(RuntimeErrorKind::ValueSizeExceeded { limit: 4, size: 5 })
+    );
+}
+
+fn total(limit: usize) -> VmLimits {
+    VmLimits { max_single_value_size: Some(1000), max_total_allocation: Some(limit), ..VmLimits::default() }
+}
+
+/// Pushes a new ten byte string to an array, forever
+const MANY_SMALL_VALUES: &str = r#"
+    CreateArray 0
+    DefineVar a #1
+loop:
+    LoadString "0123456789"
+    PushToArray #1
+    JumpBack loop
+"#;
+
+#[test]
+fn values_under_the_single_limit_are_capped_together() {
+    // Each string is 10 and each push 1, so the 46th string goes over 500
+    assert_eq!(
+        run_limited(MANY_SMALL_VALUES, total(500)),
+        Err(RuntimeErrorKind::AllocationLimitExceeded { limit: 500 })
+    );
+    let limited = limits(|limits| {
+        limits.max_total_allocation = Some(500);
+        limits.max_instructions = Some(60);
+    });
+    // Within 60 instructions only 19 strings are built, far under the total
+    assert_eq!(
+        run_limited(MANY_SMALL_VALUES, limited),
+        Err(RuntimeErrorKind::InstructionLimitExceeded { limit: 60 })
+    );
+}
+
+#[test]
+fn copies_count_everything_nested() {
+    // Building takes 3 + 3 + 2 + 2 and the copy 2 + 2 * (2 + 3 + 3)
+    let source = r#"
+        LoadString "abc"
+        LoadString "abc"
+        CreateArray 2
+        DefineVar inner #1
+        LoadVar inner #1
+        LoadVar inner #1
+        CreateArray 2
+        CopyValue
+        PushToTestingStack
+    "#;
+    assert_eq!(run_limited(source, total(28)), Ok(1));
+    assert_eq!(run_limited(source, total(27)), Err(RuntimeErrorKind::AllocationLimitExceeded { limit: 27 }));
+    assert_eq!(run_limited(&source.replace("CopyValue", ""), total(10)), Ok(1));
+}
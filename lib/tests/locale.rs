@@ -0,0 +1,92 @@
+//! Values are printed and joined to text in the program's `Locale`.
+
+use celsium::{
+    assembler::assemble_program,
+    bytecode::BINOP,
+    locale::Locale,
+    std::get_std_functions,
+    typecheck::{ Suggestion, TypeErrorKind },
+    typestack::TypeStack,
+    BuiltinTypes,
+};
+
+fn run_in(locale: Locale, source: &str) -> Vec<String> {
+    let mut program = assemble_program(source).unwrap();
+    program.set_locale(locale);
+    program
+        .run_program()
+        .unwrap()
+        .iter()
+        .map(|value| locale.format_value(value))
+        .collect()
+}
+
+const JOINS: &str = r#"
+    LoadString "x="
+    LoadFloat 0.5
+    Add
+    PushToTestingStack
+    LoadDecimal 1,25
+    LoadString " m"
+    Add
+    PushToTestingStack
+"#;
+
+#[test]
+fn numbers_are_joined_with_the_decimal_separator_of_the_locale() {
+    assert_eq!(run_in(Locale::Latvian, JOINS), vec!["x=0,5", "1,25 m"]);
+    assert_eq!(run_in(Locale::English, JOINS), vec!["x=0.5", "1.25 m"]);
+    assert_eq!(run_in(Locale::Lithuanian, JOINS), vec!["x=0,5", "1,25 m"]);
+}
+
+#[test]
+fn printed_values_follow_the_locale() {
+    let source = r#"
+        LoadBool true
+        PushToTestingStack
+        LoadInt 1
+        LoadFloat 2.5
+        CreateArray 2
+        PushToTestingStack
+    "#;
+    assert_eq!(run_in(Locale::Latvian, source), vec!["Jā", "[1;2,5]"]);
+    assert_eq!(run_in(Locale::English, source), vec!["Yes", "[1, 2.5]"]);
+}
+
+#[test]
+fn teksts_converts_in_the_locale() {
+    let source = r#"
+        LoadString "Atbilde: "
+        LoadBool true
+        CallSpecialFunction teksts
+        Add
+        PushToTestingStack
+        LoadDecimal 2,5
+        CallSpecialFunction teksts
+        PushToTestingStack
+    "#;
+    assert_eq!(run_in(Locale::Latvian, source), vec!["Atbilde: Jā", "2,5"]);
+    assert_eq!(run_in(Locale::English, source), vec!["Atbilde: Yes", "2.5"]);
+    let std = get_std_functions();
+    let signature = std.iter().find(|function| function.name == "teksts").unwrap();
+    assert_eq!(signature.args.len(), 1);
+    assert_eq!(signature.return_type, Some(BuiltinTypes::String));
+}
+
+#[test]
+fn joining_text_with_a_bool_suggests_teksts() {
+    let mut type_stack = TypeStack::new();
+    type_stack.push(BuiltinTypes::String);
+    type_stack.push(BuiltinTypes::Bool);
+    let kind: TypeErrorKind = type_stack.binop(BINOP::Add).unwrap_err().kind;
+    assert_eq!(kind.suggestion(), Some(Suggestion::ConvertToText));
+    assert!(kind.to_string().ends_with(", convert the value to text with teksts()"));
+    assert_eq!(
+        Locale::Latvian.suggestion(Suggestion::ConvertToText),
+        "pārveido vērtību par tekstu ar teksts()"
+    );
+    assert_eq!(
+        Locale::Lithuanian.suggestion(Suggestion::JoinWithPlus),
+        "tekstą galima tik sujungti su +"
+    );
+}
@@ -0,0 +1,327 @@
+//! Loops built with `Block` compile to flat jumps over the main bytecode.
+
+use std::collections::HashMap;
+
+use celsium::{
+    block::{ build_error::{ BuildError, BuildErrorKind }, Block, TextSpan, FIRST_LOOP_COUNTER },
+    bytecode::{ BINOP, OPTCODE },
+    execution::StopReason,
+    module::{ Function, FunctionSignature },
+    CelsiumProgram,
+    Scope,
+};
+
+fn block() -> Block {
+    Block::new(Scope { ast_id: 0, module_path: String::new() })
+}
+
+fn build(main: Block) -> Result<CelsiumProgram, BuildError> {
+    CelsiumProgram::new(main, vec![], HashMap::new(), HashMap::new(), HashMap::new())
+}
+
+fn run(main: Block) -> Vec<String> {
+    build(main)
+        .unwrap()
+        .run_program()
+        .unwrap()
+        .iter()
+        .map(|value| value.to_string())
+        .collect()
+}
+
+/// Pushes the loop variable `#1` to the testing stack
+fn print_variable() -> Block {
+    let mut body = block();
+    body.load_variable(1, 0, "i");
+    body.push_to_testing_stack(false);
+    body
+}
+
+fn range(start: i64, end: i64, step: i64) -> Block {
+    let mut range = block();
+    range.load_int(start);
+    range.load_int(end);
+    range.load_int(step);
+    range
+}
+
+fn strings(items: &[&str]) -> Block {
+    let mut array = block();
+    for item in items {
+        array.load_string(item);
+    }
+    array.create_array(items.len());
+    array
+}
+
+fn numbers(items: std::ops::Range<i64>) -> Block {
+    let mut array = block();
+    for item in items.clone() {
+        array.load_int(item);
+    }
+    array.create_array(items.count());
+    array
+}
+
+#[test]
+fn range_loops_count_to_the_end() {
+    let mut main = block();
+    main.define_range_loop(1, "i".to_string(), 0, range(2, 11, 3), print_variable());
+    assert_eq!(run(main), vec!["2", "5", "8"]);
+
+    let mut main = block();
+    main.define_range_loop(1, "i".to_string(), 0, range(3, 0, -1), print_variable());
+    assert_eq!(run(main), vec!["3", "2", "1"]);
+
+    // An empty range never runs the body
+    let mut main = block();
+    main.define_range_loop(1, "i".to_string(), 0, range(5, 5, 1), print_variable());
+    main.load_int(7);
+    main.push_to_testing_stack(false);
+    assert_eq!(run(main), vec!["7"]);
+}
+
+#[test]
+fn foreach_loops_go_over_arrays_and_strings() {
+    let mut array = block();
+    array.load_int(1);
+    array.load_string("divi");
+    array.load_bool(true);
+    array.create_array(3);
+    let mut main = block();
+    main.define_foreach_loop(1, "i".to_string(), 0, array, print_variable());
+    assert_eq!(run(main), vec!["1", "divi", "Jā"]);
+
+    let mut string = block();
+    string.load_string("āb");
+    let mut main = block();
+    main.define_foreach_loop(1, "i".to_string(), 0, string, print_variable());
+    assert_eq!(run(main), vec!["ā", "b"]);
+}
+
+#[test]
+fn foreach_loops_go_over_characters_of_any_width() {
+    let mut string = block();
+    string.load_string("aš💡z");
+    let mut main = block();
+    main.define_foreach_loop(1, "i".to_string(), 0, string, print_variable());
+    assert_eq!(run(main), vec!["a", "š", "💡", "z"]);
+
+    // Each step only reads the next character, so long strings take as many steps as characters
+    let mut string = block();
+    string.load_string(&"ā".repeat(50_000));
+    let mut body = block();
+    body.load_variable(2, 0, "n");
+    body.load_int(1);
+    body.binop(BINOP::Add, 0);
+    body.assign_variable(2);
+    let mut main = block();
+    main.load_int(0);
+    main.define_variable(2, "n".to_string(), 0);
+    main.define_foreach_loop(1, "i".to_string(), 0, string, body);
+    main.load_variable(2, 0, "n");
+    main.push_to_testing_stack(false);
+    assert_eq!(run(main), vec!["50000"]);
+}
+
+#[test]
+fn nested_loops_keep_their_own_state() {
+    let mut inner_body = print_variable();
+    inner_body.load_variable(2, 0, "j");
+    inner_body.push_to_testing_stack(false);
+    let mut outer_body = block();
+    outer_body.define_foreach_loop(2, "j".to_string(), 0, strings(&["a", "b"]), inner_body);
+    let mut main = block();
+    main.define_range_loop(1, "i".to_string(), 0, range(0, 2, 1), outer_body);
+    assert_eq!(run(main), vec!["0", "a", "0", "b", "1", "a", "1", "b"]);
+}
+
+fn span(line: usize) -> TextSpan {
+    TextSpan { line, col_start: 5, length: 6 }
+}
+
+/// Runs `control` when the loop variable `#1` equals `value`
+fn when_equal(value: i64, control: Block) -> Block {
+    let mut check = block();
+    check.load_variable(1, 0, "i");
+    check.load_int(value);
+    check.binop(BINOP::Eq, 0);
+    check.define_if_block(control, 0, 0);
+    check
+}
+
+fn break_loop() -> Block {
+    let mut control = block();
+    control.break_loop(span(1));
+    control
+}
+
+fn continue_loop() -> Block {
+    let mut control = block();
+    control.continue_loop(span(1));
+    control
+}
+
+#[test]
+fn break_and_continue_jump_out_of_the_body() {
+    let mut body = when_equal(1, continue_loop());
+    body.add_blocks_bytecode(when_equal(3, break_loop()));
+    body.add_blocks_bytecode(print_variable());
+    let mut main = block();
+    main.load_string("pirms");
+    main.define_range_loop(1, "i".to_string(), 0, range(0, 10, 1), body.clone());
+    // The loop state is popped on break, so the value below it is on top again
+    main.load_string(" un pēc");
+    main.binop(BINOP::Add, 0);
+    main.push_to_testing_stack(false);
+    assert_eq!(run(main), vec!["0", "2", "pirms un pēc"]);
+
+    let mut main = block();
+    main.define_foreach_loop(1, "i".to_string(), 0, numbers(0..10), body);
+    assert_eq!(run(main), vec!["0", "2"]);
+}
+
+#[test]
+fn while_loops_continue_at_the_condition() {
+    // i = 0; while i < 5 { i = i + 1; if i == 2 { continue }; if i == 4 { break }; print i }
+    let mut condition = block();
+    condition.load_variable(1, 0, "i");
+    condition.load_int(5);
+    condition.binop(BINOP::LessThan, 0);
+    let mut body = block();
+    body.load_variable(1, 0, "i");
+    body.load_int(1);
+    body.binop(BINOP::Add, 0);
+    body.assign_variable(1);
+    body.add_blocks_bytecode(when_equal(2, continue_loop()));
+    body.add_blocks_bytecode(when_equal(4, break_loop()));
+    body.add_blocks_bytecode(print_variable());
+    let mut main = block();
+    main.load_int(0);
+    main.define_variable(1, "i".to_string(), 0);
+    main.define_while_loop(body, condition, 0, 0);
+    assert_eq!(run(main), vec!["1", "3"]);
+}
+
+#[test]
+fn break_only_leaves_the_innermost_loop() {
+    let mut inner_body = when_equal(1, break_loop());
+    inner_body.load_variable(2, 0, "j");
+    inner_body.push_to_testing_stack(false);
+    let mut outer_body = block();
+    outer_body.define_foreach_loop(2, "j".to_string(), 0, strings(&["a", "b"]), inner_body);
+    let mut main = block();
+    main.define_range_loop(1, "i".to_string(), 0, range(0, 3, 1), outer_body);
+    assert_eq!(run(main), vec!["a", "b", "a", "b"]);
+}
+
+#[test]
+fn loop_control_outside_a_loop_fails_to_build() {
+    let mut main = block();
+    main.load_int(1);
+    main.break_loop(span(4));
+    assert_eq!(
+        build(main).unwrap_err(),
+        BuildError { kind: BuildErrorKind::BreakOutsideLoop, span: Some(span(4)) }
+    );
+    let mut main = block();
+    main.continue_loop(span(2));
+    assert_eq!(
+        build(main).unwrap_err(),
+        BuildError { kind: BuildErrorKind::ContinueOutsideLoop, span: Some(span(2)) }
+    );
+}
+
+/// Repeats a body that counts the runs in the global `#1`
+fn counting_loop(times: i64, extra: Block) -> Block {
+    let mut body = block();
+    body.load_variable(1, 0, "i");
+    body.load_int(1);
+    body.binop(BINOP::Add, 0);
+    body.assign_variable(1);
+    body.add_blocks_bytecode(extra);
+    let mut main = block();
+    main.load_int(0);
+    main.define_variable(1, "i".to_string(), 0);
+    main.load_int(times);
+    main.define_simple_loop(body);
+    main.add_blocks_bytecode(print_variable());
+    main
+}
+
+#[test]
+fn simple_loops_repeat_the_body() {
+    assert_eq!(run(counting_loop(3, block())), vec!["3"]);
+    assert_eq!(run(counting_loop(0, block())), vec!["0"]);
+    assert_eq!(run(counting_loop(-2, block())), vec!["0"]);
+    assert_eq!(run(counting_loop(10, when_equal(4, break_loop()))), vec!["4"]);
+}
+
+#[test]
+fn nested_simple_loops_have_their_own_counters() {
+    // Three outer runs count once each and run an inner loop that counts twice
+    let mut inner_body = block();
+    inner_body.load_variable(1, 0, "i");
+    inner_body.load_int(1);
+    inner_body.binop(BINOP::Add, 0);
+    inner_body.assign_variable(1);
+    let mut inner = block();
+    inner.load_int(2);
+    inner.define_simple_loop(inner_body);
+    let main = counting_loop(3, inner);
+    assert_eq!(run(main.clone()), vec!["9"]);
+
+    let ids: Vec<usize> = main.bytecode
+        .iter()
+        .filter_map(|optcode| match optcode {
+            OPTCODE::DefineVar { id, .. } if *id >= FIRST_LOOP_COUNTER => Some(*id),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(ids, vec![usize::MAX - 1, usize::MAX]);
+}
+
+#[test]
+fn simple_loops_are_flat_bytecode() {
+    // The body calls a function, which only works if its offset is in the same bytecode
+    let mut call = block();
+    call.call_function(0, "f");
+    let mut body = block();
+    body.load_string("f");
+    body.push_to_testing_stack(false);
+    body.return_from_function();
+    let function = Function {
+        id: 0,
+        signature: FunctionSignature::new("f".to_string(), vec![], None),
+        body,
+    };
+    let program = CelsiumProgram::new(
+        counting_loop(2, call),
+        vec![function],
+        HashMap::new(),
+        HashMap::new(),
+        HashMap::new()
+    ).unwrap();
+    assert_eq!(program.verify(), Ok(()));
+
+    // A debugger stops at the call in the body on every run, after the count is updated
+    let mut execution = program.start_execution();
+    execution.add_breakpoint(16);
+    let mut stops = 0;
+    while execution.run_until_breakpoint().unwrap() != StopReason::Finished {
+        assert!(matches!(execution.current_optcode(), Some(OPTCODE::JumpToFunction { .. })));
+        assert_eq!(execution.variables()[0].value.to_string(), (stops + 1).to_string());
+        // Next to it is the hidden counter with the runs that are left
+        assert_eq!(execution.variables()[1].id, usize::MAX);
+        assert_eq!(execution.variables()[1].value.to_string(), (1 - stops).to_string());
+        stops += 1;
+    }
+    assert_eq!(stops, 2);
+    let results: Vec<String> = execution
+        .vm()
+        .testing_stack()
+        .iter()
+        .map(|value| value.to_string())
+        .collect();
+    assert_eq!(results, vec!["f", "f", "2"]);
+}
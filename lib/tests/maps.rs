@@ -0,0 +1,98 @@
+//! Maps keep their entries in insertion order and find keys without searching them.
+
+use celsium::{
+    assembler::assemble_program,
+    vm::{ Map, MapKey, StackValue },
+};
+
+fn run(source: &str) -> Vec<String> {
+    assemble_program(source)
+        .unwrap()
+        .run_program()
+        .unwrap()
+        .iter()
+        .map(|value| value.to_string())
+        .collect()
+}
+
+#[test]
+fn entries_keep_their_insertion_order() {
+    let source = r#"
+        LoadString "b"
+        LoadInt 1
+        LoadString "a"
+        LoadInt 2
+        LoadString "b"
+        LoadInt 3
+        CreateMap 3
+        DefineVar m #1
+        LoadInt 4
+        LoadString "c"
+        SetMapValue #1
+        LoadString "a"
+        RemoveMapKey #1
+        LoadInt 5
+        LoadString "a"
+        SetMapValue #1
+        LoadVar m #1
+        PushToTestingStack
+        LoadVar m #1
+        LoadString "c"
+        GetMapValue
+        PushToTestingStack
+    "#;
+    assert_eq!(run(source), vec!["{\"b\": 3;\"c\": 4;\"a\": 5}", "4"]);
+}
+
+#[test]
+fn removing_an_entry_moves_the_later_ones_up() {
+    let mut map: Map = (0..5).map(|key| (MapKey::Int(key), StackValue::Int { value: key * 10 })).collect();
+    assert_eq!(map.remove(&MapKey::Int(1)), Some(StackValue::Int { value: 10 }));
+    assert_eq!(map.remove(&MapKey::Int(1)), None);
+    assert_eq!(map.len(), 4);
+    assert_eq!(map.get(&MapKey::Int(4)), Some(&StackValue::Int { value: 40 }));
+    assert_eq!(map.get_index(1).unwrap().key, StackValue::Int { value: 2 });
+    map.insert(MapKey::Int(3), StackValue::Bool { value: true });
+    assert_eq!(map.get_index(2).unwrap().value, StackValue::Bool { value: true });
+}
+
+#[test]
+fn big_maps_are_built_in_linear_time() {
+    let source = r#"
+        CreateMap 0
+        DefineVar m #1
+        LoadInt 0
+        DefineVar i #2
+    loop:
+        LoadVar i #2
+        LoadInt 100000
+        LessThan
+        JumpIfFalse end
+        LoadVar i #2
+        LoadVar i #2
+        SetMapValue #1
+        LoadVar i #2
+        LoadInt 1
+        Add
+        AssignVar #2
+        JumpBack loop
+    end:
+        LoadVar m #1
+        LoadInt 99999
+        GetMapValue
+        PushToTestingStack
+    "#;
+    assert_eq!(run(source), vec!["99999"]);
+}
+
+#[test]
+fn maps_round_trip_through_json() {
+    let map = StackValue::map(Map::from_iter([
+        (MapKey::String("b".to_string()), StackValue::Int { value: 1 }),
+        (MapKey::Bool(true), StackValue::Int { value: 2 }),
+    ]));
+    let json = serde_json::to_string(&map).unwrap();
+    assert_eq!(serde_json::from_str::<StackValue>(&json).unwrap(), map);
+    let float_key = r#"{"Map":{"value":[{"key":{"Float":{"value":1.5}},"value":{"Int":{"value":1}}}]}}"#;
+    assert!(serde_json::from_str::<StackValue>(float_key).is_err());
+}
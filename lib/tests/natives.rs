@@ -0,0 +1,91 @@
+//! Host functions registered on a program are called with `CallSpecialFunction` like the std ones.
+
+use std::{ cell::RefCell, rc::Rc };
+
+use celsium::{
+    assembler::assemble_program,
+    module::{ FuncArg, FunctionSignature },
+    native::NativeRegistry,
+    vm::{ runtime_error::{ RuntimeError, RuntimeErrorKind }, StackValue },
+    BuiltinTypes,
+};
+
+fn arg(name: &str) -> FuncArg {
+    FuncArg { name: name.to_string(), arg_type: BuiltinTypes::Int, mutable: false, local_var_id: None }
+}
+
+fn int(value: &StackValue) -> i64 {
+    match value {
+        StackValue::Int { value } => *value,
+        other => panic!("expected an Int, got {}", other),
+    }
+}
+
+#[test]
+fn host_functions_get_their_arguments_in_order() {
+    let mut program = assemble_program(
+        "LoadInt 10\nLoadInt 3\nCallSpecialFunction atņemt\nPushToTestingStack"
+    ).unwrap();
+    let signature = FunctionSignature::new(
+        "atņemt".to_string(),
+        vec![arg("a"), arg("b")],
+        Some(BuiltinTypes::Int)
+    );
+    program.register_native_function(signature, |_, arguments| {
+        Ok(StackValue::Int { value: int(&arguments[0]) - int(&arguments[1]) })
+    });
+    assert_eq!(program.run_program().unwrap(), vec![StackValue::Int { value: 7 }]);
+    assert!(program.get_native_functions().iter().any(|function| function.name == "atņemt"));
+}
+
+#[test]
+fn functions_without_a_return_type_push_nothing() {
+    let calls = Rc::new(RefCell::new(vec![]));
+    let mut program = assemble_program(
+        "LoadInt 1\nCallSpecialFunction pierakstīt\nLoadInt 2\nCallSpecialFunction pierakstīt"
+    ).unwrap();
+    let recorded = calls.clone();
+    let signature = FunctionSignature::new("pierakstīt".to_string(), vec![arg("x")], None);
+    program.register_native_function(signature, move |_, arguments| {
+        recorded.borrow_mut().push(int(&arguments[0]));
+        Ok(StackValue::Bool { value: false })
+    });
+    assert_eq!(program.run_program().unwrap(), vec![]);
+    assert_eq!(*calls.borrow(), vec![1, 2]);
+}
+
+#[test]
+fn registering_an_existing_name_replaces_it() {
+    let mut registry = NativeRegistry::new();
+    let index = registry.resolve("garums").unwrap();
+    let signature = FunctionSignature::new("garums".to_string(), vec![arg("x")], Some(BuiltinTypes::Int));
+    let replaced = registry.register(signature, |_, _| Ok(StackValue::Int { value: -1 }));
+    assert_eq!(replaced, index);
+
+    let mut program = assemble_program(
+        "LoadString \"abc\"\nCallSpecialFunction garums\nPushToTestingStack"
+    ).unwrap();
+    program.set_native_registry(registry);
+    assert_eq!(program.run_program().unwrap(), vec![StackValue::Int { value: -1 }]);
+}
+
+#[test]
+fn host_errors_stop_the_program() {
+    let mut program = assemble_program("LoadInt 0\nCallSpecialFunction kļūda").unwrap();
+    let signature = FunctionSignature::new("kļūda".to_string(), vec![arg("x")], None);
+    program.register_native_function(signature, |_, _| {
+        Err(RuntimeError::new(RuntimeErrorKind::DivisionByZero))
+    });
+    let error = program.run_program().unwrap_err();
+    assert_eq!(error.kind, RuntimeErrorKind::DivisionByZero);
+    assert_eq!(error.optcode_index, Some(1));
+}
+
+#[test]
+fn unknown_functions_fail_when_called() {
+    let mut program = assemble_program("LoadInt 1\nPushToTestingStack\nCallSpecialFunction nav").unwrap();
+    assert_eq!(
+        program.run_program().unwrap_err().kind,
+        RuntimeErrorKind::UnknownFunction { name: "nav".to_string() }
+    );
+}
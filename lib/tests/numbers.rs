@@ -0,0 +1,157 @@
+//! Ints grow into `BigInt`s instead of overflowing and shrink back when the result fits.
+//! Decimals are exact fractions, printed with the decimal separator.
+
+use std::collections::HashMap;
+
+use celsium::{
+    assembler::assemble_program,
+    block::Block,
+    bytecode::BINOP,
+    vm::{ runtime_error::RuntimeErrorKind, StackValue },
+    CelsiumProgram,
+    Scope,
+};
+
+fn run(source: &str) -> Vec<StackValue> {
+    assemble_program(source).unwrap().run_program().unwrap()
+}
+
+fn printed(source: &str) -> Vec<String> {
+    run(source)
+        .iter()
+        .map(|value| value.to_string())
+        .collect()
+}
+
+#[test]
+fn overflowing_ints_become_big() {
+    let source = r#"
+        LoadInt 9223372036854775807
+        LoadInt 1
+        Add
+        PushToTestingStack duplicate
+        LoadInt 1
+        Subtract
+        PushToTestingStack
+        LoadInt -9223372036854775807
+        LoadInt 3
+        Subtract
+        PushToTestingStack
+        LoadInt 9223372036854775807
+        LoadInt 9223372036854775807
+        Multiply
+        PushToTestingStack
+    "#;
+    let results = run(source);
+    assert!(matches!(results[0], StackValue::BigInt { .. }));
+    // Results that fit into i64 are plain ints again
+    assert!(matches!(results[1], StackValue::Int { value: i64::MAX }));
+    let printed: Vec<String> = results
+        .iter()
+        .map(|value| value.to_string())
+        .collect();
+    assert_eq!(printed, vec![
+        "9223372036854775808",
+        "9223372036854775807",
+        "-9223372036854775810",
+        "85070591730234615847396907784232501249",
+    ]);
+}
+
+#[test]
+fn big_ints_divide_and_compare() {
+    let big = "LoadInt 9223372036854775807\nLoadInt 10\nMultiply\n";
+    let operation = |operator: &str, right: &str| {
+        printed(&format!("{}LoadInt {}\n{}\nPushToTestingStack", big, right, operator))
+    };
+    assert_eq!(operation("Divide", "10"), vec!["9223372036854775807"]);
+    assert_eq!(operation("Remainder", "7"), vec!["0"]);
+    assert_eq!(operation("LargerThan", "9223372036854775807"), vec!["Jā"]);
+    assert_eq!(operation("Eq", "0"), vec!["Nē"]);
+    assert_eq!(printed(&format!("{}{}Eq\nPushToTestingStack", big, big)), vec!["Jā"]);
+}
+
+#[test]
+fn factorials_are_exact() {
+    let source = r#"
+        LoadInt 30
+        CallFunction factorial
+        PushToTestingStack
+    .function factorial(n: Int #10) -> Int
+        LoadVar n #10
+        LoadInt 2
+        LessThan
+        JumpIfFalse recurse
+        LoadInt 1
+        ReturnValue
+    recurse:
+        LoadVar n #10
+        LoadVar n #10
+        LoadInt 1
+        Subtract
+        CallFunction factorial
+        Multiply
+        ReturnValue
+    .end
+    "#;
+    assert_eq!(printed(source), vec!["265252859812191058636308480000000"]);
+}
+
+/// Runs `operator` on two decimal literals
+fn decimal(a: &str, operator: &str, b: &str) -> String {
+    printed(&format!("LoadDecimal {}\nLoadDecimal {}\n{}\nPushToTestingStack", a, b, operator)).remove(0)
+}
+
+#[test]
+fn decimals_are_exact() {
+    assert_eq!(decimal("0,1", "Add", "0,2"), "0,3");
+    assert_eq!(decimal("0,3", "Subtract", "0,1"), "0,2");
+    assert_eq!(decimal("1,5", "Multiply", "-0,2"), "-0,3");
+    assert_eq!(decimal("0,1", "Multiply", "10"), "1");
+    assert_eq!(decimal("0,3", "Eq", "0,1"), "Nē");
+    assert_eq!(decimal("0,30", "Eq", "0,3"), "Jā");
+    assert_eq!(decimal("-0,1", "LessThan", "0"), "Jā");
+}
+
+#[test]
+fn endless_decimals_are_rounded_when_printed() {
+    assert_eq!(decimal("1", "Divide", "3"), "0,33333333333333333333");
+    assert_eq!(decimal("2", "Divide", "3"), "0,66666666666666666667");
+    // The value itself stays exact
+    let source = "LoadDecimal 1\nLoadDecimal 3\nDivide\nLoadInt 3\nMultiply\nPushToTestingStack";
+    assert_eq!(printed(source), vec!["1"]);
+}
+
+#[test]
+fn decimals_mix_with_other_numbers() {
+    let mixed = |a: &str, b: &str| run(&format!("{}\n{}\nAdd\nPushToTestingStack", a, b)).remove(0);
+    // Ints and big ints keep the result exact
+    assert!(matches!(mixed("LoadDecimal 0,5", "LoadInt 2"), StackValue::Decimal { .. }));
+    let big = mixed("LoadInt 9223372036854775807\nLoadInt 2\nMultiply", "LoadDecimal 0,5");
+    assert_eq!(big.to_string(), "18446744073709551614,5");
+    // Floats are not exact, so neither is the result
+    assert!(matches!(mixed("LoadDecimal 0,5", "LoadFloat 0.25"), StackValue::Float { .. }));
+}
+
+#[test]
+fn dividing_decimals_by_zero_fails() {
+    for zero in ["LoadDecimal 0,0", "LoadInt 0"] {
+        let source = format!("LoadDecimal 1,5\n{}\nDivide", zero);
+        let error = assemble_program(&source).unwrap().run_program().unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::DivisionByZero);
+    }
+}
+
+#[test]
+fn blocks_load_decimal_literals() {
+    let mut main = Block::new(Scope { ast_id: 0, module_path: String::new() });
+    main.load_decimal("0,1");
+    main.load_decimal("0.2");
+    main.binop(BINOP::Add, 0);
+    main.push_to_testing_stack(false);
+    let results = CelsiumProgram::new(main, vec![], HashMap::new(), HashMap::new(), HashMap::new())
+        .unwrap()
+        .run_program()
+        .unwrap();
+    assert_eq!(results[0].to_string(), "0,3");
+}
@@ -0,0 +1,96 @@
+//! Observers added to a program get the execution events natively, not only in the browser.
+
+use std::{ cell::RefCell, collections::HashMap, rc::Rc };
+
+use celsium::{
+    assembler::assemble_program,
+    block::{ Block, TextSpan },
+    bytecode::BINOP,
+    locale::Locale,
+    observer::{ ExecutionEvent, ExecutionObserver, ExplanationLog },
+    CelsiumProgram,
+    Scope,
+};
+
+/// Writes every event as a short line
+#[derive(Debug, Default)]
+struct Trace {
+    lines: Vec<String>,
+}
+
+impl ExecutionObserver for Trace {
+    fn on_event(&mut self, event: &ExecutionEvent, _: Locale) {
+        self.lines.push(match event {
+            ExecutionEvent::BinaryOperation { operator, left, right, result, .. } =>
+                format!("{:?} {} {} = {}", operator, left, right, result),
+            ExecutionEvent::VariableDefined { name, value, .. } => format!("define {} = {}", name, value),
+            ExecutionEvent::VariableLoaded { name, value, .. } => format!("load {} = {}", name, value),
+            ExecutionEvent::CodeReplaced { replacement, span } =>
+                format!("replace {}:{} with {}", span.line, span.col_start, replacement),
+            ExecutionEvent::BranchTaken { index, jumped } => format!("branch {} {}", index, jumped),
+            ExecutionEvent::Step => "step".to_string(),
+        });
+    }
+}
+
+#[test]
+fn events_are_sent_in_order() {
+    let trace = Rc::new(RefCell::new(Trace::default()));
+    let mut program = assemble_program(
+        r#"
+        LoadInt 2
+        DefineVar x #1
+        LoadVar x #1
+        LoadInt 3
+        LargerThan
+        JumpIfFalse +1
+        Step
+        "#
+    ).unwrap();
+    program.add_observer(trace.clone());
+    program.run_program().unwrap();
+    assert_eq!(trace.borrow().lines, vec![
+        "define x = 2",
+        "step",
+        "load x = 2",
+        "step",
+        "LargerThan 2 3 = Nē",
+        "step",
+        "branch 5 true",
+    ]);
+
+    // Without observers nothing is sent
+    trace.borrow_mut().lines.clear();
+    program.clear_observers();
+    program.run_program().unwrap();
+    assert!(trace.borrow().lines.is_empty());
+}
+
+#[test]
+fn evaluated_code_is_replaced_and_explained() {
+    // `1 + 2` on line 1, with the literals as nodes 1 and 2 and the addition as node 3
+    let span = |col_start, length| TextSpan { line: 1, col_start, length };
+    let node_locations = HashMap::from([(1, span(1, 1)), (2, span(5, 1)), (3, span(1, 5))]);
+    let node_parents = HashMap::from([(1, Some(3)), (2, Some(3)), (3, None)]);
+    let mut main = Block::new(Scope { ast_id: 0, module_path: String::new() });
+    main.load_int(1);
+    main.load_int(2);
+    main.binop(BINOP::Add, 3);
+    let mut program = CelsiumProgram::new(
+        main,
+        vec![],
+        node_locations,
+        HashMap::from([(1, vec![1, 2, 3])]),
+        node_parents
+    ).unwrap();
+
+    let trace = Rc::new(RefCell::new(Trace::default()));
+    let log = ExplanationLog::shared();
+    program.add_observer(trace.clone());
+    program.add_observer(log.clone());
+    program.set_locale(Locale::English);
+    program.run_program().unwrap();
+    assert_eq!(trace.borrow().lines, vec!["Add 1 2 = 3", "replace 1:1 with 3", "step"]);
+    assert_eq!(log.borrow().lines.len(), 1);
+    assert!(log.borrow().lines[0].contains("1 + 2 = 3"), "{}", log.borrow().lines[0]);
+}
@@ -0,0 +1,150 @@
+//! `TypeStack::binop` and the VM share the rules in `celsium::operators`.
+//! These tests run every operator on every pair of operand types on both sides
+//! and check that they agree on whether it is allowed and on the result type.
+
+use celsium::{
+    bytecode::BINOP,
+    operators::OperandType,
+    typestack::TypeStack,
+    vm::{
+        decimal::parse_decimal,
+        limits::VmLimits,
+        runtime_error::{ RuntimeError, RuntimeErrorKind },
+        vm::VM,
+        Map,
+        MapKey,
+        ObjectField,
+        StackValue,
+    },
+    BuiltinTypes,
+};
+use num::BigInt;
+
+const OPERATORS: [BINOP; 14] = [
+    BINOP::Add,
+    BINOP::Subtract,
+    BINOP::Multiply,
+    BINOP::Divide,
+    BINOP::Remainder,
+    BINOP::LessThan,
+    BINOP::LargerThan,
+    BINOP::LessOrEq,
+    BINOP::LargerOrEq,
+    BINOP::NotEq,
+    BINOP::Eq,
+    BINOP::And,
+    BINOP::Or,
+    BINOP::Xor,
+];
+
+/// Non-zero values, so divisions succeed whenever the types allow them
+fn samples() -> Vec<(StackValue, BuiltinTypes)> {
+    vec![
+        (StackValue::Bool { value: true }, BuiltinTypes::Bool),
+        (StackValue::Int { value: 3 }, BuiltinTypes::Int),
+        (StackValue::BigInt { value: BigInt::from(i64::MAX) * 4 }, BuiltinTypes::Int),
+        (StackValue::Float { value: 2.5 }, BuiltinTypes::Float),
+        // A whole float, which once made `Int + Float` an `Int`
+        (StackValue::Float { value: 2.0 }, BuiltinTypes::Float),
+        (StackValue::Decimal { value: parse_decimal("0,1").unwrap() }, BuiltinTypes::Decimal),
+        (StackValue::String { value: "ab".to_string() }, BuiltinTypes::String),
+        (
+            StackValue::array(vec![StackValue::Int { value: 1 }]),
+            BuiltinTypes::Array { element_type: Box::new(BuiltinTypes::Int), length: Some(1) },
+        ),
+        (
+            StackValue::object(
+                vec![ObjectField { name: "x".to_string(), value: StackValue::Int { value: 1 } }]
+            ),
+            BuiltinTypes::Object { fields: vec![] },
+        ),
+        (
+            StackValue::map(Map::from_iter([(MapKey::Int(1), StackValue::Bool { value: false })])),
+            BuiltinTypes::Map { key: Box::new(BuiltinTypes::Int), value: Box::new(BuiltinTypes::Bool) },
+        )
+    ]
+}
+
+fn run(operator: BINOP, a: &StackValue, b: &StackValue) -> Result<StackValue, RuntimeError> {
+    let mut vm = VM::new();
+    vm.push_stackvalue(a.clone());
+    vm.push_stackvalue(b.clone());
+    vm.aritmethics(operator.symbol()).map(|(_, _, result)| result)
+}
+
+/// The result type, or `None` for a type mismatch. Other errors, like a too long text, are not about types.
+fn runtime_type(operator: BINOP, a: &StackValue, b: &StackValue) -> Option<OperandType> {
+    match run(operator, a, b) {
+        Ok(result) => Some(OperandType::of_value(&result)),
+        Err(RuntimeError { kind: RuntimeErrorKind::TypeMismatch { .. }, .. }) => None,
+        // Only repeating a text that many times can exceed the size limit
+        Err(RuntimeError { kind: RuntimeErrorKind::ValueSizeExceeded { .. }, .. }) =>
+            Some(OperandType::String),
+        Err(error) => panic!("{} '{}' {} failed: {}", a.type_name(), operator.symbol(), b.type_name(), error),
+    }
+}
+
+fn check(operator: BINOP, left: &BuiltinTypes, right: &BuiltinTypes) -> Option<BuiltinTypes> {
+    let mut typestack = TypeStack::new();
+    typestack.push(left.clone());
+    typestack.push(right.clone());
+    typestack.binop(operator).ok()
+}
+
+#[test]
+fn typestack_and_vm_agree() {
+    let samples = samples();
+    let mut disagreements = vec![];
+    for operator in OPERATORS {
+        for (a, a_type) in &samples {
+            for (b, b_type) in &samples {
+                let runtime = runtime_type(operator, a, b);
+                let static_type = check(operator, a_type, b_type).map(|data_type|
+                    OperandType::of_type(&data_type)
+                );
+                if runtime != static_type {
+                    disagreements.push(
+                        format!(
+                            "{} '{}' {}: VM {:?}, TypeStack {:?}",
+                            a.type_name(),
+                            operator.symbol(),
+                            b.type_name(),
+                            runtime,
+                            static_type
+                        )
+                    );
+                }
+            }
+        }
+    }
+    assert!(disagreements.is_empty(), "{}", disagreements.join("\n"));
+}
+
+#[test]
+fn text_is_repeated_by_an_int() {
+    let text = StackValue::String { value: "ab".to_string() };
+    let three = StackValue::Int { value: 3 };
+    let repeated = StackValue::String { value: "ababab".to_string() };
+    assert_eq!(run(BINOP::Multiply, &text, &three).unwrap(), repeated);
+    assert_eq!(run(BINOP::Multiply, &three, &text).unwrap(), repeated);
+    assert_eq!(
+        run(BINOP::Multiply, &text, &StackValue::Int { value: -1 }).unwrap(),
+        StackValue::String { value: String::new() }
+    );
+    let too_many = StackValue::BigInt { value: BigInt::from(i64::MAX) * 4 };
+    let error = run(BINOP::Multiply, &text, &too_many).unwrap_err();
+    assert!(matches!(error.kind, RuntimeErrorKind::ValueSizeExceeded { .. }));
+}
+
+#[test]
+fn repetition_is_limited_before_it_allocates() {
+    let limits = VmLimits { max_single_value_size: Some(100), ..VmLimits::default() };
+    let mut vm = VM::with_limits(limits);
+    vm.push_stackvalue(StackValue::String { value: "ab".to_string() });
+    vm.push_stackvalue(StackValue::Int { value: 10_i64.pow(17) });
+    let error = vm.aritmethics(BINOP::Multiply.symbol()).unwrap_err();
+    assert_eq!(
+        error.kind,
+        RuntimeErrorKind::ValueSizeExceeded { limit: 100, size: 2 * 10_usize.pow(17) }
+    );
+}
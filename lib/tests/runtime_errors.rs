@@ -0,0 +1,87 @@
+//! Failing programs and VM calls return a `RuntimeError` instead of panicking.
+
+use celsium::{
+    assembler::assemble_program,
+    vm::{ runtime_error::{ RuntimeError, RuntimeErrorKind }, vm::VM, StackValue },
+    BuiltinTypes,
+};
+
+fn run(source: &str) -> Result<Vec<StackValue>, RuntimeError> {
+    assemble_program(source).unwrap().run_program()
+}
+
+fn error_kind(source: &str) -> RuntimeErrorKind {
+    run(source).unwrap_err().kind
+}
+
+#[test]
+fn failing_optcodes_return_their_error() {
+    assert_eq!(
+        error_kind("LoadBool true\nLoadString \"a\"\nSubtract"),
+        RuntimeErrorKind::TypeMismatch { operation: "-", left: "Bool", right: Some("String") }
+    );
+    assert_eq!(
+        error_kind("LoadInt 1\nCreateArray 1\nLoadInt 3\nGetIndex"),
+        RuntimeErrorKind::IndexOutOfBounds { index: 3, length: 1 }
+    );
+    assert_eq!(error_kind("LoadInt 1\nLoadInt 0\nDivide"), RuntimeErrorKind::DivisionByZero);
+    assert_eq!(error_kind("LoadVar x #7"), RuntimeErrorKind::UnknownVariable { id: 7 });
+    assert_eq!(error_kind("Add"), RuntimeErrorKind::StackUnderflow);
+    assert_eq!(
+        error_kind("LoadInt 1\nCreateObject a\nGetObjectField b"),
+        RuntimeErrorKind::MissingField { field_name: "b".to_string() }
+    );
+}
+
+#[test]
+fn errors_know_the_failing_optcode() {
+    let error = run("LoadInt 1\nLoadInt 0\nRemainder").unwrap_err();
+    assert_eq!(error.optcode_index, Some(2));
+}
+
+#[test]
+fn an_empty_random_range_is_an_error() {
+    let source = "LoadInt 5\nLoadInt 5\nCallSpecialFunction nejaušs_robežās";
+    assert_eq!(error_kind(source), RuntimeErrorKind::EmptyRandomRange { min: 5, max: 5 });
+    let source = "LoadInt 5\nLoadInt 6\nCallSpecialFunction nejaušs_robežās\nPushToTestingStack";
+    assert_eq!(run(source).unwrap(), vec![StackValue::Int { value: 5 }]);
+}
+
+#[test]
+fn vm_push_parses_literals() {
+    let mut vm = VM::new();
+    vm.push(&BuiltinTypes::Int, &"42".to_string()).unwrap();
+    vm.push(&BuiltinTypes::Bool, &"1".to_string()).unwrap();
+    vm.push(&BuiltinTypes::Float, &"2.5".to_string()).unwrap();
+    vm.push(&BuiltinTypes::Decimal, &"0,1".to_string()).unwrap();
+    assert_eq!(vm.pop().unwrap().to_string(), "0,1");
+    assert_eq!(vm.pop().unwrap(), StackValue::Float { value: 2.5 });
+    assert_eq!(vm.pop().unwrap(), StackValue::Bool { value: true });
+    assert_eq!(vm.pop().unwrap(), StackValue::Int { value: 42 });
+
+    let array = BuiltinTypes::Array { element_type: Box::new(BuiltinTypes::Int), length: None };
+    for (data_type, literal) in [
+        (BuiltinTypes::Int, "4x"),
+        (BuiltinTypes::Bool, "yes"),
+        (BuiltinTypes::Float, ""),
+        (BuiltinTypes::Decimal, "1e5"),
+        (array, "[1]"),
+    ] {
+        let error = vm.push(&data_type, &literal.to_string()).unwrap_err();
+        assert_eq!(
+            error.kind,
+            RuntimeErrorKind::InvalidLiteral { data_type, literal: literal.to_string() }
+        );
+    }
+}
+
+#[test]
+fn unknown_operators_are_an_error() {
+    let mut vm = VM::new();
+    vm.push_stackvalue(StackValue::Int { value: 1 });
+    vm.push_stackvalue(StackValue::Int { value: 2 });
+    assert_eq!(
+        vm.aritmethics("**").unwrap_err().kind,
+        RuntimeErrorKind::UnknownOperator { operator: "**".to_string() }
+    );
+}
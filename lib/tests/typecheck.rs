@@ -0,0 +1,184 @@
+//! `typecheck` accepts well typed programs and reports each kind of mistake where it happens.
+
+use celsium::{
+    assembler::assemble_program,
+    compiletime_helper::CompileTimeHelper,
+    typecheck::{ typecheck, TypeErrorKind },
+    BuiltinTypes,
+    ObjectFieldType,
+    Scope,
+};
+
+fn scope() -> Scope {
+    Scope { ast_id: 0, module_path: String::new() }
+}
+
+/// Functions `f() -> Int` (id 0) and `g()` (id 1), then the variables
+/// `x: Int` (#2), `s: String` (#3), `a: [Int]` (#4) and `o: { n: Int }` (#5)
+fn helper() -> CompileTimeHelper {
+    let mut helper = CompileTimeHelper::new(String::new(), String::new());
+    helper.def_function("f".to_string(), vec![], scope(), false, Some(BuiltinTypes::Int));
+    helper.def_function("g".to_string(), vec![], scope(), false, None);
+    let array = BuiltinTypes::Array { element_type: Box::new(BuiltinTypes::Int), length: None };
+    let object = BuiltinTypes::Object {
+        fields: vec![ObjectFieldType { name: "n".to_string(), data_type: BuiltinTypes::Int }],
+    };
+    for (name, data_type) in [
+        ("x", BuiltinTypes::Int),
+        ("s", BuiltinTypes::String),
+        ("a", array),
+        ("o", object),
+    ] {
+        helper.def_var(name.to_string(), data_type, scope(), false).unwrap();
+    }
+    helper
+}
+
+/// `main` is the main block, the functions `f` and `g` have the given bodies
+fn errors(main: &str, f: &str, g: &str) -> Vec<TypeErrorKind> {
+    let source = format!(".function f() -> Int\n{}\n.end\n.function g()\n{}\n.end\n{}", f, g, main);
+    let program = assemble_program(&source).unwrap();
+    typecheck(&program, &helper())
+        .into_iter()
+        .map(|error| error.kind)
+        .collect()
+}
+
+fn main_errors(main: &str) -> Vec<TypeErrorKind> {
+    errors(main, "LoadInt 1\nReturnValue", "Return")
+}
+
+fn return_errors(f: &str, g: &str) -> Vec<TypeErrorKind> {
+    errors("", f, g)
+}
+
+#[test]
+fn variables_get_values_of_their_type() {
+    assert_eq!(main_errors("LoadInt 1\nAssignVar #2\nLoadString \"a\"\nDefineVar s #3"), vec![]);
+    assert_eq!(main_errors("CallFunction f\nAssignVar #2"), vec![]);
+    assert_eq!(
+        main_errors("LoadString \"a\"\nAssignVar #2"),
+        vec![TypeErrorKind::VariableType {
+            name: "x".to_string(),
+            expected: BuiltinTypes::Int,
+            found: BuiltinTypes::String,
+        }]
+    );
+    assert_eq!(
+        main_errors("CallFunction f\nDefineVar s #3"),
+        vec![TypeErrorKind::VariableType {
+            name: "s".to_string(),
+            expected: BuiltinTypes::String,
+            found: BuiltinTypes::Int,
+        }]
+    );
+}
+
+#[test]
+fn arrays_and_text_are_indexed_by_ints() {
+    assert_eq!(main_errors("LoadVar a #4\nLoadInt 0\nGetIndex\nAssignVar #2"), vec![]);
+    assert_eq!(main_errors("LoadVar s #3\nLoadInt 0\nGetIndex\nAssignVar #3"), vec![]);
+    assert_eq!(
+        main_errors("LoadVar a #4\nLoadString \"0\"\nGetIndex\nPushToTestingStack"),
+        vec![TypeErrorKind::IndexType { found: BuiltinTypes::String }]
+    );
+    assert_eq!(
+        main_errors("LoadVar x #2\nLoadInt 0\nGetIndex\nPushToTestingStack"),
+        vec![TypeErrorKind::NotIndexable { found: BuiltinTypes::Int }]
+    );
+    assert_eq!(
+        main_errors("LoadVar a #4\nLoadInt 0\nGetIndex\nAssignVar #3"),
+        vec![TypeErrorKind::VariableType {
+            name: "s".to_string(),
+            expected: BuiltinTypes::String,
+            found: BuiltinTypes::Int,
+        }]
+    );
+}
+
+#[test]
+fn object_fields_must_exist() {
+    assert_eq!(main_errors("LoadVar o #5\nGetObjectField n\nAssignVar #2"), vec![]);
+    assert_eq!(
+        main_errors("LoadVar o #5\nGetObjectField m\nPushToTestingStack"),
+        vec![TypeErrorKind::MissingField { field_name: "m".to_string() }]
+    );
+    assert_eq!(
+        main_errors("LoadVar s #3\nGetObjectField n\nPushToTestingStack"),
+        vec![TypeErrorKind::NotAnObject { field_name: "n".to_string(), found: BuiltinTypes::String }]
+    );
+}
+
+#[test]
+fn special_functions_get_arguments_of_their_signature() {
+    assert_eq!(main_errors("LoadVar s #3\nCallSpecialFunction apgriezt\nAssignVar #3"), vec![]);
+    assert_eq!(
+        main_errors("LoadInt 1\nLoadFloat 0.5\nCallSpecialFunction minimums\nAssignVar #3"),
+        vec![TypeErrorKind::VariableType {
+            name: "s".to_string(),
+            expected: BuiltinTypes::String,
+            found: BuiltinTypes::Float,
+        }]
+    );
+    // `teksts` takes any value and gives text
+    assert_eq!(main_errors("LoadVar a #4\nCallSpecialFunction teksts\nAssignVar #3"), vec![]);
+    assert_eq!(
+        main_errors("LoadInt 1\nLoadInt 5\nCallSpecialFunction nejaušs_robežās\nAssignVar #2"),
+        vec![]
+    );
+    assert_eq!(
+        main_errors("LoadVar x #2\nCallSpecialFunction apgriezt\nPushToTestingStack"),
+        vec![TypeErrorKind::ArgumentType {
+            function: "apgriezt".to_string(),
+            argument: "teksts".to_string(),
+            expected: BuiltinTypes::String,
+            found: BuiltinTypes::Int,
+        }]
+    );
+    let source = "LoadInt 1\nLoadString \"5\"\nCallSpecialFunction nejaušs_robežās\nPushToTestingStack";
+    assert_eq!(
+        main_errors(source),
+        vec![TypeErrorKind::ArgumentType {
+            function: "nejaušs_robežās".to_string(),
+            argument: "maks".to_string(),
+            expected: BuiltinTypes::Int,
+            found: BuiltinTypes::String,
+        }]
+    );
+}
+
+#[test]
+fn functions_return_their_return_type() {
+    assert_eq!(return_errors("LoadVar x #2\nReturnValue", "Return"), vec![]);
+    let both_branches = "LoadBool true\nJumpIfFalse +2\nLoadInt 1\nReturnValue\nLoadInt 2\nReturnValue";
+    assert_eq!(return_errors(both_branches, ""), vec![]);
+    assert_eq!(
+        return_errors("LoadString \"1\"\nReturnValue", ""),
+        vec![TypeErrorKind::ReturnType {
+            function: "f".to_string(),
+            expected: Some(BuiltinTypes::Int),
+            found: BuiltinTypes::String,
+        }]
+    );
+    assert_eq!(
+        return_errors("LoadInt 1\nReturnValue", "LoadInt 1\nReturnValue"),
+        vec![TypeErrorKind::ReturnType {
+            function: "g".to_string(),
+            expected: None,
+            found: BuiltinTypes::Int,
+        }]
+    );
+}
+
+#[test]
+fn functions_with_a_return_type_can_not_end_without_a_value() {
+    let missing = vec![TypeErrorKind::MissingReturnValue {
+        function: "f".to_string(),
+        expected: BuiltinTypes::Int,
+    }];
+    assert_eq!(return_errors("Return", ""), missing);
+    // Falls off the end of the body when the condition is false
+    assert_eq!(return_errors("LoadBool true\nJumpIfFalse +2\nLoadInt 1\nReturnValue", ""), missing);
+    // The main block ends with a `Return` of its own
+    assert_eq!(main_errors("Return"), vec![]);
+}
@@ -0,0 +1,135 @@
+//! The verifier rejects bytecode that can fail when run and accepts what the assembler builds.
+
+use celsium::{
+    assembler::{ assemble, assemble_program },
+    block::TextSpan,
+    bytecode::OPTCODE,
+    module::FunctionSignature,
+    native::NativeRegistry,
+    verifier::{ verify, verify_with, VerifyError, VerifyErrorKind },
+};
+
+fn errors(source: &str) -> Vec<VerifyError> {
+    verify(&assemble(source).unwrap()).err().unwrap_or_default()
+}
+
+fn error(kind: VerifyErrorKind, index: usize) -> Vec<VerifyError> {
+    vec![VerifyError { kind, index }]
+}
+
+#[test]
+fn well_formed_bytecode_passes() {
+    let source = r#"
+        LoadInt 0
+        DefineVar i #1
+    loop:
+        LoadVar i #1
+        LoadInt 3
+        LessThan
+        JumpIfFalse end
+        LoadVar i #1
+        CallSpecialFunction izvade
+        LoadVar i #1
+        LoadInt 1
+        Add
+        AssignVar #1
+        JumpBack loop
+    end:
+    "#;
+    assert_eq!(errors(source), vec![]);
+}
+
+#[test]
+fn jumps_must_land_inside_the_block() {
+    assert_eq!(
+        errors("LoadInt 1\nJump +5"),
+        error(VerifyErrorKind::JumpOutOfBounds { target: 7, length: 2 }, 1)
+    );
+    assert_eq!(
+        errors("LoadInt 1\nJumpBack -3"),
+        error(VerifyErrorKind::JumpOutOfBounds { target: -1, length: 2 }, 1)
+    );
+    // Right after the last optcode is where blocks end
+    assert_eq!(errors("LoadBool true\nJumpIfFalse +0"), vec![]);
+}
+
+#[test]
+fn optcodes_need_their_operands() {
+    assert_eq!(
+        errors("LoadInt 1\nAdd"),
+        error(VerifyErrorKind::StackUnderflow { needed: 2, available: 1 }, 1)
+    );
+    // Only one of the paths pushes a value
+    let source = "LoadBool true\nJumpIfFalse skip\nLoadBool true\nskip:\nNot";
+    assert_eq!(errors(source), error(VerifyErrorKind::StackUnderflow { needed: 1, available: 0 }, 3));
+}
+
+#[test]
+fn variables_must_be_defined_on_every_path() {
+    assert_eq!(errors("LoadVar x #4"), error(VerifyErrorKind::UndefinedVariable { id: 4 }, 0));
+    let source = r#"
+        LoadBool true
+        JumpIfFalse skip
+        LoadInt 1
+        DefineVar x #4
+    skip:
+        LoadInt 2
+        AssignVar #4
+    "#;
+    assert_eq!(errors(source), error(VerifyErrorKind::UndefinedVariable { id: 4 }, 5));
+}
+
+#[test]
+fn unlinked_calls_and_loop_control_are_rejected() {
+    let span = TextSpan { line: 1, col_start: 1, length: 5 };
+    let kinds = |bytecode: &[OPTCODE]| -> Vec<VerifyErrorKind> {
+        verify(bytecode)
+            .unwrap_err()
+            .into_iter()
+            .map(|error| error.kind)
+            .collect()
+    };
+    // Nothing after a Break is reachable
+    let bytecode = [
+        OPTCODE::CallFunction { id: 3, name: "f".to_string() },
+        OPTCODE::Break { span: span.clone() },
+        OPTCODE::Continue { span: span.clone() },
+    ];
+    assert_eq!(
+        kinds(&bytecode),
+        vec![VerifyErrorKind::UnlinkedCall { name: "f".to_string() }, VerifyErrorKind::BreakOutsideLoop]
+    );
+    assert_eq!(kinds(&[OPTCODE::Continue { span }]), vec![VerifyErrorKind::ContinueOutsideLoop]);
+}
+
+#[test]
+fn linked_programs_are_verified_with_their_functions() {
+    let function = r#"
+    .function double(n: Int #10) -> Int
+        LoadVar n #10
+        LoadVar n #10
+        Add
+        ReturnValue
+    .end
+    "#;
+    let program = assemble_program(&format!("LoadInt 1\nCallFunction double\nNot\n{}", function));
+    assert_eq!(program.unwrap().verify(), Ok(()));
+    // The call takes the argument and leaves only its result
+    let program = assemble_program(&format!("LoadInt 1\nCallFunction double\nAdd\n{}", function)).unwrap();
+    let errors = program.verify().unwrap_err();
+    assert_eq!(errors[0].kind, VerifyErrorKind::StackUnderflow { needed: 2, available: 1 });
+}
+
+#[test]
+fn huge_jump_targets_are_out_of_bounds() {
+    let signature = FunctionSignature::new("f".to_string(), vec![], None);
+    let call = OPTCODE::JumpToFunction { target: usize::MAX, function: 0, function_name: "f".to_string() };
+    assert_eq!(
+        verify_with(&[call], &[signature], &NativeRegistry::new()),
+        Err(error(VerifyErrorKind::JumpOutOfBounds { target: i64::MAX, length: 1 }, 0))
+    );
+    for jump in [OPTCODE::Jump { steps: usize::MAX }, OPTCODE::JumpBack { steps: usize::MAX }] {
+        let errors = verify(&[jump]).unwrap_err();
+        assert!(matches!(errors[0].kind, VerifyErrorKind::JumpOutOfBounds { .. }), "{:?}", errors);
+    }
+}